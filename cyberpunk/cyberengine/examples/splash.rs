@@ -0,0 +1,102 @@
+//! Sprites popping in and out of existence. Run from the workspace root, it needs test.jpg:
+//!
+//! ```text
+//! cargo run -p cyberengine --example splash
+//! ```
+
+extern crate cyberengine;
+extern crate rand;
+extern crate specs;
+
+use std::time::Duration;
+
+use cyberengine::game::Game;
+use cyberengine::resource;
+use cyberengine::state::GameState;
+use cyberengine::systems::DeltaTime;
+use cyberengine::systems::sprite::{SpriteRenderer, Position, Sprite, SpriteSpawn, SpriteLoader, PendingSprite};
+use cyberengine::ui::Inspector;
+
+struct SpriteSpawner {}
+
+impl<'r> specs::System<'r> for SpriteSpawner {
+    type SystemData = (specs::ReadStorage<'r, Sprite>,
+        specs::WriteStorage<'r, SpriteSpawn>,
+        specs::WriteStorage<'r, SpriteDuration>,
+        specs::WriteStorage<'r, Position>,
+        specs::Entities<'r>);
+
+    fn run(&mut self, (sprites, mut spawns, mut durs, mut pos, entities): Self::SystemData) {
+        use specs::Join;
+
+        let c = (&sprites).join().count();
+        if c < 100 {
+            let e = entities.create();
+            pos.insert(e, Position { x: rand::random::<f32>() * 1000. - 500., y: rand::random::<f32>() * 1000. - 500.});
+            spawns.insert(e, SpriteSpawn { texture_identifier: resource::Identifier::Image("test.jpg".to_owned()) });
+            durs.insert(e, SpriteDuration(Duration::new(0,0)));
+        }
+    }
+}
+
+struct SpriteDespawner {}
+struct SpriteDuration(Duration);
+
+impl specs::Component for SpriteDuration {
+    type Storage = specs::VecStorage<Self>;
+}
+
+
+impl<'r> specs::System<'r> for SpriteDespawner {
+    type SystemData = (specs::WriteStorage<'r, SpriteDuration>,
+        specs::Entities<'r>,
+        specs::Fetch<'r, DeltaTime>);
+
+    fn run(&mut self, (mut sprites, entities, dt): Self::SystemData) {
+        use specs::Join;
+        let timeout = Duration::from_millis(500);
+
+        for (sprite, entity) in (&mut sprites, &*entities).join() {
+            let &mut SpriteDuration(ref mut dur) = sprite;
+            *dur = *dur + dt.0;
+
+            if *dur > timeout {
+                entities.delete(entity).unwrap();
+            }
+
+
+        }
+    }
+}
+
+fn splash_state() -> GameState{
+    GameState::new("splash",
+        |world| {
+            world.register::<Position>();
+            world.register::<Sprite>();
+            world.register::<SpriteSpawn>();
+            world.register::<PendingSprite>();
+            world.register::<SpriteDuration>();
+
+            let dispatcher: specs::Dispatcher = specs::DispatcherBuilder::new()
+                .add(SpriteSpawner {}, "sprite_spawner", &[])
+                .add(SpriteDespawner {}, "sprite_despawner", &[])
+                .build();
+
+            dispatcher
+        },
+        vec![
+            Box::new(SpriteLoader::new()),
+            Box::new(SpriteRenderer::new())
+        ])
+        .with_inspector(
+            Inspector::new()
+                .with_default::<Position>("Position")
+                .with::<Sprite>("Sprite")
+                .with::<SpriteSpawn>("SpriteSpawn"),
+        )
+}
+
+fn main() -> () {
+    Game::builder().with_state(splash_state()).build().play();
+}
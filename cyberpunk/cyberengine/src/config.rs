@@ -1,12 +1,62 @@
 //! Config loading stuff
-//! TODO: Figure out what I am trying to do here.
+//!
+//! The config is built from layers, each one overriding the previous one field by field:
+//!
+//! 1. defaults embedded into the binary
+//! 2. optional `game_config.toml` files on disk (next to the game, per-user settings...)
+//! 3. environment variables, like `CYBERENGINE_GRAPHICS_VSYNC=true`
+//!    (variables with the prefix that don't match any key are ignored)
+//!
+//! The embedded defaults also work as the schema: every other layer may only set keys
+//! that exist in the defaults, with the same type, and has to leave the config valid.
+//! `Loader::load_skipping_broken` skips the layers that don't, instead of failing.
+use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
 use toml;
+use toml::Value;
+use toml::value::Table;
+
+use error::Error;
 
 /// General game settings, should not be edited by player
 /// TODO: Maybe use this at compile time? Or something?
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GameConfig {
     pub graphics: GraphicsSettings,
+    pub timing: TimingSettings,
+    pub development: DevelopmentSettings,
+}
+
+impl GameConfig {
+    /// Checks that the values make sense. Returns the offending key and a reason if not.
+    fn validate(&self) -> Result<(), (&'static str, String)> {
+        if self.graphics.window_width == 0 {
+            return Err(("graphics.window_width", "must be greater than 0".to_owned()));
+        }
+        if self.graphics.window_height == 0 {
+            return Err(("graphics.window_height", "must be greater than 0".to_owned()));
+        }
+        if !self.graphics.multisampling.is_power_of_two() && self.graphics.multisampling != 0 {
+            return Err((
+                "graphics.multisampling",
+                format!("must be 0 or a power of two, got {}", self.graphics.multisampling),
+            ));
+        }
+        if self.timing.tick_rate == 0 {
+            return Err(("timing.tick_rate", "must be greater than 0".to_owned()));
+        }
+        if self.timing.max_ticks_per_frame == 0 {
+            return Err(("timing.max_ticks_per_frame", "must be greater than 0".to_owned()));
+        }
+        Ok(())
+    }
 }
 
 /// Graphics settings
@@ -18,3 +68,637 @@ pub struct GraphicsSettings {
     pub multisampling: u16,
     pub title: String,
 }
+
+/// Simulation timing settings
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimingSettings {
+    /// How many simulation ticks are run per second
+    pub tick_rate: u32,
+    /// How many ticks we are allowed to catch up in one frame, before giving up
+    /// and dropping the rest (so we don't spiral to death on slow machines)
+    pub max_ticks_per_frame: u32,
+}
+
+/// Settings for working on the game, not for players
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DevelopmentSettings {
+    /// Reload textures, shaders, maps and this config when they change on disk
+    pub hot_reload: bool,
+    /// How often to check for changes, in milliseconds
+    pub poll_interval_ms: u64,
+}
+
+/// Things that can go wrong when loading the config.
+/// `source` is the file (or environment variable) the problem came from.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { source: String, error: io::Error },
+    Parse { source: String, error: toml::de::Error },
+    UnknownKey { source: String, key: String },
+    InvalidType {
+        source: String,
+        key: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    InvalidValue {
+        source: String,
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io { ref source, ref error } => {
+                write!(f, "{}: could not read file: {}", source, error)
+            }
+            ConfigError::Parse { ref source, ref error } => write!(f, "{}: {}", source, error),
+            ConfigError::UnknownKey { ref source, ref key } => {
+                write!(f, "{}: unknown key `{}`", source, key)
+            }
+            ConfigError::InvalidType {
+                ref source,
+                ref key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: key `{}` should be {}, found {}",
+                source,
+                key,
+                expected,
+                found
+            ),
+            ConfigError::InvalidValue {
+                ref source,
+                ref key,
+                ref message,
+            } => write!(f, "{}: key `{}` {}", source, key, message),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Io { .. } => "could not read config file",
+            ConfigError::Parse { .. } => "could not parse config file",
+            ConfigError::UnknownKey { .. } => "unknown config key",
+            ConfigError::InvalidType { .. } => "config value has wrong type",
+            ConfigError::InvalidValue { .. } => "invalid config value",
+        }
+    }
+}
+
+const DEFAULTS_SOURCE: &'static str = "<embedded defaults>";
+
+/// Builds a GameConfig from layers.
+pub struct Loader {
+    defaults: &'static str,
+    files: Vec<PathBuf>,
+    env_prefix: Option<String>,
+}
+
+impl Loader {
+    /// `defaults` is the embedded config, that has every key set.
+    pub fn new(defaults: &'static str) -> Loader {
+        Loader {
+            defaults: defaults,
+            files: Vec::new(),
+            env_prefix: None,
+        }
+    }
+
+    /// Adds an optional config file on top of the previous layers. Missing files are skipped.
+    pub fn with_file<P: AsRef<Path>>(mut self, path: P) -> Loader {
+        self.files.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Allows overriding keys with `<PREFIX>_<SECTION>_<KEY>` environment variables.
+    pub fn with_env_prefix(mut self, prefix: &str) -> Loader {
+        self.env_prefix = Some(prefix.to_uppercase());
+        self
+    }
+
+    /// Builds the config, failing on the first broken layer
+    pub fn load(self) -> Result<GameConfig, Error> {
+        let (config, _) = self.build(false)?;
+        Ok(config)
+    }
+
+    /// Builds the config from the layers that work. Broken files and environment
+    /// variables are skipped, and their errors returned with the config, so one typo
+    /// doesn't throw the other layers away. Only fails if the embedded defaults are broken.
+    pub fn load_skipping_broken(self) -> Result<(GameConfig, Vec<ConfigError>), Error> {
+        self.build(true)
+    }
+
+    fn build(self, skip_broken: bool) -> Result<(GameConfig, Vec<ConfigError>), Error> {
+        let mut origins: HashMap<String, String> = HashMap::new();
+        let mut skipped: Vec<ConfigError> = Vec::new();
+
+        let mut config = match parse(self.defaults, DEFAULTS_SOURCE)? {
+            Value::Table(table) => table,
+            _ => unreachable!(),
+        };
+        record_origins(&config, "", DEFAULTS_SOURCE, &mut origins);
+        to_config(&config, &origins)?;
+
+        for path in &self.files {
+            let source = path.display().to_string();
+            let contents = match read_file(path) {
+                Ok(Some(contents)) => contents,
+                Ok(None) => continue,
+                Err(error) => {
+                    skip(ConfigError::Io { source: source, error: error }, skip_broken, &mut skipped)?;
+                    continue;
+                }
+            };
+            println!("Loading config from {}", source);
+            let layer = match parse(&contents, &source) {
+                Ok(Value::Table(layer)) => layer,
+                Ok(_) => unreachable!(),
+                Err(error) => {
+                    skip(error, skip_broken, &mut skipped)?;
+                    continue;
+                }
+            };
+            if let Err(error) = apply(&mut config, layer, &source, &mut origins) {
+                skip(error, skip_broken, &mut skipped)?;
+            }
+        }
+
+        if let Some(ref prefix) = self.env_prefix {
+            let mut vars: Vec<(String, String)> = env::vars()
+                .filter(|&(ref name, _)| name.starts_with(&format!("{}_", prefix)))
+                .collect();
+            vars.sort();
+
+            for (name, raw) in vars {
+                let layer = env_layer(&name[prefix.len() + 1..], &raw, &config);
+                match apply(&mut config, layer, &format!("${}", name), &mut origins) {
+                    Ok(()) => {}
+                    // Could be meant for something else entirely, unlike a typo in a file
+                    Err(ConfigError::UnknownKey { .. }) => {
+                        println!("Ignoring ${}, there is no such config key", name)
+                    }
+                    Err(error) => skip(error, skip_broken, &mut skipped)?,
+                }
+            }
+        }
+
+        Ok((to_config(&config, &origins)?, skipped))
+    }
+}
+
+/// Keeps the error for later if broken layers are skipped, returns it otherwise
+fn skip(error: ConfigError, skip_broken: bool, skipped: &mut Vec<ConfigError>) -> Result<(), ConfigError> {
+    if skip_broken {
+        skipped.push(error);
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// Merges `layer` on top of `config`. Nothing is changed if the layer is broken, or
+/// makes the config invalid, so a broken layer can be skipped as a whole.
+fn apply(
+    config: &mut Table,
+    layer: Table,
+    source: &str,
+    origins: &mut HashMap<String, String>,
+) -> Result<(), ConfigError> {
+    let mut merged = config.clone();
+    let mut merged_origins = origins.clone();
+    merge(&mut merged, layer, "", source, &mut merged_origins)?;
+    to_config(&merged, &merged_origins)?;
+    *config = merged;
+    *origins = merged_origins;
+    Ok(())
+}
+
+/// Deserializes and validates the merged layers. Errors name the layer the bad value came from.
+fn to_config(config: &Table, origins: &HashMap<String, String>) -> Result<GameConfig, ConfigError> {
+    let config: GameConfig = match Value::Table(config.clone()).try_into() {
+        Ok(config) => config,
+        Err(error) => {
+            return Err(ConfigError::Parse {
+                source: DEFAULTS_SOURCE.to_owned(),
+                error: error,
+            })
+        }
+    };
+
+    match config.validate() {
+        Ok(()) => Ok(config),
+        Err((key, message)) => Err(ConfigError::InvalidValue {
+            source: origins.get(key).cloned().unwrap_or(DEFAULTS_SOURCE.to_owned()),
+            key: key.to_owned(),
+            message: message,
+        }),
+    }
+}
+
+/// Where the per-user settings file lives.
+/// `%APPDATA%\<game>\settings.toml` on Windows, `$XDG_CONFIG_HOME/<game>/settings.toml` elsewhere.
+pub fn user_config_path() -> Option<PathBuf> {
+    let game = match env::current_exe() {
+        Ok(exe) => match exe.file_stem() {
+            Some(stem) => stem.to_owned(),
+            None => return None,
+        },
+        Err(_) => return None,
+    };
+
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None => env::var_os("HOME").map(|home| Path::new(&home).join(".config")),
+        }
+    };
+
+    base.map(|dir| dir.join(game).join("settings.toml"))
+}
+
+fn read_file(path: &Path) -> io::Result<Option<String>> {
+    let mut f = match File::open(path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+    Ok(Some(contents))
+}
+
+fn parse(contents: &str, source: &str) -> Result<Value, ConfigError> {
+    contents.parse::<Value>().map_err(|error| {
+        ConfigError::Parse {
+            source: source.to_owned(),
+            error: error,
+        }
+    })
+}
+
+fn key_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn record_origins(table: &Table, prefix: &str, source: &str, origins: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let path = key_path(prefix, key);
+        match *value {
+            Value::Table(ref inner) => record_origins(inner, &path, source, origins),
+            _ => {
+                origins.insert(path, source.to_owned());
+            }
+        }
+    }
+}
+
+/// Merges `layer` on top of `base`, one field at a time.
+fn merge(
+    base: &mut Table,
+    layer: Table,
+    prefix: &str,
+    source: &str,
+    origins: &mut HashMap<String, String>,
+) -> Result<(), ConfigError> {
+    for (key, value) in layer {
+        let path = key_path(prefix, &key);
+        let existing = match base.get_mut(&key) {
+            Some(existing) => existing,
+            None => {
+                return Err(ConfigError::UnknownKey {
+                    source: source.to_owned(),
+                    key: path,
+                })
+            }
+        };
+
+        match (existing, value) {
+            (&mut Value::Table(ref mut inner), Value::Table(layer)) => {
+                merge(inner, layer, &path, source, origins)?
+            }
+            // Let people write `1` instead of `1.0`
+            (&mut Value::Float(ref mut existing), Value::Integer(value)) => {
+                *existing = value as f64;
+                origins.insert(path, source.to_owned());
+            }
+            (existing, value) => {
+                if !existing.same_type(&value) {
+                    return Err(ConfigError::InvalidType {
+                        source: source.to_owned(),
+                        key: path,
+                        expected: existing.type_str(),
+                        found: value.type_str(),
+                    });
+                }
+                *existing = value;
+                origins.insert(path, source.to_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Turns `GRAPHICS_WINDOW_WIDTH=1920` into `{ graphics = { window_width = 1920 } }`.
+/// Values are parsed as TOML, and used as plain strings if that fails (or if the
+/// existing value is a string anyway).
+fn env_layer(name: &str, raw: &str, config: &Table) -> Table {
+    let name = name.to_lowercase();
+    let (section, key) = match name.find('_') {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => ("", &name[..]),
+    };
+
+    let wants_string = match config.get(section) {
+        Some(&Value::Table(ref table)) => match table.get(key) {
+            Some(&Value::String(_)) => true,
+            _ => false,
+        },
+        _ => false,
+    };
+
+    let value = if wants_string {
+        Value::String(raw.to_owned())
+    } else {
+        match format!("value = {}", raw).parse::<Value>() {
+            Ok(Value::Table(mut parsed)) => parsed.remove("value").unwrap(),
+            _ => Value::String(raw.to_owned()),
+        }
+    };
+
+    let mut layer = Table::new();
+    if section.is_empty() {
+        layer.insert(key.to_owned(), value);
+    } else {
+        let mut inner = Table::new();
+        inner.insert(key.to_owned(), value);
+        layer.insert(section.to_owned(), Value::Table(inner));
+    }
+    layer
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::prelude::*;
+    use std::path::PathBuf;
+
+    use toml::Value;
+    use toml::value::Table;
+
+    use error::Error;
+    use super::{env_layer, ConfigError, GameConfig, Loader};
+
+    const DEFAULTS: &'static str = r#"
+        [graphics]
+        window_width = 1280
+        window_height = 720
+        vsync = true
+        multisampling = 4
+        title = "Test"
+
+        [timing]
+        tick_rate = 60
+        max_ticks_per_frame = 5
+
+        [development]
+        hot_reload = false
+        poll_interval_ms = 500
+    "#;
+
+    /// Writes a config file into the temp directory. Names have to be unique, tests run in parallel.
+    fn file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("cyberengine_config_{}.toml", name));
+        File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    fn source_of(path: &PathBuf) -> String {
+        path.display().to_string()
+    }
+
+    fn section_value<'a>(layer: &'a Table, section: &str, key: &str) -> Option<&'a Value> {
+        match layer.get(section) {
+            Some(&Value::Table(ref table)) => table.get(key),
+            _ => None,
+        }
+    }
+
+    fn config_error(result: Result<GameConfig, Error>) -> ConfigError {
+        match result {
+            Err(Error::Config(error)) => error,
+            other => panic!("Expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn layers_override_the_previous_ones_field_by_field() {
+        let game = file("merge_game", "[graphics]\nwindow_width = 800\n[timing]\ntick_rate = 30");
+        let user = file("merge_user", "[graphics]\nwindow_width = 1024\nvsync = false");
+        env::set_var("MERGETEST_TIMING_TICK_RATE", "120");
+        env::set_var("MERGETEST_GRAPHICS_TITLE", "Hello there");
+
+        let config = Loader::new(DEFAULTS)
+            .with_file(&game)
+            .with_file(env::temp_dir().join("cyberengine_config_missing.toml"))
+            .with_file(&user)
+            .with_env_prefix("mergetest")
+            .load()
+            .unwrap();
+
+        assert_eq!(config.graphics.window_width, 1024);
+        assert_eq!(config.graphics.window_height, 720);
+        assert_eq!(config.graphics.vsync, false);
+        assert_eq!(config.graphics.multisampling, 4);
+        assert_eq!(config.graphics.title, "Hello there");
+        assert_eq!(config.timing.tick_rate, 120);
+        assert_eq!(config.timing.max_ticks_per_frame, 5);
+        assert_eq!(config.development.poll_interval_ms, 500);
+    }
+
+    #[test]
+    fn env_names_are_split_at_the_first_underscore() {
+        let defaults = match DEFAULTS.parse::<Value>().unwrap() {
+            Value::Table(table) => table,
+            _ => unreachable!(),
+        };
+
+        let layer = env_layer("DEVELOPMENT_POLL_INTERVAL_MS", "250", &defaults);
+        assert_eq!(
+            section_value(&layer, "development", "poll_interval_ms"),
+            Some(&Value::Integer(250))
+        );
+
+        // Strings stay strings, even if they look like something else
+        let layer = env_layer("GRAPHICS_TITLE", "true", &defaults);
+        assert_eq!(
+            section_value(&layer, "graphics", "title"),
+            Some(&Value::String("true".to_owned()))
+        );
+        // Anything that isn't TOML is a string too
+        let layer = env_layer("GRAPHICS_VSYNC", "nope nope", &defaults);
+        assert_eq!(
+            section_value(&layer, "graphics", "vsync"),
+            Some(&Value::String("nope nope".to_owned()))
+        );
+
+        let layer = env_layer("VSYNC", "true", &defaults);
+        assert_eq!(layer.get("vsync"), Some(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn unknown_env_keys_are_ignored() {
+        env::set_var("UNKNOWNTEST_GRAPHICS_WINDOW_WIDTH", "640");
+        env::set_var("UNKNOWNTEST_GRAPHICS_NOPE", "1");
+        env::set_var("UNKNOWNTEST_NOPE", "1");
+
+        let config = Loader::new(DEFAULTS).with_env_prefix("UNKNOWNTEST").load().unwrap();
+        assert_eq!(config.graphics.window_width, 640);
+    }
+
+    #[test]
+    fn parse_errors_name_the_file() {
+        let path = file("parse", "[graphics\nwindow_width = 800");
+
+        match config_error(Loader::new(DEFAULTS).with_file(&path).load()) {
+            ConfigError::Parse { source, .. } => assert_eq!(source, source_of(&path)),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_keys_in_files_name_the_file_and_key() {
+        let path = file("unknown", "[graphics]\nwindow_widht = 800");
+
+        match config_error(Loader::new(DEFAULTS).with_file(&path).load()) {
+            ConfigError::UnknownKey { source, key } => {
+                assert_eq!(source, source_of(&path));
+                assert_eq!(key, "graphics.window_widht");
+            }
+            other => panic!("Expected an unknown key, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wrong_types_name_the_file_and_key() {
+        let path = file("types", "[graphics]\nvsync = \"yes\"");
+
+        match config_error(Loader::new(DEFAULTS).with_file(&path).load()) {
+            ConfigError::InvalidType {
+                source,
+                key,
+                expected,
+                found,
+            } => {
+                assert_eq!(source, source_of(&path));
+                assert_eq!(key, "graphics.vsync");
+                assert_eq!(expected, "boolean");
+                assert_eq!(found, "string");
+            }
+            other => panic!("Expected an invalid type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_values_name_the_layer_they_came_from() {
+        let path = file("values", "[timing]\ntick_rate = 0");
+        match config_error(Loader::new(DEFAULTS).with_file(&path).load()) {
+            ConfigError::InvalidValue { source, key, .. } => {
+                assert_eq!(source, source_of(&path));
+                assert_eq!(key, "timing.tick_rate");
+            }
+            other => panic!("Expected an invalid value, got {:?}", other),
+        }
+
+        env::set_var("VALUETEST_GRAPHICS_WINDOW_HEIGHT", "0");
+        match config_error(Loader::new(DEFAULTS).with_env_prefix("VALUETEST").load()) {
+            ConfigError::InvalidValue { source, key, .. } => {
+                assert_eq!(source, "$VALUETEST_GRAPHICS_WINDOW_HEIGHT");
+                assert_eq!(key, "graphics.window_height");
+            }
+            other => panic!("Expected an invalid value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_zero_sizes_and_rates() {
+        let valid = || Loader::new(DEFAULTS).load().unwrap();
+        assert!(valid().validate().is_ok());
+
+        let mut config = valid();
+        config.graphics.window_width = 0;
+        assert_eq!(config.validate().unwrap_err().0, "graphics.window_width");
+
+        let mut config = valid();
+        config.graphics.window_height = 0;
+        assert_eq!(config.validate().unwrap_err().0, "graphics.window_height");
+
+        let mut config = valid();
+        config.graphics.multisampling = 3;
+        assert_eq!(config.validate().unwrap_err().0, "graphics.multisampling");
+
+        let mut config = valid();
+        config.timing.tick_rate = 0;
+        assert_eq!(config.validate().unwrap_err().0, "timing.tick_rate");
+
+        let mut config = valid();
+        config.timing.max_ticks_per_frame = 0;
+        assert_eq!(config.validate().unwrap_err().0, "timing.max_ticks_per_frame");
+    }
+
+    #[test]
+    fn broken_layers_can_be_skipped_one_by_one() {
+        let broken = file("skip_broken", "[graphics\nwindow_width = 640");
+        let good = file("skip_good", "[graphics]\nwindow_width = 800");
+        // Valid until the last key, none of it is kept
+        let invalid = file("skip_invalid", "[graphics]\nwindow_height = 600\n[timing]\ntick_rate = 0");
+        env::set_var("SKIPTEST_GRAPHICS_VSYNC", "3");
+        env::set_var("SKIPTEST_TIMING_MAX_TICKS_PER_FRAME", "10");
+
+        let (config, skipped) = Loader::new(DEFAULTS)
+            .with_file(&broken)
+            .with_file(&good)
+            .with_file(&invalid)
+            .with_env_prefix("SKIPTEST")
+            .load_skipping_broken()
+            .unwrap();
+
+        assert_eq!(config.graphics.window_width, 800);
+        assert_eq!(config.graphics.window_height, 720);
+        assert_eq!(config.graphics.vsync, true);
+        assert_eq!(config.timing.tick_rate, 60);
+        assert_eq!(config.timing.max_ticks_per_frame, 10);
+
+        let sources: Vec<String> = skipped
+            .iter()
+            .map(|error| match *error {
+                ConfigError::Parse { ref source, .. } |
+                ConfigError::InvalidValue { ref source, .. } |
+                ConfigError::InvalidType { ref source, .. } => source.clone(),
+                ref other => panic!("Unexpected error {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                source_of(&broken),
+                source_of(&invalid),
+                "$SKIPTEST_GRAPHICS_VSYNC".to_owned(),
+            ]
+        );
+    }
+}
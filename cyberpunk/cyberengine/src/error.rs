@@ -0,0 +1,102 @@
+//! Errors
+//!
+//! Everything that loads stuff from disk or creates things on the GPU returns
+//! `cyberengine::Error`, so callers can decide whether a missing file is worth crashing over.
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use image;
+
+use config::ConfigError;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be opened or read
+    Io { path: String, error: io::Error },
+    /// An image file could not be decoded
+    Image { path: String, error: image::ImageError },
+    /// Creating a texture, buffer or such on the GPU failed
+    Gpu(String),
+    /// A builder wasn't given what it needs
+    Builder(String),
+    /// Config or input bindings are broken
+    Config(ConfigError),
+    /// A map or tileset file is broken. `path` is the file, or the name of the tileset.
+    Tilemap { path: String, message: String },
+    /// No loader for the file, or the loader gave something else than was asked for
+    Asset { path: String, message: String },
+    /// A scene file is broken, or from a version that can't be migrated
+    Scene { path: String, message: String },
+}
+
+impl Error {
+    pub fn tilemap<S: Into<String>>(path: &str, message: S) -> Error {
+        Error::Tilemap {
+            path: path.to_owned(),
+            message: message.into(),
+        }
+    }
+
+    pub fn asset<S: Into<String>>(path: &str, message: S) -> Error {
+        Error::Asset {
+            path: path.to_owned(),
+            message: message.into(),
+        }
+    }
+
+    pub fn scene<S: Into<String>>(path: &str, message: S) -> Error {
+        Error::Scene {
+            path: path.to_owned(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io { ref path, ref error } => write!(f, "{}: {}", path, error),
+            Error::Image { ref path, ref error } => write!(f, "{}: {}", path, error),
+            Error::Gpu(ref message) => write!(f, "GPU error: {}", message),
+            Error::Builder(ref message) => write!(f, "{}", message),
+            Error::Config(ref error) => write!(f, "{}", error),
+            Error::Tilemap { ref path, ref message } => write!(f, "{}: {}", path, message),
+            Error::Asset { ref path, ref message } => write!(f, "{}: {}", path, message),
+            Error::Scene { ref path, ref message } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io { .. } => "could not read file",
+            Error::Image { .. } => "could not decode image",
+            Error::Gpu(_) => "could not create GPU resource",
+            Error::Builder(_) => "builder is missing something",
+            Error::Config(_) => "invalid config",
+            Error::Tilemap { .. } => "invalid tilemap",
+            Error::Asset { .. } => "could not load asset",
+            Error::Scene { .. } => "invalid scene",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io { ref error, .. } => Some(error),
+            Error::Image { ref error, .. } => Some(error),
+            Error::Config(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(error: ConfigError) -> Error {
+        Error::Config(error)
+    }
+}
@@ -1,59 +1,352 @@
 //! Game Entry Point
-use std::time::Instant;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use state::GameState;
+use state;
 use window;
 use config;
-use toml;
+use input;
+use resource::AssetRegistry;
+use resource::assets::AssetServer;
+use resource::watch::FileWatcher;
+
+const DEFAULT_CONFIG: &'static str = include_str!("../../src/game_config.toml");
 
 /// The default, single-window Game.
 ///
 /// I was planning to make this a Trait, so users could implement their own Game objects,
-/// but I think I like this approach more.
+/// but I think I like this approach more. The game brings its own states:
+///
+/// ```ignore
+/// Game::builder()
+///     .with_state(title_state())
+///     .with_state(level_state())
+///     .initial_state("title")
+///     .build()
+///     .play();
+/// ```
 pub struct Game {
     config: config::GameConfig,
     window: window::Window,
 }
 
-impl Game {
-    pub fn new() -> Game {
-        let config = Self::load_config();
-        let window = window::Builder::new()
+/// Builds Games
+pub struct Builder {
+    states: Vec<state::GameState>,
+    initial_state: Option<&'static str>,
+    assets: Option<AssetRegistry>,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder {
+            states: Vec::new(),
+            initial_state: None,
+            assets: None,
+        }
+    }
+
+    pub fn with_state(mut self, state: state::GameState) -> Builder {
+        self.states.push(state);
+        self
+    }
+
+    /// The state on the stack when the game starts. Defaults to the first one added.
+    pub fn initial_state(mut self, name: &'static str) -> Builder {
+        self.initial_state = Some(name);
+        self
+    }
+
+    /// Where every state loads its assets from, like the base assets with mods mounted
+    /// over them. Defaults to `AssetRegistry::with_defaults`.
+    pub fn with_assets(mut self, registry: AssetRegistry) -> Builder {
+        self.assets = Some(registry);
+        self
+    }
+
+    /// Loads the config and opens the window
+    pub fn build(self) -> Game {
+        let config = Game::load_config();
+        let bindings = Game::load_bindings();
+        let registry = match self.assets {
+            Some(registry) => registry,
+            None => AssetRegistry::with_defaults(),
+        };
+        let mut builder = window::Builder::new()
             .with_title(config.graphics.title.clone())
             .with_dimensions(config.graphics.window_width, config.graphics.window_height)
             .with_vsync(config.graphics.vsync)
             .with_multisampling(config.graphics.multisampling)
-            .build();
+            .with_input_bindings(bindings)
+            .with_assets(AssetServer::with_registry(registry));
+        if config.development.hot_reload {
+            builder = builder.with_hot_reload(Duration::from_millis(config.development.poll_interval_ms));
+        }
+        if self.states.is_empty() {
+            println!("No states, there won't be much to see");
+        }
+        for state in self.states {
+            builder = builder.with_state(state);
+        }
+        if let Some(name) = self.initial_state {
+            builder = builder.with_initial_state(name);
+        }
+        let window = builder.build();
         Game {
             config: config,
             window: window,
         }
     }
+}
+
+impl Game {
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
 
+    /// Loads the config, skipping the files and variables that are broken
     fn load_config() -> config::GameConfig {
-        // TODO: I have no idea what I am doing
-        toml::from_str(include_str!("../../src/game_config.toml")).unwrap()
+        let (config, skipped) = Self::config_loader()
+            .load_skipping_broken()
+            .expect("Embedded default config is broken");
+        for e in skipped {
+            println!("Skipped broken config: {}", e);
+        }
+        config
+    }
+
+    /// The embedded defaults, `game_config.toml` next to the game, the per-user settings
+    /// file and `CYBERENGINE_*` environment variables, in that order.
+    fn config_loader() -> config::Loader {
+        let mut loader = config::Loader::new(DEFAULT_CONFIG);
+        for path in Self::config_files() {
+            loader = loader.with_file(path);
+        }
+        loader.with_env_prefix("CYBERENGINE")
+    }
+
+    /// The config files on disk, in the order they are applied
+    fn config_files() -> Vec<PathBuf> {
+        let mut files = vec![PathBuf::from("game_config.toml")];
+        if let Some(path) = config::user_config_path() {
+            files.push(path);
+        }
+        files
+    }
+
+    /// Loads the input bindings from the embedded defaults and `input.toml` next to the game.
+    fn load_bindings() -> input::Bindings {
+        let defaults = include_str!("../../src/input.toml");
+
+        match input::Bindings::load(defaults, "input.toml") {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                println!("Failed to load input bindings, using defaults: {}", e);
+                input::Bindings::parse(defaults, "<embedded bindings>")
+                    .expect("Embedded default bindings are broken")
+            }
+        }
     }
 
     pub fn play(mut self) -> () {
         // IDK poll the window or something
 
-        let mut last_frame_update = Instant::now();
+        let mut timestep = FixedTimestep::new(
+            self.config.timing.tick_rate,
+            self.config.timing.max_ticks_per_frame,
+        );
 
         let mut window = self.window;
+        let mut config = self.config;
+
+        let mut config_watcher = if config.development.hot_reload {
+            let mut watcher = FileWatcher::new(Duration::from_millis(config.development.poll_interval_ms));
+            for path in Self::config_files() {
+                watcher.watch(path);
+            }
+            Some(watcher)
+        } else {
+            None
+        };
 
         loop {
+            if let Some(ref mut watcher) = config_watcher {
+                if !watcher.poll().is_empty() {
+                    // Unlike at startup, a broken file keeps the config we have
+                    match Self::config_loader().load() {
+                        Ok(new_config) => {
+                            apply_config(&config, &new_config, &mut timestep, &mut window);
+                            config = new_config;
+                        }
+                        Err(e) => println!("Failed to reload config: {}", e),
+                    }
+                }
+            }
 
             if !window.poll_events() {
                 break;
             }
 
-            let delta = last_frame_update.elapsed();
-            window.update(delta);
+            for _ in 0..timestep.advance() {
+                window.update(timestep.tick);
+            }
 
-            last_frame_update = Instant::now();
+            window = window.render(timestep.alpha());
+        }
+    }
+}
+
+/// Applies the settings that can change while the game runs
+fn apply_config(
+    old: &config::GameConfig,
+    new: &config::GameConfig,
+    timestep: &mut FixedTimestep,
+    window: &mut window::Window,
+) -> () {
+    println!("Config reloaded");
+    if old.timing.tick_rate != new.timing.tick_rate
+        || old.timing.max_ticks_per_frame != new.timing.max_ticks_per_frame
+    {
+        *timestep = FixedTimestep::new(new.timing.tick_rate, new.timing.max_ticks_per_frame);
+    }
+    if old.graphics.title != new.graphics.title {
+        window.set_title(&new.graphics.title);
+    }
+    // Only when the config changes, so a window the player resized stays that way
+    if old.graphics.window_width != new.graphics.window_width
+        || old.graphics.window_height != new.graphics.window_height
+    {
+        window.set_dimensions(new.graphics.window_width, new.graphics.window_height);
+    }
+    if old.graphics.vsync != new.graphics.vsync || old.graphics.multisampling != new.graphics.multisampling {
+        println!("Changing vsync or multisampling needs a restart");
+    }
+}
+
+/// Fixed timestep accumulator.
+///
+/// Real time is accumulated every frame, and consumed in fixed size ticks, so the simulation
+/// behaves the same no matter how fast the machine is. Whatever is left over is exposed as
+/// the interpolation alpha for rendering.
+struct FixedTimestep {
+    tick: Duration,
+    max_ticks: u32,
+    accumulator: Duration,
+    last_update: Instant,
+    /// Whether the last advance had to drop time, so we only complain when it starts
+    behind: bool,
+}
 
-            window = window.render();
+impl FixedTimestep {
+    fn new(tick_rate: u32, max_ticks: u32) -> FixedTimestep {
+        FixedTimestep {
+            tick: Duration::new(1, 0) / tick_rate,
+            max_ticks: max_ticks,
+            accumulator: Duration::new(0, 0),
+            last_update: Instant::now(),
+            behind: false,
         }
     }
+
+    /// Accumulates the time passed since last call, and returns how many ticks should be run.
+    fn advance(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update);
+        self.last_update = now;
+        self.advance_by(elapsed)
+    }
+
+    /// Same as `advance`, but with the time given instead of measured
+    fn advance_by(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.tick && ticks < self.max_ticks {
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+
+        if self.accumulator >= self.tick {
+            // We are too far behind to ever catch up, just drop the backlog
+            if !self.behind {
+                println!("Simulation running behind, skipping {:?}", self.accumulator);
+            }
+            self.behind = true;
+            self.accumulator = Duration::new(0, 0);
+        } else if self.behind {
+            println!("Simulation caught up");
+            self.behind = false;
+        }
+
+        ticks
+    }
+
+    /// How far we are between the last tick and the next one, from 0.0 to 1.0
+    fn alpha(&self) -> f32 {
+        as_secs(self.accumulator) / as_secs(self.tick)
+    }
+}
+
+fn as_secs(d: Duration) -> f32 {
+    d.as_secs() as f32 + d.subsec_nanos() as f32 / 1_000_000_000.
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::FixedTimestep;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn counts_whole_ticks() {
+        // 100 Hz, so 10 ms ticks
+        let mut timestep = FixedTimestep::new(100, 5);
+        assert_eq!(timestep.advance_by(ms(5)), 0);
+        assert_eq!(timestep.advance_by(ms(5)), 1);
+        assert_eq!(timestep.advance_by(ms(25)), 2);
+        // The 5 ms left over from before counts towards the next tick
+        assert_eq!(timestep.advance_by(ms(5)), 1);
+        assert_eq!(timestep.accumulator, ms(0));
+    }
+
+    #[test]
+    fn ticks_are_capped() {
+        let mut timestep = FixedTimestep::new(100, 5);
+        assert_eq!(timestep.advance_by(ms(1000)), 5);
+        // Whatever didn't fit is dropped instead of being caught up on later
+        assert_eq!(timestep.accumulator, ms(0));
+        assert!(timestep.behind);
+        assert_eq!(timestep.advance_by(ms(200)), 5);
+        assert!(timestep.behind);
+
+        assert_eq!(timestep.advance_by(ms(15)), 1);
+        assert_eq!(timestep.accumulator, ms(5));
+        assert!(!timestep.behind);
+    }
+
+    #[test]
+    fn exactly_at_the_cap_keeps_the_remainder() {
+        let mut timestep = FixedTimestep::new(100, 5);
+        assert_eq!(timestep.advance_by(ms(57)), 5);
+        assert_eq!(timestep.accumulator, ms(7));
+        assert!(!timestep.behind);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut timestep = FixedTimestep::new(60, 5);
+        assert_eq!(timestep.alpha(), 0.);
+        for step in 0..200 {
+            timestep.advance_by(ms(step % 37));
+            let alpha = timestep.alpha();
+            assert!(alpha >= 0. && alpha < 1., "alpha {} after {} ms", alpha, step % 37);
+        }
+
+        timestep.accumulator = ms(0);
+        timestep.advance_by(ms(8));
+        assert!((timestep.alpha() - 0.48).abs() < 0.001);
+    }
 }
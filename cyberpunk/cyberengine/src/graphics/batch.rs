@@ -0,0 +1,117 @@
+//! Sprite batching
+//!
+//! Drawing every sprite on its own is slow, so the SpriteBatch collects quads into one
+//! dynamic vertex buffer, and only issues a draw call when the texture or blend mode
+//! changes (or the buffer fills up). Sort your sprites by texture to get the most out of it.
+
+use gfx;
+
+use graphics;
+use graphics::{BlendMode, Renderer, Resources, ShaderResourceView};
+use graphics::texture::{Texture, Vertex};
+
+/// Quads are two triangles, no index buffer
+const VERTICES_PER_QUAD: usize = 6;
+
+pub struct SpriteBatch {
+    vertices: Vec<Vertex>,
+    vbuf: gfx::handle::Buffer<Resources, Vertex>,
+    /// How many quads fit in the vertex buffer
+    capacity: usize,
+    /// Id of the texture, its view and the blend mode of the quads waiting to be drawn
+    current: Option<(usize, ShaderResourceView, BlendMode)>,
+}
+
+impl SpriteBatch {
+    /// `capacity` is the number of sprites that can be drawn in one draw call
+    pub fn new(factory: &mut graphics::Factory, capacity: usize) -> SpriteBatch {
+        SpriteBatch {
+            vertices: Vec::with_capacity(capacity * VERTICES_PER_QUAD),
+            vbuf: graphics::create_dynamic_vertex_buffer(factory, capacity * VERTICES_PER_QUAD),
+            capacity: capacity,
+            current: None,
+        }
+    }
+
+    /// Queues the whole texture, centered on `position`.
+    pub fn draw(
+        &mut self,
+        renderer: &mut Renderer,
+        texture: &Texture,
+        position: (f32, f32),
+        color: [f32; 4],
+        blend: BlendMode,
+    ) -> () {
+        let (w, h) = texture.dimensions();
+        let (hw, hh) = (w as f32 / 2., h as f32 / 2.);
+        let (x, y) = position;
+        self.draw_quad(
+            renderer,
+            texture,
+            [[x - hw, y + hh], [x + hw, y + hh], [x + hw, y - hh], [x - hw, y - hh]],
+            [[0., 0.], [1., 0.], [1., 1.], [0., 1.]],
+            color,
+            blend,
+        );
+    }
+
+    /// Queues a quad with any corners and texture coordinates.
+    /// Corners go top left, top right, bottom right, bottom left.
+    pub fn draw_quad(
+        &mut self,
+        renderer: &mut Renderer,
+        texture: &Texture,
+        positions: [[f32; 2]; 4],
+        uvs: [[f32; 2]; 4],
+        color: [f32; 4],
+        blend: BlendMode,
+    ) -> () {
+        let changed = match self.current {
+            Some((id, _, current_blend)) => id != texture.id() || current_blend != blend,
+            None => true,
+        };
+        if changed {
+            self.flush(renderer);
+            self.current = Some((texture.id(), texture.clone_view(), blend));
+        } else if self.vertices.len() / VERTICES_PER_QUAD >= self.capacity {
+            self.flush(renderer);
+        }
+
+        push_quad(&mut self.vertices, positions, uvs, color);
+    }
+
+    /// Draws everything queued so far. Call this when you are done drawing,
+    /// otherwise the last texture never makes it to the screen.
+    pub fn flush(&mut self, renderer: &mut Renderer) -> () {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        match self.current {
+            Some((_, ref view, blend)) => {
+                renderer.encoder.update_buffer(&self.vbuf, &self.vertices, 0).unwrap();
+                let slice = gfx::Slice {
+                    start: 0,
+                    end: self.vertices.len() as u32,
+                    base_vertex: 0,
+                    instances: None,
+                    buffer: gfx::IndexBuffer::Auto,
+                };
+                renderer.draw_vertices_blended(view.clone(), &self.vbuf, &slice, (0., 0.), blend);
+            }
+            None => {}
+        }
+        self.vertices.clear();
+    }
+}
+
+/// Pushes two triangles. Corners go top left, top right, bottom right, bottom left.
+pub fn push_quad(vertices: &mut Vec<Vertex>, pos: [[f32; 2]; 4], uv: [[f32; 2]; 4], color: [f32; 4]) {
+    for &i in &[3, 2, 1, 1, 0, 3] {
+        vertices.push(Vertex {
+            pos: pos[i],
+            uv: uv[i],
+            color: color,
+        });
+    }
+}
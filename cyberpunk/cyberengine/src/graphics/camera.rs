@@ -0,0 +1,209 @@
+//! 2D camera
+//!
+//! The camera is a resource in the World. Rendering uses its view and projection,
+//! so moving the camera moves everything that is drawn.
+
+use cgmath;
+use cgmath::{Matrix4, Rad, Vector3};
+use specs::Entity;
+
+/// Orthographic camera, one world unit is one pixel at zoom 1.0
+#[derive(Debug, Clone)]
+pub struct Camera2D {
+    /// The point in the world at the center of the screen
+    pub position: (f32, f32),
+    /// Bigger is closer
+    pub zoom: f32,
+    /// Counter-clockwise, in radians
+    pub rotation: f32,
+    /// Size of the area the camera draws to, in pixels. Kept in sync with the window by GameState.
+    pub viewport: (f32, f32),
+    /// Entity the camera follows, see systems::camera::CameraFollow
+    pub target: Option<Entity>,
+    /// Roughly how many seconds it takes to catch up with the target, 0.0 snaps right to it
+    pub smoothing: f32,
+}
+
+impl Camera2D {
+    pub fn new(width: f32, height: f32) -> Camera2D {
+        Camera2D {
+            position: (0., 0.),
+            zoom: 1.,
+            rotation: 0.,
+            viewport: (width, height),
+            target: None,
+            smoothing: 0.,
+        }
+    }
+
+    pub fn with_position(mut self, x: f32, y: f32) -> Camera2D {
+        self.position = (x, y);
+        self
+    }
+
+    pub fn with_zoom(mut self, zoom: f32) -> Camera2D {
+        self.zoom = zoom;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Camera2D {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Follows `target`, see `smoothing`
+    pub fn following(mut self, target: Entity, smoothing: f32) -> Camera2D {
+        self.target = Some(target);
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Moves the camera by (dx, dy) world units
+    pub fn pan(&mut self, dx: f32, dy: f32) -> () {
+        self.position.0 += dx;
+        self.position.1 += dy;
+    }
+
+    /// Zooms by `factor` (2.0 is twice as close), keeping the world point under
+    /// `screen` (like the mouse cursor) in place.
+    pub fn zoom_at(&mut self, factor: f32, screen: (f32, f32)) -> () {
+        let before = self.screen_to_world(screen.0, screen.1);
+        self.zoom *= factor;
+        let after = self.screen_to_world(screen.0, screen.1);
+        self.pan(before.0 - after.0, before.1 - after.1);
+    }
+
+    /// World to camera space. Scale * rotate * translate.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_scale(self.zoom) * Matrix4::from_angle_z(Rad(-self.rotation))
+            * Matrix4::from_translation(Vector3::new(-self.position.0, -self.position.1, 0.))
+    }
+
+    /// Orthographic projection, the origin of camera space is at the center of the viewport
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        let (hw, hh) = (self.viewport.0 / 2., self.viewport.1 / 2.);
+        cgmath::ortho(-hw, hw, -hh, hh, -1., 1.)
+    }
+
+    /// Screen pixels (origin at the top left, Y down, like mouse coordinates) to world coordinates.
+    pub fn screen_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        // Relative to the center, Y up
+        let (cx, cy) = (
+            (x - self.viewport.0 / 2.) / self.zoom,
+            (self.viewport.1 / 2. - y) / self.zoom,
+        );
+        let (sin, cos) = self.rotation.sin_cos();
+        (
+            self.position.0 + cx * cos - cy * sin,
+            self.position.1 + cx * sin + cy * cos,
+        )
+    }
+
+    /// World coordinates to screen pixels, the other way around from `screen_to_world`.
+    pub fn world_to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = (x - self.position.0, y - self.position.1);
+        let (sin, cos) = (-self.rotation).sin_cos();
+        let (cx, cy) = (
+            (dx * cos - dy * sin) * self.zoom,
+            (dx * sin + dy * cos) * self.zoom,
+        );
+        (cx + self.viewport.0 / 2., self.viewport.1 / 2. - cy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use super::Camera2D;
+
+    fn assert_close(a: (f32, f32), b: (f32, f32)) -> () {
+        assert!(
+            (a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    /// Screen points all over the viewport, and a bit outside it
+    fn assert_round_trips(camera: &Camera2D) -> () {
+        for &(x, y) in &[(0., 0.), (640., 360.), (1279., 719.), (-30., 900.), (123.5, 45.25)] {
+            let world = camera.screen_to_world(x, y);
+            assert_close(camera.world_to_screen(world.0, world.1), (x, y));
+        }
+        for &(x, y) in &[(0., 0.), (-500., 20.), (1e3, -1e3)] {
+            let screen = camera.world_to_screen(x, y);
+            assert_close(camera.screen_to_world(screen.0, screen.1), (x, y));
+        }
+    }
+
+    #[test]
+    fn center_of_the_screen_is_the_position() {
+        let camera = Camera2D::new(1280., 720.)
+            .with_position(100., -50.)
+            .with_zoom(3.)
+            .with_rotation(1.);
+        assert_close(camera.screen_to_world(640., 360.), (100., -50.));
+        assert_close(camera.world_to_screen(100., -50.), (640., 360.));
+    }
+
+    #[test]
+    fn screen_y_goes_down() {
+        let camera = Camera2D::new(800., 600.);
+        // Top left corner of the screen is up and to the left in the world
+        assert_close(camera.screen_to_world(0., 0.), (-400., 300.));
+        assert_close(camera.screen_to_world(800., 600.), (400., -300.));
+    }
+
+    #[test]
+    fn zoom_scales_around_the_center() {
+        let camera = Camera2D::new(800., 600.).with_zoom(2.);
+        assert_close(camera.screen_to_world(0., 0.), (-200., 150.));
+        assert_round_trips(&camera);
+        assert_round_trips(&Camera2D::new(800., 600.).with_zoom(0.25));
+    }
+
+    #[test]
+    fn rotation_is_counter_clockwise() {
+        // The camera turns left, so the world seems to turn right on screen
+        let camera = Camera2D::new(800., 600.).with_rotation(PI / 2.);
+        assert_close(camera.world_to_screen(100., 0.), (400., 400.));
+        assert_close(camera.screen_to_world(500., 300.), (0., 100.));
+        assert_round_trips(&camera);
+    }
+
+    #[test]
+    fn round_trips_off_center() {
+        // Viewport that isn't the window size, and a camera away from the origin
+        let camera = Camera2D::new(1000., 500.)
+            .with_position(-250.5, 75.)
+            .with_zoom(1.5)
+            .with_rotation(-0.3);
+        assert_round_trips(&camera);
+        assert_close(camera.screen_to_world(500., 250.), (-250.5, 75.));
+    }
+
+    #[test]
+    fn matrices_agree_with_world_to_screen() {
+        use cgmath::Vector4;
+
+        let camera = Camera2D::new(1000., 500.)
+            .with_position(-250.5, 75.)
+            .with_zoom(1.5)
+            .with_rotation(-0.3);
+        let (x, y) = (40., -120.);
+        let clip = camera.projection_matrix() * camera.view_matrix() * Vector4::new(x, y, 0., 1.);
+        // Clip space is -1..1 with Y up
+        let screen = ((clip.x + 1.) / 2. * 1000., (1. - clip.y) / 2. * 500.);
+        assert_close(screen, camera.world_to_screen(x, y));
+    }
+
+    #[test]
+    fn zoom_at_keeps_the_point_in_place() {
+        let mut camera = Camera2D::new(800., 600.).with_position(10., 20.).with_rotation(0.5);
+        let before = camera.screen_to_world(100., 500.);
+        camera.zoom_at(4., (100., 500.));
+        assert_eq!(camera.zoom, 4.);
+        assert_close(camera.screen_to_world(100., 500.), before);
+    }
+}
@@ -1,16 +1,30 @@
 //! gfx-rs wrappers for ease of use
 
-use cgmath;
-use cgmath::{Deg, Matrix4, SquareMatrix, Vector3, Vector4, Point3};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::time::Duration;
+
+use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
 
 use gfx;
 use gfx_core;
+use gfx_window_glutin;
+use glutin;
 
 use specs;
 use shred;
 
+use error::{Error, Result};
+use resource::watch::FileWatcher;
+
 const CLEAR_COLOR: [f32; 4] = [0., 0., 0., 1.];
 
+/// The shaders are built in, but hot reloading reads them from here
+const SHADER_DIR: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders");
+const TEXTURE_VS: &'static [u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/texture_400.glslv"));
+const TEXTURE_FS: &'static [u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/texture_400.glslf"));
+
 pub use gfx_device_gl as backend;
 
 // Type aliases for ease of use
@@ -33,8 +47,17 @@ gfx_constant_struct! {
     }
 }
 
+pub mod batch;
+pub mod camera;
 pub mod texture;
 
+/// How the colors of a draw are combined with what is already on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BlendMode {
+    Alpha,
+    Additive,
+}
+
 pub struct Renderer {
     pub factory: Factory,
     device: Device,
@@ -43,10 +66,22 @@ pub struct Renderer {
     pub encoder: Encoder,
 
     pub debug_texture: texture::Texture,
+    /// 1x1 white pixel, for drawing plain colors
+    white: texture::Texture,
+    /// For `fill_rect`
+    rect_vbuf: gfx::handle::Buffer<Resources, texture::Vertex>,
 
     // PSO's
     pso_texture: PipelineState<texture::pipe::Meta>,
+    pso_texture_additive: PipelineState<texture::pipe_additive::Meta>,
     linear_sampler: gfx_core::handle::Sampler<Resources>,
+
+    /// Shared by every draw, so we don't allocate a new one each time
+    projection_cb: gfx::handle::Buffer<Resources, ModelViewProjection>,
+    view: Matrix4<f32>,
+    proj: Matrix4<f32>,
+
+    shader_watcher: Option<FileWatcher>,
 }
 
 impl Renderer {
@@ -60,25 +95,32 @@ impl Renderer {
         let encoder = factory.create_command_buffer().into();
         let sampler = factory.create_sampler_linear();
 
-        let pso_texture = factory
-            .create_pipeline_simple(
-                include_bytes!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/shaders/texture_400.glslv"
-                )),
-                include_bytes!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/shaders/texture_400.glslf"
-                )),
-                texture::pipe::new(),
-            )
-            .unwrap();
-
-        let debug_texture = texture::Builder::new()
+        let pso_texture = create_texture_pso(&mut factory, TEXTURE_VS, TEXTURE_FS, texture::pipe::new())
+            .expect("Built-in shaders are broken");
+        let pso_texture_additive =
+            create_texture_pso(&mut factory, TEXTURE_VS, TEXTURE_FS, texture::pipe_additive::new())
+                .expect("Built-in shaders are broken");
+        let projection_cb = factory.create_constant_buffer(1);
+
+        let debug_texture = match texture::Builder::new()
             .from_file("test.jpg".to_owned())
-            .build(&mut factory);
+            .build(&mut factory)
+        {
+            Ok(texture) => texture,
+            Err(e) => {
+                println!("Failed to load the debug texture: {}", e);
+                texture::missing_texture(&mut factory).expect("Failed to create the missing texture")
+            }
+        };
 
-        Renderer {
+        let white = texture::Builder::new()
+            .with_dimensions(1, 1)
+            .from_pixels(vec![255, 255, 255, 255])
+            .build(&mut factory)
+            .expect("Failed to create the white texture");
+        let rect_vbuf = create_dynamic_vertex_buffer(&mut factory, 6);
+
+        let mut renderer = Renderer {
             factory: factory,
             device: device,
             encoder: encoder,
@@ -86,9 +128,57 @@ impl Renderer {
             main_depth: main_depth,
 
             debug_texture: debug_texture,
+            white: white,
+            rect_vbuf: rect_vbuf,
 
             pso_texture: pso_texture,
+            pso_texture_additive: pso_texture_additive,
             linear_sampler: sampler,
+
+            projection_cb: projection_cb,
+            view: Matrix4::identity(),
+            proj: Matrix4::identity(),
+
+            shader_watcher: None,
+        };
+        // Until someone gives us a camera, show the world the window size
+        let (width, height) = renderer.target_size();
+        renderer.set_camera(&camera::Camera2D::new(width as f32, height as f32));
+        renderer
+    }
+
+    /// Starts watching the shader sources for changes, checking every `interval`.
+    /// Only works where the engine was built, since the sources are looked up from there.
+    pub fn enable_hot_reload(&mut self, interval: Duration) -> () {
+        let mut watcher = FileWatcher::new(interval);
+        watcher.watch_dir(SHADER_DIR);
+        self.shader_watcher = Some(watcher);
+    }
+
+    /// Rebuilds the pipelines if the shaders changed. Broken shaders are reported,
+    /// and the old pipelines kept.
+    pub fn reload_shaders(&mut self) -> () {
+        let changed = match self.shader_watcher {
+            Some(ref mut watcher) => !watcher.poll().is_empty(),
+            None => false,
+        };
+        if !changed {
+            return;
+        }
+
+        println!("Reloading shaders");
+        let result = read_shader("texture_400.glslv").and_then(|vs| {
+            let fs = read_shader("texture_400.glslf")?;
+            let pso = create_texture_pso(&mut self.factory, &vs, &fs, texture::pipe::new())?;
+            let pso_additive = create_texture_pso(&mut self.factory, &vs, &fs, texture::pipe_additive::new())?;
+            Ok((pso, pso_additive))
+        });
+        match result {
+            Ok((pso, pso_additive)) => {
+                self.pso_texture = pso;
+                self.pso_texture_additive = pso_additive;
+            }
+            Err(e) => println!("Failed to reload shaders: {}", e),
         }
     }
 
@@ -105,35 +195,165 @@ impl Renderer {
         self.device.cleanup();
     }
 
+    /// What the GL driver supports
+    pub fn device_info(&self) -> &backend::Info {
+        self.device.get_info()
+    }
+
+    /// Size of the main target (the window) in pixels
+    pub fn target_size(&self) -> (u32, u32) {
+        let (width, height, _, _) = self.main_target.get_dimensions();
+        (width as u32, height as u32)
+    }
+
+    /// Recreates the main target and depth views after the window has been resized.
+    pub fn resize(&mut self, window: &glutin::GlWindow) -> () {
+        gfx_window_glutin::update_views(window, &mut self.main_target, &mut self.main_depth);
+    }
+
+    /// Uses the view and projection of the camera for the following draws.
+    pub fn set_camera(&mut self, camera: &camera::Camera2D) -> () {
+        self.view = camera.view_matrix();
+        self.proj = camera.projection_matrix();
+    }
+
     /// Draws a texture to the main target of the renderer.
     pub fn draw_texture(&mut self, texture: &texture::Texture, position: (f32, f32)) -> () {
-        use gfx::traits::FactoryExt;
+        self.draw_vertices(texture.clone_view(), &texture.vbuf, &texture.slice, position);
+    }
 
-        let data = texture::pipe::Data {
-            texture: (texture.clone_view(), self.linear_sampler.clone()),
-            vbuf: texture.vbuf.clone(),
-            out: self.main_target.clone(),
-            projection_cb: self.factory.create_constant_buffer(1),
+    /// Fills a rectangle with a color, ignoring the camera. `rect` is (x, y, width, height)
+    /// in pixels, with the origin at the top left like mouse coordinates.
+    pub fn fill_rect(&mut self, rect: (f32, f32, f32, f32), color: [f32; 4]) -> () {
+        let (width, height) = self.target_size();
+        let (width, height) = (width as f32, height as f32);
+        // Straight to clip space, -1..1 with Y up
+        let left = rect.0 / width * 2. - 1.;
+        let right = (rect.0 + rect.2) / width * 2. - 1.;
+        let top = 1. - rect.1 / height * 2.;
+        let bottom = 1. - (rect.1 + rect.3) / height * 2.;
+        let corner = |x, y| texture::Vertex {
+            pos: [x, y],
+            uv: [0., 0.],
+            color: color,
+        };
+        let vertices = [
+            corner(left, bottom),
+            corner(right, bottom),
+            corner(right, top),
+            corner(right, top),
+            corner(left, top),
+            corner(left, bottom),
+        ];
+        self.encoder.update_buffer(&self.rect_vbuf, &vertices, 0).unwrap();
+        let slice = gfx::Slice {
+            start: 0,
+            end: vertices.len() as u32,
+            base_vertex: 0,
+            instances: None,
+            buffer: gfx::IndexBuffer::Auto,
         };
 
+        let (view, proj) = (self.view, self.proj);
+        self.view = Matrix4::identity();
+        self.proj = Matrix4::identity();
+        let white = self.white.clone_view();
+        let vbuf = self.rect_vbuf.clone();
+        self.draw_vertices(white, &vbuf, &slice, (0., 0.));
+        self.view = view;
+        self.proj = proj;
+    }
+
+    /// Draws arbitrary textured vertices (tilemap layers, etc) to the main target.
+    pub fn draw_vertices(
+        &mut self,
+        view: ShaderResourceView,
+        vbuf: &gfx::handle::Buffer<Resources, texture::Vertex>,
+        slice: &gfx::Slice<Resources>,
+        position: (f32, f32),
+    ) -> () {
+        self.draw_vertices_blended(view, vbuf, slice, position, BlendMode::Alpha);
+    }
+
+    /// Same as `draw_vertices`, with a choice of blend mode.
+    pub fn draw_vertices_blended(
+        &mut self,
+        view: ShaderResourceView,
+        vbuf: &gfx::handle::Buffer<Resources, texture::Vertex>,
+        slice: &gfx::Slice<Resources>,
+        position: (f32, f32),
+        blend: BlendMode,
+    ) -> () {
         let mvp = ModelViewProjection {
             model: Matrix4::from_translation(Vector3::new(position.0, position.1, 0.)).into(),
-            view: Matrix4::look_at(
-                Point3::new(0., 0., 720.),
-                Point3::new(0., 0., 0.),
-                Vector3::unit_y(),
-            ).into(),
-            proj: cgmath::perspective(Deg(60.0f32), 1280. / 720., 0.1, 5000.).into(),
+            view: self.view.into(),
+            proj: self.proj.into(),
         };
-        self.encoder.update_constant_buffer(
-            &data.projection_cb,
-            &mvp,
-        );
+        self.encoder.update_constant_buffer(&self.projection_cb, &mvp);
 
-        self.encoder.draw(&texture.slice, &self.pso_texture, &data);
+        match blend {
+            BlendMode::Alpha => {
+                let data = texture::pipe::Data {
+                    texture: (view, self.linear_sampler.clone()),
+                    vbuf: vbuf.clone(),
+                    out: self.main_target.clone(),
+                    projection_cb: self.projection_cb.clone(),
+                };
+                self.encoder.draw(slice, &self.pso_texture, &data);
+            }
+            BlendMode::Additive => {
+                let data = texture::pipe_additive::Data {
+                    texture: (view, self.linear_sampler.clone()),
+                    vbuf: vbuf.clone(),
+                    out: self.main_target.clone(),
+                    projection_cb: self.projection_cb.clone(),
+                };
+                self.encoder.draw(slice, &self.pso_texture_additive, &data);
+            }
+        }
     }
 }
 
+/// Both texture pipelines use the same shaders, only the blending differs.
+fn create_texture_pso<I: gfx::pso::PipelineInit>(
+    factory: &mut Factory,
+    vs: &[u8],
+    fs: &[u8],
+    init: I,
+) -> Result<PipelineState<I::Meta>> {
+    use gfx::traits::FactoryExt;
+    factory
+        .create_pipeline_simple(vs, fs, init)
+        .map_err(|e| Error::Gpu(format!("Failed to create pipeline: {:?}", e)))
+}
+
+fn read_shader(name: &str) -> Result<Vec<u8>> {
+    let path = Path::new(SHADER_DIR).join(name);
+    let io_error = |error| Error::Io {
+        path: path.display().to_string(),
+        error: error,
+    };
+    let mut f = File::open(&path).map_err(&io_error)?;
+    let mut source = Vec::new();
+    f.read_to_end(&mut source).map_err(&io_error)?;
+    Ok(source)
+}
+
+/// Creates a vertex buffer that can be updated with `Encoder::update_buffer`.
+pub fn create_dynamic_vertex_buffer(
+    factory: &mut Factory,
+    len: usize,
+) -> gfx::handle::Buffer<Resources, texture::Vertex> {
+    use gfx_core::Factory;
+    factory
+        .create_buffer(
+            len,
+            gfx::buffer::Role::Vertex,
+            gfx::memory::Usage::Dynamic,
+            gfx::memory::Bind::empty(),
+        )
+        .expect("Failed to create a vertex buffer")
+}
 
 pub trait RenderingSystem {
     /// Render the world with the system, and return the Renderer (unharmed)
@@ -1,5 +1,6 @@
 //! High-level wrapper for gfx-rs Textures and related stuff
 use std::cmp;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 use cgmath::{Matrix4, SquareMatrix, Vector4};
 use gfx;
 use gfx::traits::FactoryExt;
@@ -7,6 +8,7 @@ use gfx::traits::FactoryExt;
 //use genmesh::generators::Plane;
 use image;
 
+use error::{Error, Result};
 use graphics;
 use resource;
 
@@ -23,14 +25,29 @@ gfx_defines! {
         projection_cb: gfx::ConstantBuffer<graphics::ModelViewProjection> = "b_VsLocals",
         out: gfx::BlendTarget<graphics::ColorFormat> = ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
     }
+
+    // Same as pipe, but adds the colors together (for lights, particles and such)
+    pipeline pipe_additive {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        texture: gfx::TextureSampler<[f32; 4]> = "t_Texture",
+        projection_cb: gfx::ConstantBuffer<graphics::ModelViewProjection> = "b_VsLocals",
+        out: gfx::BlendTarget<graphics::ColorFormat> = ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ADD),
+    }
 }
 
+/// Every built texture gets its own id, clones share it
+static NEXT_TEXTURE_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Size of the squares in the missing texture
+const CHECKER_SIZE: u32 = 8;
+
 pub struct Builder {
     dimensions: Option<(u32, u32)>,
     indices: Option<Vec<u32>>,
     vertex_data: Option<Vec<Vertex>>,
     view: Option<graphics::ShaderResourceView>,
     filename: Option<String>,
+    pixels: Option<Vec<u8>>,
     flip_y: bool,
 }
 
@@ -43,13 +60,13 @@ impl Builder {
             vertex_data: None,
             view: None,
             filename: None,
+            pixels: None,
             flip_y: false,
         }
     }
 
     fn default_vertex_data_and_index(d: (u32, u32)) -> (Vec<Vertex>, Vec<u32>) {
         let (hw, hh) = (d.0 as f32 / 2., d.1 as f32 / 2.);
-        println!("vittu: {:?}", (hw, hh));
         (vec![
             Vertex { pos: [-hw, -hh], uv: [0., 1.], color: [1., 1., 1., 1.]},
             Vertex { pos: [hw, -hh], uv: [1., 1.], color: [1., 1., 1., 1.]},
@@ -74,6 +91,12 @@ impl Builder {
         self
     }
 
+    /// Raw RGBA pixels, row by row. Needs `with_dimensions` too.
+    pub fn from_pixels(mut self, pixels: Vec<u8>) -> Builder {
+        self.pixels = Some(pixels);
+        self
+    }
+
     pub fn with_view(mut self, view: graphics::ShaderResourceView) -> Builder {
         self.view = Some(view);
         self
@@ -85,39 +108,46 @@ impl Builder {
         self
     }
 
-    pub fn build(self, factory: &mut graphics::Factory) -> Texture {
+    pub fn build(self, factory: &mut graphics::Factory) -> Result<Texture> {
         let mut dimensions = match self.dimensions {
             Some(dimensions) => dimensions,
             None => (0, 0),
         };
 
-        let view = match self.view {
-            Some(view) => view,
-            None => {
-                match self.filename {
-                    Some(filename) => {
-                        use gfx::texture as t;
-                        use gfx_core::Factory;
-                        let img = image::open(filename).unwrap().to_rgba();
-                        let (width, height) = img.dimensions();
-                        let kind = t::Kind::D2(width as t::Size, height as t::Size, t::AaMode::Single);
-                        let (_, view) = factory
-                            .create_texture_immutable_u8::<graphics::ColorFormat>(kind, &[&img])
-                            .unwrap();
-
-                        dimensions = (width, height);
-                        view
-                    },
-                    None => {
-                        panic!("TextureBuilder needs to have either view or filename!");
-                    }
+        let view = match (self.view, self.filename, self.pixels) {
+            (Some(view), _, _) => view,
+            (None, Some(filename), _) => {
+                let img = decode_image(&filename)?;
+                dimensions = img.dimensions();
+                create_view(factory, dimensions, &img)?
+            }
+            (None, None, Some(pixels)) => {
+                if self.dimensions.is_none() {
+                    return Err(Error::Builder("texture::Builder needs dimensions for pixels".to_owned()));
                 }
-            },
+                if pixels.len() != (dimensions.0 * dimensions.1 * 4) as usize {
+                    return Err(Error::Builder(format!(
+                        "texture::Builder got {} bytes of pixels for a {}x{} texture",
+                        pixels.len(),
+                        dimensions.0,
+                        dimensions.1
+                    )));
+                }
+                create_view(factory, dimensions, &pixels)?
+            }
+            (None, None, None) => {
+                return Err(Error::Builder(
+                    "texture::Builder needs a view, a filename or pixels".to_owned(),
+                ))
+            }
         };
 
-        let (mut vertex_data, indices) = match self.vertex_data {
-            Some(vertex_data) => (vertex_data, self.indices.unwrap()),
-            None => Builder::default_vertex_data_and_index(dimensions),
+        let (mut vertex_data, indices) = match (self.vertex_data, self.indices) {
+            (Some(vertex_data), Some(indices)) => (vertex_data, indices),
+            (Some(_), None) => {
+                return Err(Error::Builder("texture::Builder got vertex data without indices".to_owned()))
+            }
+            (None, _) => Builder::default_vertex_data_and_index(dimensions),
         };
 
         if self.flip_y {
@@ -130,17 +160,70 @@ impl Builder {
 
         let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertex_data, &indices as &[u32]);
 
-        Texture {
+        Ok(Texture {
+            id: NEXT_TEXTURE_ID.fetch_add(1, Ordering::Relaxed),
             dimensions: dimensions,
             slice: slice,
             view: view,
             vbuf: vbuf,
+        })
+    }
+}
+
+/// Reads an image file into RGBA pixels. Doesn't touch the GPU, so this is fine to call
+/// from other threads (see resource::assets).
+pub fn decode_image(filename: &str) -> Result<image::RgbaImage> {
+    match image::open(filename) {
+        Ok(img) => Ok(img.to_rgba()),
+        Err(image::ImageError::IoError(error)) => Err(Error::Io {
+            path: filename.to_owned(),
+            error: error,
+        }),
+        Err(error) => Err(Error::Image {
+            path: filename.to_owned(),
+            error: error,
+        }),
+    }
+}
+
+fn create_view(
+    factory: &mut graphics::Factory,
+    dimensions: (u32, u32),
+    pixels: &[u8],
+) -> Result<graphics::ShaderResourceView> {
+    use gfx::texture as t;
+    use gfx_core::Factory;
+
+    let kind = t::Kind::D2(dimensions.0 as t::Size, dimensions.1 as t::Size, t::AaMode::Single);
+    match factory.create_texture_immutable_u8::<graphics::ColorFormat>(kind, &[pixels]) {
+        Ok((_, view)) => Ok(view),
+        Err(e) => Err(Error::Gpu(format!("Failed to create texture: {:?}", e))),
+    }
+}
+
+/// Magenta and black checkerboard, for textures that failed to load.
+pub fn missing_texture(factory: &mut graphics::Factory) -> Result<Texture> {
+    let size = CHECKER_SIZE * 4;
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            if (x / CHECKER_SIZE + y / CHECKER_SIZE) % 2 == 0 {
+                pixels.extend_from_slice(&[255, 0, 255, 255]);
+            } else {
+                pixels.extend_from_slice(&[0, 0, 0, 255]);
+            }
         }
     }
+
+    Builder::new()
+        .with_dimensions(size, size)
+        .from_pixels(pixels)
+        .build(factory)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Texture {
+    id: usize,
     dimensions: (u32, u32),
     view: graphics::ShaderResourceView,
     pub vbuf: gfx::handle::Buffer<graphics::Resources, Vertex>,
@@ -151,5 +234,15 @@ impl Texture {
     pub fn clone_view(&self) -> graphics::ShaderResourceView {
         self.view.clone()
     }
+
+    /// Identifies the texture, for sorting and batching. Clones have the same id.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Size of the texture in pixels
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
 }
 
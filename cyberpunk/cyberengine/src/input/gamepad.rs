@@ -0,0 +1,502 @@
+//! Gamepads
+//!
+//! Gamepad events come from a GamepadBackend, which the Window polls every frame.
+//! The real one uses gilrs (behind the `gilrs` feature), and there is a scripted one
+//! for machines without any controllers, like headless test boxes.
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[cfg(feature = "gilrs")]
+use gilrs;
+
+/// Gamepads are numbered by the backend, numbers of disconnected pads may be reused
+pub type GamepadId = usize;
+
+/// Buttons are named by their position, South is A on Xbox and Cross on PlayStation pads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Sticks go from -1.0 to 1.0, up and right are positive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftZ,
+    RightZ,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId, String),
+    Disconnected(GamepadId),
+    ButtonPressed(GamepadId, GamepadButton),
+    ButtonReleased(GamepadId, GamepadButton),
+    AxisChanged(GamepadId, GamepadAxis, f32),
+}
+
+/// Where gamepad events come from
+pub trait GamepadBackend {
+    /// Returns the events that happened since the last poll
+    fn poll(&mut self) -> Vec<GamepadEvent>;
+}
+
+/// No gamepads, ever
+pub struct NoGamepads;
+
+impl GamepadBackend for NoGamepads {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        Vec::new()
+    }
+}
+
+/// Fake gamepads, for testing without real ones. Every poll returns the next
+/// frame of the script, and nothing once the script runs out.
+pub struct ScriptedGamepads {
+    frames: VecDeque<Vec<GamepadEvent>>,
+}
+
+impl ScriptedGamepads {
+    pub fn new() -> ScriptedGamepads {
+        ScriptedGamepads {
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Adds a frame of events to the end of the script
+    pub fn with_frame(mut self, events: Vec<GamepadEvent>) -> ScriptedGamepads {
+        self.frames.push_back(events);
+        self
+    }
+
+    /// Adds `count` frames where nothing happens
+    pub fn with_idle_frames(mut self, count: usize) -> ScriptedGamepads {
+        for _ in 0..count {
+            self.frames.push_back(Vec::new());
+        }
+        self
+    }
+}
+
+impl GamepadBackend for ScriptedGamepads {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        self.frames.pop_front().unwrap_or_default()
+    }
+}
+
+/// Real gamepads through gilrs
+#[cfg(feature = "gilrs")]
+pub struct GilrsGamepads {
+    gilrs: gilrs::Gilrs,
+    /// Pads that were already connected at startup, reported on the first poll
+    connected: Vec<GamepadEvent>,
+}
+
+#[cfg(feature = "gilrs")]
+impl GilrsGamepads {
+    pub fn new() -> Result<GilrsGamepads, String> {
+        let gilrs = gilrs::Gilrs::new().map_err(|e| format!("{}", e))?;
+        let connected = gilrs
+            .gamepads()
+            .map(|(id, pad)| GamepadEvent::Connected(id, pad.name().to_owned()))
+            .collect();
+        Ok(GilrsGamepads {
+            gilrs: gilrs,
+            connected: connected,
+        })
+    }
+}
+
+#[cfg(feature = "gilrs")]
+impl GamepadBackend for GilrsGamepads {
+    fn poll(&mut self) -> Vec<GamepadEvent> {
+        use gilrs::EventType;
+
+        let mut events: Vec<GamepadEvent> = self.connected.drain(..).collect();
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            let event = match event {
+                EventType::Connected => {
+                    GamepadEvent::Connected(id, self.gilrs.gamepad(id).name().to_owned())
+                }
+                EventType::Disconnected => GamepadEvent::Disconnected(id),
+                EventType::ButtonPressed(button, _) => match convert_button(button) {
+                    Some(button) => GamepadEvent::ButtonPressed(id, button),
+                    None => continue,
+                },
+                EventType::ButtonReleased(button, _) => match convert_button(button) {
+                    Some(button) => GamepadEvent::ButtonReleased(id, button),
+                    None => continue,
+                },
+                EventType::AxisChanged(axis, value, _) => match convert_axis(axis) {
+                    Some(axis) => GamepadEvent::AxisChanged(id, axis, value),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            events.push(event);
+        }
+        events
+    }
+}
+
+#[cfg(feature = "gilrs")]
+fn convert_button(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => GamepadButton::LeftTrigger,
+        Button::RightTrigger2 => GamepadButton::RightTrigger,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftThumb,
+        Button::RightThumb => GamepadButton::RightThumb,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "gilrs")]
+fn convert_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    use gilrs::Axis;
+    Some(match axis {
+        Axis::LeftStickX => GamepadAxis::LeftStickX,
+        Axis::LeftStickY => GamepadAxis::LeftStickY,
+        Axis::RightStickX => GamepadAxis::RightStickX,
+        Axis::RightStickY => GamepadAxis::RightStickY,
+        Axis::LeftZ => GamepadAxis::LeftZ,
+        Axis::RightZ => GamepadAxis::RightZ,
+        _ => return None,
+    })
+}
+
+/// gilrs if we have it and it works, no gamepads otherwise
+pub fn default_backend() -> Box<GamepadBackend> {
+    #[cfg(feature = "gilrs")]
+    {
+        match GilrsGamepads::new() {
+            Ok(backend) => return Box::new(backend),
+            Err(e) => println!("No gamepad support: {}", e),
+        }
+    }
+    Box::new(NoGamepads)
+}
+
+/// State of one connected gamepad
+#[derive(Debug, Clone, Default)]
+pub struct Gamepad {
+    pub name: String,
+    held: HashSet<GamepadButton>,
+    pressed: HashSet<GamepadButton>,
+    released: HashSet<GamepadButton>,
+    /// Raw values, without the deadzone
+    axes: HashMap<GamepadAxis, f32>,
+}
+
+impl Gamepad {
+    pub fn new(name: String) -> Gamepad {
+        Gamepad {
+            name: name,
+            ..Gamepad::default()
+        }
+    }
+
+    pub fn button_held(&self, button: GamepadButton) -> bool {
+        self.held.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: GamepadButton) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    pub fn button_released(&self, button: GamepadButton) -> bool {
+        self.released.contains(&button)
+    }
+
+    /// Value of the axis with the deadzone applied. Values inside the deadzone are 0.0,
+    /// and the rest is scaled so the output still starts from 0.0 at the edge.
+    pub fn axis(&self, axis: GamepadAxis, deadzone: f32) -> f32 {
+        let value = self.raw_axis(axis);
+        if value.abs() <= deadzone {
+            0.
+        } else {
+            value.signum() * (value.abs() - deadzone) / (1. - deadzone)
+        }
+    }
+
+    /// Value of the axis, straight from the backend
+    pub fn raw_axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes.get(&axis).cloned().unwrap_or(0.)
+    }
+
+    pub fn press(&mut self, button: GamepadButton) -> () {
+        if self.held.insert(button) {
+            self.pressed.insert(button);
+        }
+    }
+
+    pub fn release(&mut self, button: GamepadButton) -> () {
+        if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) -> () {
+        self.axes.insert(axis, value.max(-1.).min(1.));
+    }
+
+    /// Forgets the per-tick state
+    pub fn end_tick(&mut self) -> () {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+/// Turns "PadSouth" into a button
+pub fn button_from_name(name: &str) -> Option<GamepadButton> {
+    Some(match name {
+        "PadSouth" => GamepadButton::South,
+        "PadEast" => GamepadButton::East,
+        "PadNorth" => GamepadButton::North,
+        "PadWest" => GamepadButton::West,
+        "PadLeftBumper" => GamepadButton::LeftBumper,
+        "PadRightBumper" => GamepadButton::RightBumper,
+        "PadLeftTrigger" => GamepadButton::LeftTrigger,
+        "PadRightTrigger" => GamepadButton::RightTrigger,
+        "PadSelect" => GamepadButton::Select,
+        "PadStart" => GamepadButton::Start,
+        "PadMode" => GamepadButton::Mode,
+        "PadLeftThumb" => GamepadButton::LeftThumb,
+        "PadRightThumb" => GamepadButton::RightThumb,
+        "PadUp" => GamepadButton::DPadUp,
+        "PadDown" => GamepadButton::DPadDown,
+        "PadLeft" => GamepadButton::DPadLeft,
+        "PadRight" => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+/// Turns "LeftStickX" into an axis
+pub fn axis_from_name(name: &str) -> Option<GamepadAxis> {
+    Some(match name {
+        "LeftStickX" => GamepadAxis::LeftStickX,
+        "LeftStickY" => GamepadAxis::LeftStickY,
+        "RightStickX" => GamepadAxis::RightStickX,
+        "RightStickY" => GamepadAxis::RightStickY,
+        "LeftZ" => GamepadAxis::LeftZ,
+        "RightZ" => GamepadAxis::RightZ,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::{binding_from_name, Binding, Bindings, Input};
+
+    const BINDINGS: &str = r#"
+        [actions]
+        jump = ["Space", "PadSouth"]
+        right = ["LeftStickX+"]
+
+        [axes.horizontal]
+        positive = ["D", "LeftStickX+"]
+        negative = ["A", "LeftStickX-"]
+
+        [gamepad]
+        deadzone = 0.25
+    "#;
+
+    fn input() -> Input {
+        Input::new(Bindings::parse(BINDINGS, "<test bindings>").unwrap())
+    }
+
+    /// What the Window does every frame, minus the window
+    fn poll(input: &mut Input, pads: &mut GamepadBackend) -> () {
+        input.end_tick();
+        for event in pads.poll() {
+            input.handle_gamepad_event(event);
+        }
+    }
+
+    fn assert_near(value: f32, expected: f32) -> () {
+        assert!((value - expected).abs() < 1e-5, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let mut input = input();
+        let mut pads = ScriptedGamepads::new()
+            .with_frame(vec![
+                GamepadEvent::Connected(0, "Pad".to_owned()),
+                GamepadEvent::Connected(1, "Other pad".to_owned()),
+            ])
+            .with_frame(vec![GamepadEvent::Disconnected(0)])
+            .with_idle_frames(1)
+            .with_frame(vec![GamepadEvent::Connected(0, "Pad again".to_owned())]);
+
+        poll(&mut input, &mut pads);
+        assert_eq!(input.gamepads().len(), 2);
+        assert_eq!(input.gamepad(0).unwrap().name, "Pad");
+
+        poll(&mut input, &mut pads);
+        assert!(input.gamepad(0).is_none());
+        assert_eq!(input.gamepad(1).unwrap().name, "Other pad");
+
+        poll(&mut input, &mut pads);
+        assert_eq!(input.gamepads().len(), 1);
+
+        // Numbers get reused
+        poll(&mut input, &mut pads);
+        assert_eq!(input.gamepad(0).unwrap().name, "Pad again");
+
+        // The script has run out
+        poll(&mut input, &mut pads);
+        assert_eq!(input.gamepads().len(), 2);
+    }
+
+    #[test]
+    fn events_before_connecting_add_the_pad() {
+        let mut input = input();
+        let mut pads = ScriptedGamepads::new().with_frame(vec![
+            GamepadEvent::ButtonPressed(3, GamepadButton::Start),
+        ]);
+        poll(&mut input, &mut pads);
+        assert!(input.gamepad(3).unwrap().button_held(GamepadButton::Start));
+    }
+
+    #[test]
+    fn deadzone() {
+        let mut input = input();
+        assert_near(input.bindings().deadzone(), 0.25);
+        let mut pads = ScriptedGamepads::new()
+            .with_frame(vec![
+                GamepadEvent::Connected(0, "Pad".to_owned()),
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickX, 0.2),
+            ])
+            .with_frame(vec![GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickX, 0.625)])
+            .with_frame(vec![GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickX, -1.)])
+            .with_frame(vec![GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickX, 1.5)]);
+
+        // Inside the deadzone
+        poll(&mut input, &mut pads);
+        assert_near(input.gamepad(0).unwrap().raw_axis(GamepadAxis::LeftStickX), 0.2);
+        assert_near(input.gamepad(0).unwrap().axis(GamepadAxis::LeftStickX, 0.25), 0.);
+        assert_near(input.axis("horizontal"), 0.);
+
+        // Scaled so the edge of the deadzone is 0.0
+        poll(&mut input, &mut pads);
+        assert_near(input.axis("horizontal"), 0.5);
+
+        poll(&mut input, &mut pads);
+        assert_near(input.axis("horizontal"), -1.);
+
+        // Clamped
+        poll(&mut input, &mut pads);
+        assert_near(input.gamepad(0).unwrap().raw_axis(GamepadAxis::LeftStickX), 1.);
+        assert_near(input.axis("horizontal"), 1.);
+    }
+
+    #[test]
+    fn button_action() {
+        assert_eq!(
+            binding_from_name("PadSouth"),
+            Some(Binding::GamepadButton(GamepadButton::South))
+        );
+
+        let mut input = input();
+        let mut pads = ScriptedGamepads::new()
+            .with_frame(vec![GamepadEvent::Connected(0, "Pad".to_owned())])
+            .with_frame(vec![GamepadEvent::ButtonPressed(0, GamepadButton::South)])
+            .with_idle_frames(1)
+            .with_frame(vec![GamepadEvent::ButtonReleased(0, GamepadButton::South)])
+            .with_idle_frames(1);
+
+        poll(&mut input, &mut pads);
+        assert!(!input.action_held("jump"));
+
+        poll(&mut input, &mut pads);
+        assert!(input.action_pressed("jump"));
+        assert!(input.action_held("jump"));
+
+        // Pressed only lasts a tick
+        poll(&mut input, &mut pads);
+        assert!(!input.action_pressed("jump"));
+        assert!(input.action_held("jump"));
+
+        poll(&mut input, &mut pads);
+        assert!(input.action_released("jump"));
+        assert!(!input.action_held("jump"));
+
+        poll(&mut input, &mut pads);
+        assert!(!input.action_released("jump"));
+    }
+
+    #[test]
+    fn axis_binding() {
+        assert_eq!(
+            binding_from_name("LeftStickX+"),
+            Some(Binding::GamepadAxis(GamepadAxis::LeftStickX, true))
+        );
+        assert_eq!(
+            binding_from_name("LeftStickX-"),
+            Some(Binding::GamepadAxis(GamepadAxis::LeftStickX, false))
+        );
+        assert_eq!(binding_from_name("LeftStickW+"), None);
+
+        let mut input = input();
+        let mut pads = ScriptedGamepads::new()
+            .with_frame(vec![
+                GamepadEvent::Connected(0, "Pad".to_owned()),
+                GamepadEvent::Connected(1, "Other pad".to_owned()),
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickX, 0.5),
+            ])
+            .with_frame(vec![GamepadEvent::AxisChanged(1, GamepadAxis::LeftStickX, 1.)])
+            .with_frame(vec![
+                GamepadEvent::AxisChanged(0, GamepadAxis::LeftStickX, -1.),
+                GamepadEvent::AxisChanged(1, GamepadAxis::LeftStickX, 0.),
+            ]);
+
+        // (0.5 - 0.25) / 0.75, not past halfway
+        poll(&mut input, &mut pads);
+        assert_near(input.axis("horizontal"), 1. / 3.);
+        assert!(!input.action_held("right"));
+
+        // The pad pushed furthest wins
+        poll(&mut input, &mut pads);
+        assert!(input.action_held("right"));
+        assert!(!input.action_pressed("right"));
+        assert_near(input.axis("horizontal"), 1.);
+
+        // Pushed the other way
+        poll(&mut input, &mut pads);
+        assert!(!input.action_held("right"));
+        assert_near(input.axis("horizontal"), -1.);
+    }
+}
@@ -0,0 +1,553 @@
+//! Keyboard, mouse and gamepad input
+//!
+//! The Window collects events into an Input, which is copied into the World of the active
+//! GameState before every tick. Systems can then fetch it like any other resource:
+//!
+//! ```ignore
+//! type SystemData = Fetch<'a, Input>;
+//! if input.action_pressed("jump") { ... }
+//! ```
+//!
+//! "Just pressed" and "just released" last for exactly one tick, even if the frame
+//! runs several ticks (or none at all).
+//!
+//! Actions and axes are named bindings loaded from TOML, see `Bindings`.
+//! Gamepads have their own backend, see the `gamepad` module.
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+
+use glutin;
+use toml;
+
+use config::ConfigError;
+use error::Error;
+
+pub use glutin::{MouseButton, VirtualKeyCode as Key};
+
+pub mod gamepad;
+
+pub use self::gamepad::{Gamepad, GamepadAxis, GamepadButton, GamepadEvent, GamepadId};
+
+/// Used when the bindings don't set a deadzone
+pub const DEFAULT_DEADZONE: f32 = 0.2;
+
+/// Something that can be bound to an action
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButton),
+    /// One direction of a gamepad axis, true for the positive one
+    GamepadAxis(GamepadAxis, bool),
+}
+
+/// An axis goes from -1.0 to 1.0, like left/right movement
+#[derive(Debug, Clone, Default)]
+pub struct Axis {
+    pub positive: Vec<Binding>,
+    pub negative: Vec<Binding>,
+}
+
+/// Named actions and axes.
+///
+/// ```toml
+/// [actions]
+/// jump = ["Space", "W"]
+/// fire = ["MouseLeft"]
+///
+/// [axes.horizontal]
+/// positive = ["D", "Right", "LeftStickX+"]
+/// negative = ["A", "Left", "LeftStickX-"]
+///
+/// [gamepad]
+/// deadzone = 0.2
+/// ```
+///
+/// Keys are named like glutin's VirtualKeyCode, mouse buttons are
+/// MouseLeft, MouseRight, MouseMiddle and Mouse4, Mouse5...
+/// Gamepad buttons are PadSouth, PadStart, PadUp... (see `gamepad::button_from_name`),
+/// and gamepad axes are the name of the axis followed by the direction, like LeftStickY+.
+#[derive(Debug, Clone, Default)]
+pub struct Bindings {
+    pub actions: HashMap<String, Vec<Binding>>,
+    pub axes: HashMap<String, Axis>,
+    /// How far a stick has to move before it counts, DEFAULT_DEADZONE if not set
+    pub deadzone: Option<f32>,
+}
+
+impl Bindings {
+    pub fn deadzone(&self) -> f32 {
+        self.deadzone.unwrap_or(DEFAULT_DEADZONE)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawBindings {
+    #[serde(default)]
+    actions: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    axes: HashMap<String, RawAxis>,
+    gamepad: Option<RawGamepad>,
+}
+
+#[derive(Deserialize)]
+struct RawGamepad {
+    deadzone: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct RawAxis {
+    #[serde(default)]
+    positive: Vec<String>,
+    #[serde(default)]
+    negative: Vec<String>,
+}
+
+impl Bindings {
+    /// Parses the `defaults`, and then the optional file at `path` on top of them.
+    /// Actions and axes in the file replace the default ones with the same name.
+    pub fn load<P: AsRef<Path>>(defaults: &str, path: P) -> Result<Bindings, Error> {
+        let mut bindings = Bindings::parse(defaults, "<embedded bindings>")?;
+
+        let source = path.as_ref().display().to_string();
+        let contents = match File::open(path.as_ref()) {
+            Ok(mut f) => {
+                let mut contents = String::new();
+                match f.read_to_string(&mut contents) {
+                    Ok(_) => contents,
+                    Err(error) => return Err(ConfigError::Io { source: source, error: error }.into()),
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(bindings),
+            Err(error) => return Err(ConfigError::Io { source: source, error: error }.into()),
+        };
+
+        println!("Loading input bindings from {}", source);
+        let overrides = Bindings::parse(&contents, &source)?;
+        bindings.actions.extend(overrides.actions);
+        bindings.axes.extend(overrides.axes);
+        if overrides.deadzone.is_some() {
+            bindings.deadzone = overrides.deadzone;
+        }
+        Ok(bindings)
+    }
+
+    /// Parses bindings from a TOML string. `source` is only used for error messages.
+    pub fn parse(contents: &str, source: &str) -> Result<Bindings, Error> {
+        let raw: RawBindings = toml::from_str(contents).map_err(|error| {
+            ConfigError::Parse {
+                source: source.to_owned(),
+                error: error,
+            }
+        })?;
+
+        let mut bindings = Bindings::default();
+        if let Some(gamepad) = raw.gamepad {
+            if let Some(deadzone) = gamepad.deadzone {
+                if deadzone < 0. || deadzone >= 1. {
+                    return Err(ConfigError::InvalidValue {
+                        source: source.to_owned(),
+                        key: "gamepad.deadzone".to_owned(),
+                        message: format!("must be between 0.0 and 1.0, got {}", deadzone),
+                    }.into());
+                }
+                bindings.deadzone = Some(deadzone);
+            }
+        }
+        for (name, inputs) in raw.actions {
+            let key = format!("actions.{}", name);
+            bindings.actions.insert(name, parse_bindings(&inputs, source, &key)?);
+        }
+        for (name, axis) in raw.axes {
+            let key = format!("axes.{}", name);
+            bindings.axes.insert(
+                name,
+                Axis {
+                    positive: parse_bindings(&axis.positive, source, &format!("{}.positive", key))?,
+                    negative: parse_bindings(&axis.negative, source, &format!("{}.negative", key))?,
+                },
+            );
+        }
+        Ok(bindings)
+    }
+}
+
+fn parse_bindings(names: &[String], source: &str, key: &str) -> Result<Vec<Binding>, ConfigError> {
+    names
+        .iter()
+        .map(|name| match binding_from_name(name) {
+            Some(binding) => Ok(binding),
+            None => Err(ConfigError::InvalidValue {
+                source: source.to_owned(),
+                key: key.to_owned(),
+                message: format!("has an unknown input {:?}", name),
+            }),
+        })
+        .collect()
+}
+
+/// Current state of the keyboard, mouse and gamepads
+#[derive(Debug, Clone, Default)]
+pub struct Input {
+    held_keys: HashSet<Key>,
+    pressed_keys: HashSet<Key>,
+    released_keys: HashSet<Key>,
+    held_buttons: HashSet<MouseButton>,
+    pressed_buttons: HashSet<MouseButton>,
+    released_buttons: HashSet<MouseButton>,
+    /// Cursor position in window pixels, origin at the top left
+    pub cursor: (f32, f32),
+    /// How much the wheel was scrolled during the tick, in lines
+    pub wheel: f32,
+    /// Text typed during the tick
+    pub text: String,
+    gamepads: HashMap<GamepadId, Gamepad>,
+    bindings: Arc<Bindings>,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Input {
+        Input {
+            bindings: Arc::new(bindings),
+            ..Input::default()
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn key_held(&self, key: Key) -> bool {
+        self.held_keys.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key: Key) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    pub fn key_released(&self, key: Key) -> bool {
+        self.released_keys.contains(&key)
+    }
+
+    pub fn button_held(&self, button: MouseButton) -> bool {
+        self.held_buttons.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    pub fn button_released(&self, button: MouseButton) -> bool {
+        self.released_buttons.contains(&button)
+    }
+
+    /// The connected gamepads
+    pub fn gamepads(&self) -> &HashMap<GamepadId, Gamepad> {
+        &self.gamepads
+    }
+
+    pub fn gamepad(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.gamepads.get(&id)
+    }
+
+    /// How much a binding is pressed, from 0.0 to 1.0. Buttons are either 0.0 or 1.0,
+    /// gamepad axes can be anything in between. With several gamepads, the one
+    /// pushed the furthest wins.
+    pub fn binding_value(&self, binding: &Binding) -> f32 {
+        let deadzone = self.bindings.deadzone();
+        let pressed = |held: bool| if held { 1. } else { 0. };
+        match *binding {
+            Binding::Key(key) => pressed(self.key_held(key)),
+            Binding::Mouse(button) => pressed(self.button_held(button)),
+            Binding::GamepadButton(button) => {
+                pressed(self.gamepads.values().any(|pad| pad.button_held(button)))
+            }
+            Binding::GamepadAxis(axis, positive) => self.gamepads
+                .values()
+                .map(|pad| {
+                    let value = pad.axis(axis, deadzone);
+                    if positive { value } else { -value }
+                })
+                .fold(0., f32::max),
+        }
+    }
+
+    /// Axis bindings count as held when they are pushed more than halfway
+    pub fn binding_held(&self, binding: &Binding) -> bool {
+        self.binding_value(binding) > 0.5
+    }
+
+    /// Axis bindings are never "just pressed", use binding_held for them
+    pub fn binding_pressed(&self, binding: &Binding) -> bool {
+        match *binding {
+            Binding::Key(key) => self.key_pressed(key),
+            Binding::Mouse(button) => self.button_pressed(button),
+            Binding::GamepadButton(button) => {
+                self.gamepads.values().any(|pad| pad.button_pressed(button))
+            }
+            Binding::GamepadAxis(..) => false,
+        }
+    }
+
+    pub fn binding_released(&self, binding: &Binding) -> bool {
+        match *binding {
+            Binding::Key(key) => self.key_released(key),
+            Binding::Mouse(button) => self.button_released(button),
+            Binding::GamepadButton(button) => {
+                self.gamepads.values().any(|pad| pad.button_released(button))
+            }
+            Binding::GamepadAxis(..) => false,
+        }
+    }
+
+    /// Is any input bound to the action held down. Unknown actions are never held.
+    pub fn action_held(&self, action: &str) -> bool {
+        match self.bindings.actions.get(action) {
+            Some(bindings) => bindings.iter().any(|b| self.binding_held(b)),
+            None => false,
+        }
+    }
+
+    pub fn action_pressed(&self, action: &str) -> bool {
+        match self.bindings.actions.get(action) {
+            Some(bindings) => bindings.iter().any(|b| self.binding_pressed(b)),
+            None => false,
+        }
+    }
+
+    pub fn action_released(&self, action: &str) -> bool {
+        match self.bindings.actions.get(action) {
+            Some(bindings) => bindings.iter().any(|b| self.binding_released(b)),
+            None => false,
+        }
+    }
+
+    /// Value of the axis, from -1.0 to 1.0. Unknown axes are always 0.0.
+    /// Keys give full values, sticks anything in between.
+    pub fn axis(&self, axis: &str) -> f32 {
+        match self.bindings.axes.get(axis) {
+            Some(axis) => {
+                let positive = axis.positive.iter().map(|b| self.binding_value(b)).fold(0., f32::max);
+                let negative = axis.negative.iter().map(|b| self.binding_value(b)).fold(0., f32::max);
+                positive - negative
+            }
+            None => 0.,
+        }
+    }
+
+    /// Updates the gamepad state from a backend event.
+    pub fn handle_gamepad_event(&mut self, event: GamepadEvent) -> () {
+        match event {
+            GamepadEvent::Connected(id, name) => {
+                println!("Gamepad {} connected: {}", id, name);
+                self.gamepads.insert(id, Gamepad::new(name));
+            }
+            GamepadEvent::Disconnected(id) => {
+                println!("Gamepad {} disconnected", id);
+                self.gamepads.remove(&id);
+            }
+            GamepadEvent::ButtonPressed(id, button) => {
+                self.gamepad_entry(id).press(button);
+            }
+            GamepadEvent::ButtonReleased(id, button) => {
+                self.gamepad_entry(id).release(button);
+            }
+            GamepadEvent::AxisChanged(id, axis, value) => {
+                self.gamepad_entry(id).set_axis(axis, value);
+            }
+        }
+    }
+
+    /// Backends don't always tell about pads that were connected before we started
+    fn gamepad_entry(&mut self, id: GamepadId) -> &mut Gamepad {
+        self.gamepads
+            .entry(id)
+            .or_insert_with(|| Gamepad::new(format!("Gamepad {}", id)))
+    }
+
+    /// Updates the state from a window event. Events we don't care about are ignored.
+    pub fn handle_event(&mut self, event: &glutin::WindowEvent) -> () {
+        use glutin::WindowEvent::*;
+        use glutin::ElementState::{Pressed, Released};
+        use glutin::MouseScrollDelta;
+
+        match *event {
+            KeyboardInput { input, .. } => match input.virtual_keycode {
+                Some(key) => match input.state {
+                    // Key repeat sends more presses, only the first one counts
+                    Pressed => if self.held_keys.insert(key) {
+                        self.pressed_keys.insert(key);
+                    },
+                    Released => if self.held_keys.remove(&key) {
+                        self.released_keys.insert(key);
+                    },
+                },
+                None => {}
+            },
+            MouseMoved { position: (x, y), .. } => self.cursor = (x as f32, y as f32),
+            MouseInput { state, button, .. } => match state {
+                Pressed => if self.held_buttons.insert(button) {
+                    self.pressed_buttons.insert(button);
+                },
+                Released => if self.held_buttons.remove(&button) {
+                    self.released_buttons.insert(button);
+                },
+            },
+            MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(_, y) => self.wheel += y,
+                // Guess how many pixels a line is
+                MouseScrollDelta::PixelDelta(_, y) => self.wheel += y / 20.,
+            },
+            ReceivedCharacter(c) => if !c.is_control() {
+                self.text.push(c);
+            },
+            // Don't leave keys stuck down when alt-tabbing
+            Focused(false) => {
+                self.released_keys.extend(self.held_keys.drain());
+                self.released_buttons.extend(self.held_buttons.drain());
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets the per-tick state, called after every tick.
+    pub fn end_tick(&mut self) -> () {
+        self.pressed_keys.clear();
+        self.released_keys.clear();
+        self.pressed_buttons.clear();
+        self.released_buttons.clear();
+        self.wheel = 0.;
+        self.text.clear();
+        for pad in self.gamepads.values_mut() {
+            pad.end_tick();
+        }
+    }
+}
+
+/// Turns "Space", "MouseLeft", "PadSouth" or "LeftStickX+" into a Binding
+pub fn binding_from_name(name: &str) -> Option<Binding> {
+    if let Some(button) = gamepad::button_from_name(name) {
+        return Some(Binding::GamepadButton(button));
+    }
+    if name.ends_with('+') || name.ends_with('-') {
+        let (axis, direction) = name.split_at(name.len() - 1);
+        return gamepad::axis_from_name(axis).map(|axis| Binding::GamepadAxis(axis, direction == "+"));
+    }
+
+    match name {
+        "MouseLeft" => return Some(Binding::Mouse(MouseButton::Left)),
+        "MouseRight" => return Some(Binding::Mouse(MouseButton::Right)),
+        "MouseMiddle" => return Some(Binding::Mouse(MouseButton::Middle)),
+        _ => {}
+    }
+    if name.starts_with("Mouse") {
+        return match name["Mouse".len()..].parse::<u8>() {
+            Ok(n) => Some(Binding::Mouse(MouseButton::Other(n))),
+            Err(_) => None,
+        };
+    }
+    key_from_name(name).map(Binding::Key)
+}
+
+/// Names are the same as the VirtualKeyCode variants
+pub fn key_from_name(name: &str) -> Option<Key> {
+    use glutin::VirtualKeyCode::*;
+    let key = match name {
+        "Key1" | "1" => Key1,
+        "Key2" | "2" => Key2,
+        "Key3" | "3" => Key3,
+        "Key4" | "4" => Key4,
+        "Key5" | "5" => Key5,
+        "Key6" | "6" => Key6,
+        "Key7" | "7" => Key7,
+        "Key8" | "8" => Key8,
+        "Key9" | "9" => Key9,
+        "Key0" | "0" => Key0,
+        "A" => A,
+        "B" => B,
+        "C" => C,
+        "D" => D,
+        "E" => E,
+        "F" => F,
+        "G" => G,
+        "H" => H,
+        "I" => I,
+        "J" => J,
+        "K" => K,
+        "L" => L,
+        "M" => M,
+        "N" => N,
+        "O" => O,
+        "P" => P,
+        "Q" => Q,
+        "R" => R,
+        "S" => S,
+        "T" => T,
+        "U" => U,
+        "V" => V,
+        "W" => W,
+        "X" => X,
+        "Y" => Y,
+        "Z" => Z,
+        "Escape" => Escape,
+        "F1" => F1,
+        "F2" => F2,
+        "F3" => F3,
+        "F4" => F4,
+        "F5" => F5,
+        "F6" => F6,
+        "F7" => F7,
+        "F8" => F8,
+        "F9" => F9,
+        "F10" => F10,
+        "F11" => F11,
+        "F12" => F12,
+        "Insert" => Insert,
+        "Home" => Home,
+        "Delete" => Delete,
+        "End" => End,
+        "PageDown" => PageDown,
+        "PageUp" => PageUp,
+        "Left" => Left,
+        "Up" => Up,
+        "Right" => Right,
+        "Down" => Down,
+        "Back" => Back,
+        "Return" => Return,
+        "Space" => Space,
+        "Tab" => Tab,
+        "Numpad0" => Numpad0,
+        "Numpad1" => Numpad1,
+        "Numpad2" => Numpad2,
+        "Numpad3" => Numpad3,
+        "Numpad4" => Numpad4,
+        "Numpad5" => Numpad5,
+        "Numpad6" => Numpad6,
+        "Numpad7" => Numpad7,
+        "Numpad8" => Numpad8,
+        "Numpad9" => Numpad9,
+        "LAlt" => LAlt,
+        "LControl" => LControl,
+        "LShift" => LShift,
+        "RAlt" => RAlt,
+        "RControl" => RControl,
+        "RShift" => RShift,
+        "Comma" => Comma,
+        "Period" => Period,
+        "Minus" => Minus,
+        "Equals" => Equals,
+        "Slash" => Slash,
+        "Semicolon" => Semicolon,
+        "Apostrophe" => Apostrophe,
+        "Grave" => Grave,
+        "LBracket" => LBracket,
+        "RBracket" => RBracket,
+        "Backslash" => Backslash,
+        _ => return None,
+    };
+    Some(key)
+}
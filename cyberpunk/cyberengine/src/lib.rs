@@ -3,8 +3,13 @@
 
 #![allow(unused_variables)]
 
+extern crate base64;
 extern crate cgmath;
+extern crate cyberpak;
+extern crate flate2;
 extern crate genmesh;
+#[cfg(feature = "gilrs")]
+extern crate gilrs;
 #[macro_use]
 extern crate gfx;
 extern crate gfx_core;
@@ -14,20 +19,28 @@ extern crate image;
 extern crate imgui;
 extern crate imgui_gfx_renderer;
 extern crate rand;
+extern crate ron;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate specs;
 extern crate shred;
 extern crate toml;
 extern crate winit;
+extern crate xml;
 extern crate glutin;
 extern crate gfx_window_glutin;
 extern crate rayon;
+extern crate zip;
 
 pub use specs::{Component, VecStorage, World, System, DispatcherBuilder};
+pub use error::{Error, Result};
 
 pub mod config;
+pub mod error;
 pub mod game;
+pub mod input;
 pub mod state;
 
 pub mod window;
@@ -35,3 +48,5 @@ pub mod graphics;
 pub mod screen;
 pub mod systems;
 pub mod resource;
+pub mod scene;
+pub mod ui;
@@ -0,0 +1,455 @@
+//! Asynchronous asset loading
+//!
+//! The AssetServer hands out a Handle as soon as something asks for an asset, and
+//! decodes the file on the rayon thread pool. Decoded images still need to get to
+//! the GPU, which can only happen on the render thread, so the state Manager calls
+//! `upload` once per frame, and it uploads as much as the per-frame budget allows.
+//!
+//! Things that want an asset keep the handle around until `texture` returns something.
+//! Anything else an AssetLoader makes, like maps, can be loaded with `load`, and is
+//! handed over by `take` once it's done.
+//! Files are read through an AssetRegistry, so mounted archives and mods work too.
+//!
+//! Every state has its own AssetServer, but they are `share`s of the one the Game
+//! made, so a texture two states use is only loaded once. Textures are dropped when
+//! no state uses them anymore.
+//!
+//! With hot reloading enabled, textures that are plain files are watched, and loaded
+//! again when they change. `take_reloaded` tells which textures were replaced, so
+//! whoever holds copies of them can swap them out.
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use image;
+use rayon;
+
+use error::Error;
+use graphics;
+use graphics::texture::{self, Texture};
+use resource::AssetRegistry;
+use resource::loader::BoxedAsset;
+use resource::watch::FileWatcher;
+
+/// How many bytes of pixels are uploaded per frame by default
+const DEFAULT_UPLOAD_BUDGET: usize = 4 * 1024 * 1024;
+
+/// Typed id of an asset, cheap to copy around. Handles to the same file are equal.
+pub struct Handle<T> {
+    id: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T> Handle<T> {
+    fn new(id: usize) -> Handle<T> {
+        Handle {
+            id: id,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+// Derives would want T: Clone and friends, which we don't need
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle::new(self.id)
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Handle<T>) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState {
+    /// Being decoded, or waiting for its turn to be uploaded
+    Loading,
+    Loaded,
+    /// Couldn't be loaded, the missing texture is used instead
+    Failed,
+}
+
+enum Slot<T> {
+    Loading,
+    Loaded(T),
+    Failed,
+}
+
+type Decoded = (usize, String, Result<image::RgbaImage, Error>);
+
+/// The server that asked for it with `load`, and the result once the job is done
+type Loaded = (usize, Option<Result<BoxedAsset, Error>>);
+
+/// What the shares of an AssetServer have in common
+struct Store {
+    next_id: usize,
+    next_server: usize,
+    by_path: HashMap<String, usize>,
+    textures: HashMap<usize, Slot<Texture>>,
+    /// The servers using each texture
+    users: HashMap<usize, HashSet<usize>>,
+    /// Decoded, but not uploaded yet
+    pending: VecDeque<Decoded>,
+    missing: Option<Texture>,
+    upload_budget: usize,
+    watcher: Option<FileWatcher>,
+    /// Asset paths of the watched files
+    watched: HashMap<PathBuf, String>,
+    /// For each server, the id of the old texture and the one that replaced it
+    reloaded: HashMap<usize, Vec<(usize, Texture)>>,
+}
+
+/// Loads assets in the background. Lives in the World as a resource.
+pub struct AssetServer {
+    id: usize,
+    registry: Arc<AssetRegistry>,
+    store: Arc<Mutex<Store>>,
+    /// Filled by the decoding tasks
+    decoded: Arc<Mutex<Vec<Decoded>>>,
+    /// Filled by the tasks of `load`, by id
+    loaded: Arc<Mutex<HashMap<usize, Loaded>>>,
+}
+
+impl AssetServer {
+    /// Reads from the working directory, see `AssetRegistry::with_defaults`
+    pub fn new() -> AssetServer {
+        AssetServer::with_registry(AssetRegistry::with_defaults())
+    }
+
+    pub fn with_registry(registry: AssetRegistry) -> AssetServer {
+        AssetServer {
+            id: 0,
+            registry: Arc::new(registry),
+            store: Arc::new(Mutex::new(Store {
+                next_id: 0,
+                next_server: 1,
+                by_path: HashMap::new(),
+                textures: HashMap::new(),
+                users: HashMap::new(),
+                pending: VecDeque::new(),
+                missing: None,
+                upload_budget: DEFAULT_UPLOAD_BUDGET,
+                watcher: None,
+                watched: HashMap::new(),
+                reloaded: HashMap::new(),
+            })),
+            decoded: Arc::new(Mutex::new(Vec::new())),
+            loaded: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Another server reading from the same registry and sharing the same textures.
+    /// What it loads stays loaded until it's unloaded or dropped, and nobody else uses it.
+    pub fn share(&self) -> AssetServer {
+        let id = {
+            let mut store = self.store.lock().unwrap();
+            store.next_server += 1;
+            store.next_server - 1
+        };
+        AssetServer {
+            id: id,
+            registry: self.registry.clone(),
+            store: self.store.clone(),
+            decoded: self.decoded.clone(),
+            loaded: self.loaded.clone(),
+        }
+    }
+
+    /// How many bytes of pixels can be uploaded per frame. At least one texture
+    /// is always uploaded, no matter how big it is. Shared by every share.
+    pub fn with_upload_budget(self, bytes: usize) -> AssetServer {
+        self.store.lock().unwrap().upload_budget = bytes;
+        self
+    }
+
+    /// Starts watching textures for changes, checking every `interval`.
+    pub fn enable_hot_reload(&mut self, interval: Duration) -> () {
+        let mut store = self.store.lock().unwrap();
+        if store.watcher.is_some() {
+            return;
+        }
+        store.watcher = Some(FileWatcher::new(interval));
+        let paths: Vec<String> = store.by_path.keys().cloned().collect();
+        for path in paths {
+            self.watch(&mut store, &path);
+        }
+    }
+
+    fn watch(&self, store: &mut Store, path: &str) -> () {
+        if let Some(ref mut watcher) = store.watcher {
+            if let Some(file) = self.registry.file_path(path) {
+                watcher.watch(&file);
+                store.watched.insert(file, path.to_owned());
+            }
+        }
+    }
+
+    /// Textures used by this server that were reloaded since the last call, as the id
+    /// of the old texture and the new texture.
+    pub fn take_reloaded(&mut self) -> Vec<(usize, Texture)> {
+        match self.store.lock().unwrap().reloaded.get_mut(&self.id) {
+            Some(reloaded) => reloaded.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// For loading things that don't need the GPU, like maps or config
+    pub fn registry(&self) -> &AssetRegistry {
+        &self.registry
+    }
+
+    /// Starts loading an image in the background, if it isn't loaded already.
+    pub fn load_texture(&mut self, path: &str) -> Handle<Texture> {
+        let mut store = self.store.lock().unwrap();
+        let store = &mut *store;
+        if let Some(&id) = store.by_path.get(path) {
+            store.users.entry(id).or_insert_with(HashSet::new).insert(self.id);
+            return Handle::new(id);
+        }
+
+        let id = store.next_id;
+        store.next_id += 1;
+        store.by_path.insert(path.to_owned(), id);
+        store.textures.insert(id, Slot::Loading);
+        store.users.entry(id).or_insert_with(HashSet::new).insert(self.id);
+        self.decode(id, path);
+        self.watch(store, path);
+
+        Handle::new(id)
+    }
+
+    /// Reads and decodes an image on the thread pool
+    fn decode(&self, id: usize, path: &str) -> () {
+        let decoded = self.decoded.clone();
+        let registry = self.registry.clone();
+        let path = path.to_owned();
+        rayon::spawn(move || {
+            let result = registry.load::<image::RgbaImage>(&path);
+            decoded.lock().unwrap().push((id, path, result));
+        });
+    }
+
+    /// Starts loading anything the registry has a loader for, like a Tilemap. Unlike
+    /// textures, nothing is shared or cached, every call loads the file again.
+    /// `take` gives the result once it's done.
+    pub fn load<T: Any + Send>(&mut self, path: &str) -> Handle<T> {
+        let id = {
+            let mut store = self.store.lock().unwrap();
+            store.next_id += 1;
+            store.next_id - 1
+        };
+        self.loaded.lock().unwrap().insert(id, (self.id, None));
+
+        let loaded = self.loaded.clone();
+        let registry = self.registry.clone();
+        let path = path.to_owned();
+        rayon::spawn(move || {
+            let result = registry.load::<T>(&path).map(|asset| Box::new(asset) as BoxedAsset);
+            // Not there if it was unloaded in the meantime
+            if let Some(&mut (_, ref mut slot)) = loaded.lock().unwrap().get_mut(&id) {
+                *slot = Some(result);
+            }
+        });
+
+        Handle::new(id)
+    }
+
+    /// What `load` gave, once it's done. Only the first call after that gets it.
+    pub fn take<T: Any>(&mut self, handle: Handle<T>) -> Option<Result<T, Error>> {
+        let mut loaded = self.loaded.lock().unwrap();
+        let done = match loaded.get(&handle.id) {
+            Some(&(_, Some(_))) => true,
+            _ => false,
+        };
+        if !done {
+            return None;
+        }
+        match loaded.remove(&handle.id) {
+            Some((_, Some(Ok(asset)))) => match asset.downcast::<T>() {
+                Ok(asset) => Some(Ok(*asset)),
+                Err(_) => Some(Err(Error::asset(
+                    &format!("{:?}", handle),
+                    "the handle is for a different type of asset",
+                ))),
+            },
+            Some((_, Some(Err(e)))) => Some(Err(e)),
+            _ => None,
+        }
+    }
+
+    pub fn state(&self, handle: Handle<Texture>) -> LoadState {
+        match self.store.lock().unwrap().textures.get(&handle.id) {
+            Some(&Slot::Loading) => LoadState::Loading,
+            Some(&Slot::Loaded(_)) => LoadState::Loaded,
+            Some(&Slot::Failed) | None => LoadState::Failed,
+        }
+    }
+
+    /// The texture, once it's on the GPU. Failed textures give the missing texture.
+    pub fn texture(&self, handle: Handle<Texture>) -> Option<Texture> {
+        let store = self.store.lock().unwrap();
+        match store.textures.get(&handle.id) {
+            Some(&Slot::Loading) => None,
+            Some(&Slot::Loaded(ref texture)) => Some(texture.clone()),
+            Some(&Slot::Failed) | None => store.missing.clone(),
+        }
+    }
+
+    /// Uploads decoded images to the GPU, as many as the budget allows. Does it for
+    /// every share, so call it on one of them per frame. Has to be called on the render thread.
+    pub fn upload(&mut self, factory: &mut graphics::Factory) -> () {
+        let mut store = self.store.lock().unwrap();
+        let store = &mut *store;
+
+        let changed = match store.watcher {
+            Some(ref mut watcher) => watcher.poll(),
+            None => Vec::new(),
+        };
+        for file in changed {
+            let path = match store.watched.get(&file) {
+                Some(path) => path.clone(),
+                None => continue,
+            };
+            if let Some(&id) = store.by_path.get(&path) {
+                println!("Reloading {}", path);
+                self.decode(id, &path);
+            }
+        }
+
+        {
+            let mut decoded = self.decoded.lock().unwrap();
+            store.pending.extend(decoded.drain(..));
+        }
+
+        let mut uploaded = 0;
+        while uploaded < store.upload_budget {
+            let (id, path, result) = match store.pending.pop_front() {
+                Some(decoded) => decoded,
+                None => break,
+            };
+
+            if !store.textures.contains_key(&id) {
+                // Unloaded while it was being decoded
+                continue;
+            }
+
+            let texture = result.and_then(|img| {
+                uploaded += img.len();
+                let (width, height) = img.dimensions();
+                texture::Builder::new()
+                    .with_dimensions(width, height)
+                    .from_pixels(img.into_raw())
+                    .build(factory)
+            });
+
+            let slot = match (texture, store.textures.remove(&id)) {
+                (Ok(texture), Some(Slot::Loaded(old))) => {
+                    if let Some(users) = store.users.get(&id) {
+                        for user in users {
+                            store
+                                .reloaded
+                                .entry(*user)
+                                .or_insert_with(Vec::new)
+                                .push((old.id(), texture.clone()));
+                        }
+                    }
+                    Slot::Loaded(texture)
+                }
+                (Ok(texture), _) => Slot::Loaded(texture),
+                (Err(e), Some(Slot::Loaded(old))) => {
+                    println!("Failed to reload {}, keeping the old one: {}", path, e);
+                    Slot::Loaded(old)
+                }
+                (Err(e), _) => {
+                    println!("Failed to load {}, using the missing texture: {}", path, e);
+                    if store.missing.is_none() {
+                        store.missing =
+                            Some(texture::missing_texture(factory).expect("Failed to create the missing texture"));
+                    }
+                    Slot::Failed
+                }
+            };
+            store.textures.insert(id, slot);
+        }
+    }
+
+    /// Lets go of every texture this server loaded. They are dropped once no other
+    /// share uses them, and nothing else holds a copy. Handles from before count as
+    /// failed after that. Whatever `load` is still loading for it is thrown away.
+    pub fn unload_all(&mut self) -> () {
+        let mut store = self.store.lock().unwrap();
+        let store = &mut *store;
+
+        let mut unused = Vec::new();
+        for (&id, users) in store.users.iter_mut() {
+            if users.remove(&self.id) && users.is_empty() {
+                unused.push(id);
+            }
+        }
+        store.reloaded.remove(&self.id);
+        let id = self.id;
+        self.loaded.lock().unwrap().retain(|_, &mut (server, _)| server != id);
+        if unused.is_empty() {
+            return;
+        }
+
+        println!("Unloading {} textures", unused.len());
+        for id in unused {
+            store.users.remove(&id);
+            store.textures.remove(&id);
+        }
+        let textures = &store.textures;
+        store.by_path.retain(|_, id| textures.contains_key(id));
+        let by_path = &store.by_path;
+        store.watched.retain(|_, path| by_path.contains_key(path));
+    }
+
+    /// How many textures this server is still waiting for
+    pub fn loading(&self) -> usize {
+        let store = self.store.lock().unwrap();
+        store
+            .users
+            .iter()
+            .filter(|&(id, users)| {
+                users.contains(&self.id) && match store.textures.get(id) {
+                    Some(&Slot::Loading) => true,
+                    _ => false,
+                }
+            })
+            .count()
+    }
+}
+
+impl Drop for AssetServer {
+    fn drop(&mut self) {
+        self.unload_all();
+    }
+}
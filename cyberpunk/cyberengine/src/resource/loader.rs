@@ -0,0 +1,152 @@
+//! Turning bytes into assets
+//!
+//! Loaders are picked by file extension. The built-in ones cover images, Tiled maps
+//! and tilesets, TOML, shaders and audio. Games can register their own, or replace
+//! the built-in ones, with `AssetRegistry::with_loader`.
+
+use std::any::Any;
+use std::str;
+
+use image;
+use toml;
+
+use error::{Error, Result};
+use resource::AssetRegistry;
+use resource::tilemap::{self, Tilemap, Tileset};
+
+/// Loaded assets are boxed, `AssetRegistry::load` downcasts them back
+pub type BoxedAsset = Box<Any + Send>;
+
+pub trait AssetLoader: Send + Sync {
+    /// File extensions this loader handles, lowercase and without the dot
+    fn extensions(&self) -> &[&'static str];
+
+    /// Turns the bytes of `path` into an asset. `registry` is there for assets that
+    /// refer to other files, like maps with external tilesets.
+    fn load(&self, path: &str, bytes: Vec<u8>, registry: &AssetRegistry) -> Result<BoxedAsset>;
+}
+
+/// Shader source, compiled by whoever uses it
+#[derive(Debug, Clone)]
+pub struct Shader {
+    pub source: Vec<u8>,
+}
+
+/// Audio file, still encoded. There is no audio backend yet, so this is just the bytes.
+#[derive(Debug, Clone)]
+pub struct Audio {
+    /// The file extension, like "ogg"
+    pub format: String,
+    pub bytes: Vec<u8>,
+}
+
+fn utf8<'a>(path: &str, bytes: &'a [u8]) -> Result<&'a str> {
+    str::from_utf8(bytes).map_err(|e| Error::asset(path, format!("not UTF-8: {}", e)))
+}
+
+/// Images, decoded into RGBA pixels (`image::RgbaImage`)
+pub struct ImageLoader;
+
+impl AssetLoader for ImageLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["png", "jpg", "jpeg", "bmp", "gif", "tga"]
+    }
+
+    fn load(&self, path: &str, bytes: Vec<u8>, _: &AssetRegistry) -> Result<BoxedAsset> {
+        match image::load_from_memory(&bytes) {
+            Ok(img) => Ok(Box::new(img.to_rgba())),
+            Err(error) => Err(Error::Image {
+                path: path.to_owned(),
+                error: error,
+            }),
+        }
+    }
+}
+
+/// Tiled maps (`Tilemap`), with their external tilesets resolved through the registry.
+/// Textures are not loaded, that needs the GPU.
+pub struct TilemapLoader;
+
+impl AssetLoader for TilemapLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["tmx", "json"]
+    }
+
+    fn load(&self, path: &str, bytes: Vec<u8>, registry: &AssetRegistry) -> Result<BoxedAsset> {
+        let mut map = Tilemap::parse(path, utf8(path, &bytes)?)?;
+        map.resolve_tilesets_with(|tileset| registry.read_string(tileset))?;
+        Ok(Box::new(map))
+    }
+}
+
+/// External Tiled tilesets (`Tileset`). JSON tilesets look just like JSON maps from
+/// the outside, so only .tsx is registered by default.
+pub struct TilesetLoader;
+
+impl AssetLoader for TilesetLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["tsx"]
+    }
+
+    fn load(&self, path: &str, bytes: Vec<u8>, _: &AssetRegistry) -> Result<BoxedAsset> {
+        let tileset: Tileset = tilemap::parse_tileset(path, utf8(path, &bytes)?)?;
+        Ok(Box::new(tileset))
+    }
+}
+
+/// TOML files (`toml::Value`)
+pub struct TomlLoader;
+
+impl AssetLoader for TomlLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["toml"]
+    }
+
+    fn load(&self, path: &str, bytes: Vec<u8>, _: &AssetRegistry) -> Result<BoxedAsset> {
+        match utf8(path, &bytes)?.parse::<toml::Value>() {
+            Ok(value) => Ok(Box::new(value)),
+            Err(e) => Err(Error::asset(path, e.to_string())),
+        }
+    }
+}
+
+/// GLSL sources (`Shader`)
+pub struct ShaderLoader;
+
+impl AssetLoader for ShaderLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["glslv", "glslf", "vert", "frag"]
+    }
+
+    fn load(&self, _: &str, bytes: Vec<u8>, _: &AssetRegistry) -> Result<BoxedAsset> {
+        Ok(Box::new(Shader { source: bytes }))
+    }
+}
+
+/// Sound files (`Audio`)
+pub struct AudioLoader;
+
+impl AssetLoader for AudioLoader {
+    fn extensions(&self) -> &[&'static str] {
+        &["ogg", "wav", "flac", "mp3"]
+    }
+
+    fn load(&self, path: &str, bytes: Vec<u8>, _: &AssetRegistry) -> Result<BoxedAsset> {
+        Ok(Box::new(Audio {
+            format: extension(path),
+            bytes: bytes,
+        }))
+    }
+}
+
+/// Lowercase extension of an asset path, empty if it has none
+pub fn extension(path: &str) -> String {
+    let file = match path.rfind('/') {
+        Some(i) => &path[i + 1..],
+        None => path,
+    };
+    match file.rfind('.') {
+        Some(i) => file[i + 1..].to_lowercase(),
+        None => String::new(),
+    }
+}
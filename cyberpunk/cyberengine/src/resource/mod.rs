@@ -1,7 +1,191 @@
 //! For loading stuff from places.
+//!
+//! Assets are read from AssetSources (directories, files embedded in the binary,
+//! archives) mounted into an AssetRegistry, and turned into something useful by the
+//! AssetLoader registered for the file extension. Sources with a higher priority are
+//! searched first, so a mod directory mounted over the base assets can replace any file.
+//!
+//! ```ignore
+//! let registry = AssetRegistry::new()
+//!     .with_source(DirectorySource::new("assets"), 0)
+//!     .with_source(DirectorySource::new("mods/neon"), 10);
+//! let map: Tilemap = registry.load("maps/offices.json")?;
+//! ```
+//!
+//! For shipping, pack the assets into one archive with the `cyberpak` packer and
+//! mount it with `PakSource`.
 
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use error::{Error, Result};
+
+pub mod assets;
+pub mod loader;
+pub mod source;
+pub mod tilemap;
+pub mod tmx;
+pub mod watch;
+
+use self::loader::AssetLoader;
+use self::source::{AssetSource, DirectorySource, PakSource};
+
+/// Archive next to the executable that `AssetRegistry::with_defaults` mounts, made with
+/// `cyberpak pack assets assets.pak`
+pub const DEFAULT_ARCHIVE: &'static str = "assets.pak";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Identifier {
     Image(String),
 }
 
-//pub mod tilemap;
+struct Mount {
+    priority: i32,
+    source: Box<AssetSource>,
+}
+
+/// Mounted sources and registered loaders
+pub struct AssetRegistry {
+    /// Highest priority first
+    mounts: Vec<Mount>,
+    /// By extension
+    loaders: HashMap<String, Arc<AssetLoader>>,
+}
+
+impl AssetRegistry {
+    /// All the built-in loaders, but no sources
+    pub fn new() -> AssetRegistry {
+        AssetRegistry {
+            mounts: Vec::new(),
+            loaders: HashMap::new(),
+        }.with_loader(loader::ImageLoader)
+            .with_loader(loader::TilemapLoader)
+            .with_loader(loader::TilesetLoader)
+            .with_loader(loader::TomlLoader)
+            .with_loader(loader::ShaderLoader)
+            .with_loader(loader::AudioLoader)
+    }
+
+    /// The built-in loaders, the working directory at priority 0, and `DEFAULT_ARCHIVE`
+    /// under it if there is one. Loose files win, so they can be edited without repacking.
+    pub fn with_defaults() -> AssetRegistry {
+        let mut registry = AssetRegistry::new().with_source(DirectorySource::new("."), 0);
+        if Path::new(DEFAULT_ARCHIVE).is_file() {
+            match PakSource::open(DEFAULT_ARCHIVE) {
+                Ok(source) => registry.mount(Box::new(source), -10),
+                Err(e) => println!("Failed to mount {}: {}", DEFAULT_ARCHIVE, e),
+            }
+        }
+        registry
+    }
+
+    pub fn with_source<S: AssetSource + 'static>(mut self, source: S, priority: i32) -> AssetRegistry {
+        self.mount(Box::new(source), priority);
+        self
+    }
+
+    /// Mounts a source. Sources with a higher priority are searched first, and
+    /// with the same priority the one mounted last wins.
+    pub fn mount(&mut self, source: Box<AssetSource>, priority: i32) -> () {
+        println!("Mounting {} (priority {})", source.name(), priority);
+        let index = self.mounts
+            .iter()
+            .position(|mount| mount.priority <= priority)
+            .unwrap_or(self.mounts.len());
+        self.mounts.insert(
+            index,
+            Mount {
+                priority: priority,
+                source: source,
+            },
+        );
+    }
+
+    /// Registers a loader for its extensions, replacing whatever was there before
+    pub fn with_loader<L: AssetLoader + 'static>(mut self, loader: L) -> AssetRegistry {
+        self.register_loader(Arc::new(loader));
+        self
+    }
+
+    pub fn register_loader(&mut self, loader: Arc<AssetLoader>) -> () {
+        for extension in loader.extensions() {
+            self.loaders.insert(extension.to_string(), loader.clone());
+        }
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.source_of(path).is_some()
+    }
+
+    /// Name of the source `path` would be read from
+    pub fn source_of(&self, path: &str) -> Option<String> {
+        let path = normalize(path);
+        self.mounts
+            .iter()
+            .find(|mount| mount.source.exists(&path))
+            .map(|mount| mount.source.name())
+    }
+
+    /// Where `path` is on disk, if the source it would be read from has plain files
+    pub fn file_path(&self, path: &str) -> Option<PathBuf> {
+        let path = normalize(path);
+        match self.mounts.iter().find(|mount| mount.source.exists(&path)) {
+            Some(mount) => mount.source.file_path(&path),
+            None => None,
+        }
+    }
+
+    /// Reads a file from the first source that has it
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let path = normalize(path);
+        for mount in &self.mounts {
+            match mount.source.read(&path) {
+                Ok(bytes) => return Ok(bytes),
+                Err(ref e) if source::is_not_found(e) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(source::not_found(&path))
+    }
+
+    pub fn read_string(&self, path: &str) -> Result<String> {
+        match String::from_utf8(self.read(path)?) {
+            Ok(contents) => Ok(contents),
+            Err(e) => Err(Error::asset(path, format!("not UTF-8: {}", e))),
+        }
+    }
+
+    /// Reads and loads an asset with the loader for its extension.
+    /// `T` has to be what the loader makes, like `image::RgbaImage` for images.
+    pub fn load<T: Any>(&self, path: &str) -> Result<T> {
+        let path = normalize(path);
+        let extension = loader::extension(&path);
+        let loader = match self.loaders.get(&extension) {
+            Some(loader) => loader.clone(),
+            None => return Err(Error::asset(&path, format!("no loader for {:?} files", extension))),
+        };
+
+        let bytes = self.read(&path)?;
+        match loader.load(&path, bytes, self)?.downcast::<T>() {
+            Ok(asset) => Ok(*asset),
+            Err(_) => Err(Error::asset(&path, "the loader made a different type of asset")),
+        }
+    }
+}
+
+/// Forward slashes, no `.` or `..` segments
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split(|c| c == '/' || c == '\\') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
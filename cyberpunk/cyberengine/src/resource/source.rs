@@ -0,0 +1,243 @@
+//! Places assets are read from
+//!
+//! Paths are relative and use `/` as the separator, no matter what the platform is.
+//! Sources are mounted into an AssetRegistry, see `resource`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use cyberpak;
+use zip;
+
+use error::{Error, Result};
+
+pub trait AssetSource: Send + Sync {
+    /// Shown in log messages
+    fn name(&self) -> String;
+
+    fn exists(&self, path: &str) -> bool;
+
+    /// Reads the whole file. Missing files are `Error::Io` with `io::ErrorKind::NotFound`.
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// Where the file is on disk, if it is a plain file that can be watched for changes
+    fn file_path(&self, path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub fn not_found(path: &str) -> Error {
+    Error::Io {
+        path: path.to_owned(),
+        error: io::Error::new(io::ErrorKind::NotFound, "no such asset"),
+    }
+}
+
+/// Is the error just a missing file, so the next source should be tried
+pub fn is_not_found(error: &Error) -> bool {
+    match *error {
+        Error::Io { ref error, .. } => error.kind() == io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+/// Loose files in a directory
+pub struct DirectorySource {
+    root: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new<P: AsRef<Path>>(root: P) -> DirectorySource {
+        DirectorySource {
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    fn full_path(&self, path: &str) -> PathBuf {
+        let mut full = self.root.clone();
+        for part in path.split('/') {
+            full.push(part);
+        }
+        full
+    }
+}
+
+impl AssetSource for DirectorySource {
+    fn name(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.full_path(path).is_file()
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let io_error = |error| Error::Io {
+            path: path.to_owned(),
+            error: error,
+        };
+        let mut f = File::open(self.full_path(path)).map_err(&io_error)?;
+        let mut bytes = Vec::new();
+        f.read_to_end(&mut bytes).map_err(&io_error)?;
+        Ok(bytes)
+    }
+
+    fn file_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.full_path(path))
+    }
+}
+
+/// Files compiled into the binary:
+///
+/// ```ignore
+/// EmbeddedSource::new()
+///     .with_file("shaders/texture_400.glslv", include_bytes!("../shaders/texture_400.glslv"))
+/// ```
+pub struct EmbeddedSource {
+    name: String,
+    files: HashMap<String, &'static [u8]>,
+}
+
+impl EmbeddedSource {
+    pub fn new() -> EmbeddedSource {
+        EmbeddedSource {
+            name: "<embedded>".to_owned(),
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> EmbeddedSource {
+        self.name = name.to_owned();
+        self
+    }
+
+    pub fn with_file(mut self, path: &str, bytes: &'static [u8]) -> EmbeddedSource {
+        self.files.insert(path.to_owned(), bytes);
+        self
+    }
+}
+
+impl AssetSource for EmbeddedSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        match self.files.get(path) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Err(not_found(path)),
+        }
+    }
+}
+
+/// Files inside a zip archive (.zip, or .pak if you want it to look more gamey)
+pub struct ZipSource {
+    name: String,
+    // Reading from the archive needs &mut
+    archive: Mutex<zip::ZipArchive<File>>,
+}
+
+impl ZipSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ZipSource> {
+        let name = path.as_ref().display().to_string();
+        let file = File::open(path.as_ref()).map_err(|error| Error::Io {
+            path: name.clone(),
+            error: error,
+        })?;
+        let archive = zip::ZipArchive::new(file).map_err(|error| zip_error(&name, error))?;
+        Ok(ZipSource {
+            name: name,
+            archive: Mutex::new(archive),
+        })
+    }
+}
+
+impl AssetSource for ZipSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let mut archive = self.archive.lock().unwrap();
+        let found = archive.by_name(path).is_ok();
+        found
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_name(path).map_err(|error| zip_error(path, error))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(|error| Error::Io {
+            path: path.to_owned(),
+            error: error,
+        })?;
+        Ok(bytes)
+    }
+}
+
+/// Files inside a cyberpak archive, made with the `cyberpak` packer. Checksums are
+/// checked on every read, so a broken archive gives errors instead of garbage.
+pub struct PakSource {
+    name: String,
+    archive: Mutex<cyberpak::Archive<BufReader<File>>>,
+}
+
+impl PakSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PakSource> {
+        let name = path.as_ref().display().to_string();
+        let io_error = |error| Error::Io {
+            path: name.clone(),
+            error: error,
+        };
+        let file = File::open(path.as_ref()).map_err(&io_error)?;
+        let archive = cyberpak::Archive::open(BufReader::new(file)).map_err(&io_error)?;
+        println!("{}: {} files", name, archive.entries().len());
+        Ok(PakSource {
+            name: name,
+            archive: Mutex::new(archive),
+        })
+    }
+}
+
+impl AssetSource for PakSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.archive.lock().unwrap().entry(path).is_some()
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.archive
+            .lock()
+            .unwrap()
+            .read(path)
+            .map_err(|error| Error::Io {
+                path: format!("{}:{}", self.name, path),
+                error: error,
+            })
+    }
+}
+
+fn zip_error(path: &str, error: zip::result::ZipError) -> Error {
+    use zip::result::ZipError;
+
+    let error = match error {
+        ZipError::Io(error) => error,
+        ZipError::FileNotFound => return not_found(path),
+        other => io::Error::new(io::ErrorKind::InvalidData, format!("{}", other)),
+    };
+    Error::Io {
+        path: path.to_owned(),
+        error: error,
+    }
+}
@@ -1,41 +1,225 @@
 //! Complex Tiled Implementation
 //!
-//! The Tilemap is loaded from Tiled JSON file. (unless someone implements some
-//! other loaders Kappa). It should handle loading all the required stuff
-//! (Tilesets, Scripts?), and rendering itself.
+//! The Tilemap is loaded from Tiled JSON or TMX files (see `resource::tmx`), the
+//! format is picked from the file extension. It should handle loading all the
+//! required stuff (Tilesets, Scripts?), and rendering itself.
+//!
+//! Maps are usually loaded through an AssetRegistry (see `resource::loader`), so
+//! external tilesets and tileset images come from the mounted sources too, and mods
+//! can replace them. `parse_file` reads straight from the file system.
 //!
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
+use std::time::Duration;
 use std::path::{Path, PathBuf};
 use serde_json;
 use serde_json::Value;
 use serde_json::map::Map;
 
-use genmesh::{Quad, EmitTriangles, MapVertex, Triangulate, MapToVertices, Vertices};
-use genmesh::generators::Plane;
+use gfx;
 
+use error::Error;
 use graphics;
+use graphics::batch::push_quad;
+use graphics::texture::Vertex;
+use image;
+use resource::AssetRegistry;
+use resource::assets::{AssetServer, Handle};
+use resource::tmx;
 
-gfx_defines! {
-    vertex TileVertex {
-        pos: [f32; 2] = "a_Pos",
-        uv: [f32; 2]  = "a_Uv",
-        color: [f32; 3] = "a_Color",
-    }
+// Tiled stores the flip flags of a tile in the highest bits of the GID
+const FLIPPED_HORIZONTALLY: u32 = 0x80000000;
+const FLIPPED_VERTICALLY: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY: u32 = 0x20000000;
+const GID_MASK: u32 = !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct MapObject {}
+/// Text of a text object, and how it should look
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TextObject {
+    pub text: String,
+    pub wrap: bool,
+    pub pixelsize: i32,
+    pub color: String,
+    pub fontfamily: String,
+    pub halign: String,
+    pub valign: String,
+}
+
+/// What kind of a thing a MapObject is. Sizes are in pixels.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ObjectShape {
+    Rectangle { width: f64, height: f64 },
+    Ellipse { width: f64, height: f64 },
+    Point,
+    /// Points are relative to the object position
+    Polygon(Vec<Point>),
+    /// Points are relative to the object position
+    Polyline(Vec<Point>),
+    /// Tile objects are positioned by their bottom left corner (in Tiled coordinates)
+    Tile { gid: u32, width: f64, height: f64 },
+    Text { width: f64, height: f64, text: TextObject },
+}
+
+/// An object placed in an object layer. Position is in Tiled pixel coordinates (Y down).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MapObject {
+    pub id: i32,
+    pub name: String,
+    pub objecttype: String,
+    pub x: f64,
+    pub y: f64,
+    /// Clockwise, in degrees
+    pub rotation: f64,
+    pub visible: bool,
+    pub shape: ObjectShape,
+    pub properties: Map<String, Value>,
+}
+
+/*
+    id          int     Incremental id - unique across all objects
+    name        string  String assigned to name field in editor
+    type        string  String assigned to type field in editor
+    x           double  x coordinate in pixels
+    y           double  y coordinate in pixels
+    width       double  Width in pixels. Ignored if using a gid.
+    height      double  Height in pixels. Ignored if using a gid.
+    rotation    double  Angle in degrees clockwise
+    visible     bool    Whether object is shown in editor.
+    gid         int     GID, only if object comes from a Tilemap
+    ellipse     bool    Used to mark an object as an ellipse
+    point       bool    Used to mark an object as a point
+    polygon     array   A list of x,y coordinates in pixels
+    polyline    array   A list of x,y coordinates in pixels
+    text        object  String key-value pairs
+    properties  object  String key-value pairs
+*/
+#[derive(Debug, Deserialize)]
+struct RawMapObject {
+    id: i32,
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type", default)]
+    objecttype: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    #[serde(default)]
+    rotation: f64,
+    visible: Option<bool>,
+    gid: Option<u32>,
+    ellipse: Option<bool>,
+    point: Option<bool>,
+    polygon: Option<Vec<Point>>,
+    polyline: Option<Vec<Point>>,
+    text: Option<Map<String, Value>>,
+    properties: Option<Map<String, Value>>,
+}
+
+impl From<RawMapObject> for MapObject {
+    fn from(raw: RawMapObject) -> MapObject {
+        let (width, height) = (raw.width, raw.height);
+        let shape = if let Some(gid) = raw.gid {
+            ObjectShape::Tile {
+                gid: gid,
+                width: width,
+                height: height,
+            }
+        } else if let Some(points) = raw.polygon {
+            ObjectShape::Polygon(points)
+        } else if let Some(points) = raw.polyline {
+            ObjectShape::Polyline(points)
+        } else if let Some(text) = raw.text {
+            let string = |key: &str, default: &str| match text.get(key) {
+                Some(&Value::String(ref s)) => s.clone(),
+                _ => default.to_owned(),
+            };
+            ObjectShape::Text {
+                width: width,
+                height: height,
+                text: TextObject {
+                    text: string("text", ""),
+                    wrap: text.get("wrap").and_then(Value::as_bool).unwrap_or(false),
+                    pixelsize: text.get("pixelsize").and_then(Value::as_i64).unwrap_or(16) as i32,
+                    color: string("color", "#000000"),
+                    fontfamily: string("fontfamily", "sans-serif"),
+                    halign: string("halign", "left"),
+                    valign: string("valign", "top"),
+                },
+            }
+        } else if raw.point.unwrap_or(false) {
+            ObjectShape::Point
+        } else if raw.ellipse.unwrap_or(false) {
+            ObjectShape::Ellipse {
+                width: width,
+                height: height,
+            }
+        } else {
+            ObjectShape::Rectangle {
+                width: width,
+                height: height,
+            }
+        };
+
+        MapObject {
+            id: raw.id,
+            name: raw.name,
+            objecttype: raw.objecttype,
+            x: raw.x,
+            y: raw.y,
+            rotation: raw.rotation,
+            visible: raw.visible.unwrap_or(true),
+            shape: shape,
+            properties: raw.properties.unwrap_or_else(Map::new),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Terrain {}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum LayerData {
-    TileData(Vec<i32>),
-    ObjectData(Map<String, Value>),
+    TileData(Vec<u32>),
+    ObjectData(Vec<MapObject>),
+}
+
+impl Default for LayerData {
+    fn default() -> LayerData {
+        LayerData::TileData(Vec::new())
+    }
+}
+
+/// Vertices of all the tiles in a layer that use the same tileset
+#[derive(Debug)]
+pub struct LayerMesh {
+    tileset: usize,
+    vbuf: gfx::handle::Buffer<graphics::Resources, Vertex>,
+    slice: gfx::Slice<graphics::Resources>,
+    animated: Vec<AnimatedTile>,
+}
+
+/// A tile in a LayerMesh whose UVs change over time
+#[derive(Debug)]
+struct AnimatedTile {
+    /// Index of the first vertex of the tile in the mesh
+    vertex: usize,
+    /// GID of the tile in the layer, with the flip flags
+    gid: u32,
+    positions: [[f32; 2]; 4],
+    /// Frame currently in the vertex buffer
+    frame: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -55,98 +239,275 @@ pub enum LayerData {
     draworder   string  “topdown” (default) or “index”. objectgroup only.
 */
 pub struct Layer {
+    #[serde(default)]
     pub height: i32,
+    #[serde(default)]
     pub width: i32,
     pub name: String,
     #[serde(rename = "type")]
     pub layertype: String,
     pub visible: bool,
+    #[serde(default)]
     pub x: i32,
+    #[serde(default)]
     pub y: i32,
+    #[serde(skip_deserializing)]
     pub data: LayerData,
+    #[serde(rename = "data", skip_serializing)]
+    _data: Option<Vec<u32>>,
+    #[serde(rename = "objects", skip_serializing)]
+    _objects: Option<Vec<RawMapObject>>,
     pub properties: Option<Map<String, Value>>,
     pub opacity: f64,
     #[serde(skip_serializing, skip_deserializing)]
-    _plane: Vec<graphics::texture::Vertex>,
+    meshes: Vec<LayerMesh>,
+    /// Opacity the meshes were built with, None if they need to be (re)built
+    #[serde(skip_serializing, skip_deserializing)]
+    mesh_opacity: Option<f64>,
 }
 
 impl Layer {
-    fn setup_plane(&mut self) -> () {
-        use graphics::texture::Vertex;
-        let vertex_data: Vec<Vertex> = Plane::subdivide(self.width as usize, self.height as usize)
-            .vertex(|v| {
-                Vertex {
-                    pos: [v.pos[0], v.pos[1]],
-                    uv: [0., 0.],
-                    color: [1., 0., 1.],
+    /// Moves the tiles or objects into `data`, depending on what kind of layer this is.
+    fn resolve_data(&mut self) -> Result<(), String> {
+        self.data = match (self._data.take(), self._objects.take()) {
+            (Some(tiles), _) => {
+                // The tiles are laid out in rows of `width`, so there has to be a row
+                if self.width <= 0 || self.height <= 0 {
+                    return Err(format!(
+                        "tile layer {} is {}x{} tiles",
+                        self.name, self.width, self.height
+                    ));
                 }
-            })
-            .map(|Quad {
-                 x: v0,
-                 y: v1,
-                 z: v2,
-                 w: v3,
-             }| {
-                Quad::new(
-                    Vertex {
-                        pos: v0.pos,
-                        uv: [0., 64. / 1280.],
-                        color: v0.color,
-                    },
-                    Vertex {
-                        pos: v1.pos,
-                        uv: [64. / 1728., 64. / 1280.],
-                        color: v1.color,
-                    },
-                    Vertex {
-                        pos: v2.pos,
-                        uv: [64. / 1728., 0.],
-                        color: v2.color,
-                    },
-                    Vertex {
-                        pos: v3.pos,
-                        uv: [0., 0.],
-                        color: v3.color,
+                LayerData::TileData(tiles)
+            }
+            (None, Some(objects)) => {
+                LayerData::ObjectData(objects.into_iter().map(MapObject::from).collect())
+            }
+            (None, None) => {
+                println!("Layer {} ({}) is not supported, ignoring it", self.name, self.layertype);
+                LayerData::ObjectData(Vec::new())
+            }
+        };
+        Ok(())
+    }
+
+    /// Builds one vertex buffer per tileset used by this layer.
+    fn build_meshes(
+        &mut self,
+        grid: &Grid,
+        tilesets: &[Tileset],
+        renderer: &mut graphics::Renderer,
+    ) -> () {
+        let mut vertices: Vec<Vec<Vertex>> = tilesets.iter().map(|_| Vec::new()).collect();
+        let mut animated: Vec<Vec<AnimatedTile>> = tilesets.iter().map(|_| Vec::new()).collect();
+        let color = [1., 1., 1., self.opacity as f32];
+
+        if let LayerData::TileData(ref data) = self.data {
+            for (i, &gid) in data.iter().enumerate() {
+                if gid & GID_MASK == 0 {
+                    continue;
+                }
+
+                let index = match tileset_for_gid(tilesets, gid) {
+                    Some(index) => index,
+                    None => {
+                        println!("No tileset for GID {} in layer {}", gid & GID_MASK, self.name);
+                        continue;
+                    }
+                };
+                let tileset = &tilesets[index];
+
+                let (x, y) = (i as i32 % self.width, i as i32 / self.width);
+                let (w, h) = (
+                    tileset.tilewidth.unwrap_or(grid.tilewidth) as f32,
+                    tileset.tileheight.unwrap_or(grid.tileheight) as f32,
+                );
+
+                // World Y goes up, Tiled Y goes down. Tiles bigger than the grid grow
+                // upwards from the bottom of their cell, like in Tiled. Isometric
+                // tiles are centered on the cell, the rest stick to the left side.
+                let (cell_left, cell_top) = grid.cell_origin(x, y);
+                let left = match grid.orientation {
+                    Orientation::Isometric => cell_left + (grid.tilewidth as f32 - w) / 2.,
+                    _ => cell_left,
+                };
+                let bottom = -(cell_top + grid.tileheight as f32);
+                let positions = [
+                    [left, bottom + h],
+                    [left + w, bottom + h],
+                    [left + w, bottom],
+                    [left, bottom],
+                ];
+
+                if tileset.animation(gid).is_some() {
+                    animated[index].push(AnimatedTile {
+                        vertex: vertices[index].len(),
+                        gid: gid,
+                        positions: positions,
+                        // Make sure the first animate() writes the right frame
+                        frame: usize::max_value(),
+                    });
+                }
+                push_quad(&mut vertices[index], positions, tileset.tile_uvs(gid), color);
+            }
+        }
+
+        self.meshes = vertices
+            .into_iter()
+            .zip(animated.into_iter())
+            .enumerate()
+            .filter(|&(_, (ref vertices, _))| !vertices.is_empty())
+            .map(|(tileset, (vertices, animated))| {
+                // Dynamic, so animated tiles can be updated without rebuilding everything
+                let vbuf = graphics::create_dynamic_vertex_buffer(&mut renderer.factory, vertices.len());
+                renderer.encoder.update_buffer(&vbuf, &vertices, 0).unwrap();
+                LayerMesh {
+                    tileset: tileset,
+                    vbuf: vbuf,
+                    slice: gfx::Slice {
+                        start: 0,
+                        end: vertices.len() as u32,
+                        base_vertex: 0,
+                        instances: None,
+                        buffer: gfx::IndexBuffer::Auto,
                     },
-                )
+                    animated: animated,
+                }
             })
-            .triangulate()
-            .vertices()
             .collect();
+        self.mesh_opacity = Some(self.opacity);
     }
 
-    #[allow(dead_code)]
-    fn get_tile(&self, x: i32, y: i32) -> i32 {
-        if self.layertype != "tilelayer" {
-            panic!("Attempt to get a tile from non-tile layer {:?}", self);
-        }
+    /// Updates the UVs of animated tiles whose frame has changed.
+    fn animate(&mut self, tilesets: &[Tileset], elapsed: Duration, renderer: &mut graphics::Renderer) -> () {
+        let elapsed_ms = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+        let color = [1., 1., 1., self.opacity as f32];
 
-        if x < 0 || x > self.width {
-            panic!("X coordinate out of bounds {:?}", self)
-        }
+        for mesh in &mut self.meshes {
+            let tileset = &tilesets[mesh.tileset];
+            for tile in &mut mesh.animated {
+                let frames = match tileset.animation(tile.gid) {
+                    Some(frames) => frames,
+                    None => continue,
+                };
+                let frame = frame_at(frames, elapsed_ms);
+                if frame == tile.frame {
+                    continue;
+                }
+                tile.frame = frame;
 
+                // Frames refer to tiles in the same tileset, keep the flip flags of the original
+                let gid = (tileset.firstgid as u32 + frames[frame].tileid) | (tile.gid & !GID_MASK);
+                let mut vertices = Vec::with_capacity(6);
+                push_quad(&mut vertices, tile.positions, tileset.tile_uvs(gid), color);
+                renderer.encoder.update_buffer(&mesh.vbuf, &vertices, tile.vertex).unwrap();
+            }
+        }
+    }
 
-        if y < 0 || y > self.height {
-            panic!("Y coordinate out of bounds {:?}", self)
+    /// GID of the tile at (x, y), flip flags included. 0 means there is no tile.
+    /// None if the coordinates are outside the layer, or this is not a tile layer.
+    pub fn get_tile(&self, x: i32, y: i32) -> Option<u32> {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return None;
         }
 
         match self.data {
-            LayerData::TileData(ref data) => data[(y * self.width + x) as usize],
-            _ => panic!("Not a Tile layer?? {:?}", self),
+            LayerData::TileData(ref data) => data.get((y * self.width + x) as usize).cloned(),
+            LayerData::ObjectData(_) => None,
         }
     }
 
-    fn draw(&self, factory: &mut graphics::Factory) -> () {
+    fn draw(&self, tilesets: &[Tileset], renderer: &mut graphics::Renderer) -> () {
         match self.data {
-            LayerData::TileData(_) => self.draw_tilelayer(factory),
-            LayerData::ObjectData(_) => self.draw_objectlayer(factory),
+            LayerData::TileData(_) => self.draw_tilelayer(tilesets, renderer),
+            // Objects are turned into entities (see systems::tilemap::ObjectSpawner),
+            // so whatever systems handle those entities draw them.
+            LayerData::ObjectData(_) => {}
+        }
+    }
+
+    fn draw_tilelayer(&self, tilesets: &[Tileset], renderer: &mut graphics::Renderer) -> () {
+        for mesh in &self.meshes {
+            let view = match tilesets[mesh.tileset].get_texture() {
+                Ok(texture) => texture.clone_view(),
+                Err(e) => {
+                    println!("Can't draw layer {}: {}", self.name, e);
+                    continue;
+                }
+            };
+            renderer.draw_vertices(view, &mesh.vbuf, &mesh.slice, (0., 0.));
         }
     }
+}
+
+/// Which frame of an animation should be shown, `elapsed_ms` after the start.
+fn frame_at(frames: &[Frame], elapsed_ms: u64) -> usize {
+    let total: u64 = frames.iter().map(|f| f.duration as u64).sum();
+    if total == 0 {
+        return 0;
+    }
+
+    let mut time = elapsed_ms % total;
+    for (i, frame) in frames.iter().enumerate() {
+        if time < frame.duration as u64 {
+            return i;
+        }
+        time -= frame.duration as u64;
+    }
+    frames.len() - 1
+}
 
-    fn draw_tilelayer(&self, factory: &mut graphics::Factory) -> () {}
+/// Finds the tileset a GID belongs to, assuming the tilesets are in firstgid order.
+fn tileset_for_gid(tilesets: &[Tileset], gid: u32) -> Option<usize> {
+    let gid = (gid & GID_MASK) as i32;
+    tilesets.iter().rposition(|ts| ts.firstgid <= gid)
+}
+
+/*
+    tileid      int     Local tile id of the frame
+    duration    int     How long the frame is shown, in milliseconds
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub tileid: u32,
+    pub duration: u32,
+}
+
+/*
+    animation   array   Array of Frames (optional)
+    type        string  The type of the tile (optional)
+    properties  object  String key-value pairs (optional, newer Tiled versions)
+    objectgroup object  Layer with type objectgroup, the collision shapes (optional)
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileData {
+    pub animation: Option<Vec<Frame>>,
+    #[serde(rename = "type")]
+    pub tiletype: Option<String>,
+    pub properties: Option<Map<String, Value>>,
+    /// Collision shapes drawn in Tiled's collision editor, relative to the tile
+    #[serde(skip_deserializing)]
+    pub collision: Vec<MapObject>,
+    #[serde(rename = "objectgroup", skip_serializing)]
+    _objectgroup: Option<Value>,
+}
 
-    fn draw_objectlayer(&self, factory: &mut graphics::Factory) -> () {
-        unimplemented!();
+impl TileData {
+    /// Parses the collision shapes out of the raw objectgroup.
+    fn resolve_collision(&mut self) -> Result<(), String> {
+        let objects = match self._objectgroup.take() {
+            Some(Value::Object(mut group)) => match group.remove("objects") {
+                Some(objects) => objects,
+                None => return Ok(()),
+            },
+            Some(_) => return Err("Invalid objectgroup".to_owned()),
+            None => return Ok(()),
+        };
+        let objects: Vec<RawMapObject> =
+            serde_json::from_value(objects).map_err(|e| format!("Invalid collision object: {}", e))?;
+        self.collision = objects.into_iter().map(MapObject::from).collect();
+        Ok(())
     }
 }
 
@@ -172,6 +533,9 @@ impl Layer {
 pub struct Tileset {
     #[serde(skip_serializing, skip_deserializing)]
     pub root: String,
+    /// The file an external tileset was loaded from
+    #[serde(skip_serializing, skip_deserializing)]
+    pub path: Option<String>,
     pub source: Option<String>,
     #[serde(skip_deserializing)]
     pub firstgid: i32,
@@ -195,31 +559,402 @@ pub struct Tileset {
     terrains: Vec<Terrain>,
     pub columns: Option<i32>,
     pub tilecount: Option<i32>,
-    //tiles: Map<String, Value>,
+    /// Extra data of tiles that have some, by local tile id
+    #[serde(skip_deserializing)]
+    pub tiles: HashMap<u32, TileData>,
+    #[serde(rename = "tiles", skip_serializing)]
+    _tiles: Option<Value>,
+    #[serde(skip_serializing, skip_deserializing)]
+    _texture: Option<graphics::texture::Texture>,
+    /// The image while the AssetServer loads it, see `Tilemap::request_textures`
     #[serde(skip_serializing, skip_deserializing)]
-    _texture: Option<graphics::texture::Texture<graphics::texture::pipe_tex::Meta, TileVertex>>,
+    _texture_handle: Option<Handle<graphics::texture::Texture>>,
 }
 
 impl Tileset {
-    pub fn load_image(&mut self, factory: &mut graphics::Factory) -> () {
+    /// Parses the per-tile data. Tiled < 1.2 saves it as an object with the ids as keys,
+    /// newer versions as an array with the id inside each tile.
+    fn resolve_tiles(&mut self) -> Result<(), String> {
+        let raw = match self._tiles.take() {
+            Some(raw) => raw,
+            None => return Ok(()),
+        };
+
+        let entries: Vec<(String, Value)> = match raw {
+            Value::Object(tiles) => tiles.into_iter().collect(),
+            Value::Array(tiles) => tiles
+                .into_iter()
+                .map(|tile| {
+                    let id = tile.get("id").map(|id| id.to_string()).unwrap_or_default();
+                    (id, tile)
+                })
+                .collect(),
+            _ => return Err(format!("Invalid tiles in tileset {}", self.name)),
+        };
+
+        let mut tiles = HashMap::new();
+        for (id, tile) in entries {
+            let id = id.parse::<u32>()
+                .map_err(|_| format!("Invalid tile id {:?} in tileset {}", id, self.name))?;
+            let mut tile: TileData = serde_json::from_value(tile)
+                .map_err(|e| format!("Invalid tile {} in tileset {}: {}", id, self.name, e))?;
+            tile.resolve_collision()
+                .map_err(|e| format!("Invalid tile {} in tileset {}: {}", id, self.name, e))?;
+            tiles.insert(id, tile);
+        }
+        self.tiles = tiles;
+        Ok(())
+    }
+
+    /// Animation frames of a tile, if it is animated
+    pub fn animation(&self, gid: u32) -> Option<&Vec<Frame>> {
+        match self.tiles.get(&self.local_id(gid)) {
+            Some(&TileData { animation: Some(ref frames), .. }) if !frames.is_empty() => Some(frames),
+            _ => None,
+        }
+    }
+
+    /// A property of a tile. Looks at both the per-tile properties of newer Tiled
+    /// versions and the old `tileproperties`.
+    pub fn tile_property(&self, gid: u32, name: &str) -> Option<&Value> {
+        let id = self.local_id(gid);
+        let property = match self.tiles.get(&id) {
+            Some(&TileData { properties: Some(ref properties), .. }) => properties.get(name),
+            _ => None,
+        };
+        match property {
+            Some(value) => Some(value),
+            None => match self.tileproperties {
+                Some(ref tileproperties) => match tileproperties.get(&id.to_string()) {
+                    Some(&Value::Object(ref properties)) => properties.get(name),
+                    _ => None,
+                },
+                None => None,
+            },
+        }
+    }
+
+    /// Collision shapes of a tile, empty if it has none
+    pub fn collision(&self, gid: u32) -> &[MapObject] {
+        match self.tiles.get(&self.local_id(gid)) {
+            Some(tile) => &tile.collision,
+            None => &[],
+        }
+    }
+
+    /// Id of the tile inside this tileset
+    fn local_id(&self, gid: u32) -> u32 {
+        ((gid & GID_MASK) as i32 - self.firstgid) as u32
+    }
+
+    /// Asset path of the image, relative to the map or tileset file
+    pub fn image_path(&self) -> Result<String, Error> {
         let path: PathBuf = [
             &self.root,
             match self.image {
                 Some(ref img) => img,
-                None => panic!("No image specified {:?}", self),
+                None => return Err(Error::tilemap(&self.name, "no image specified for tileset")),
             },
         ].into_iter()
             .collect();
-        let texture = graphics::texture::Texture::from_file(factory, path.to_str().unwrap());
+        Ok(utf8_path(&self.name, &path)?.to_owned())
+    }
+
+    /// Loads the image right away, see `Tilemap::request_textures` for doing it in the background
+    pub fn load_image(
+        &mut self,
+        registry: &AssetRegistry,
+        factory: &mut graphics::Factory,
+    ) -> Result<(), Error> {
+        let img = registry.load::<image::RgbaImage>(&self.image_path()?)?;
+        let (width, height) = img.dimensions();
+        let texture = graphics::texture::Builder::new()
+            .with_dimensions(width, height)
+            .from_pixels(img.into_raw())
+            .build(factory)?;
         self._texture = Some(texture);
+        Ok(())
     }
 
-    pub fn get_texture(&self) -> Result<&graphics::texture::Texture<graphics::texture::pipe_tex::Meta, TileVertex>, String> {
+    pub fn get_texture(&self) -> Result<&graphics::texture::Texture, Error> {
         match self._texture {
             Some(ref texture) => Ok(texture),
-            None => Err("Texture needs to be loaded first!".to_owned()),
+            None => Err(Error::tilemap(&self.name, "texture needs to be loaded first")),
+        }
+    }
+
+    /// Number of tile columns in the image. Older Tiled versions don't save it.
+    pub fn get_columns(&self) -> i32 {
+        match self.columns {
+            Some(columns) => columns,
+            None => {
+                let (tilewidth, margin, spacing) = (
+                    self.tilewidth.unwrap_or(1),
+                    self.margin.unwrap_or(0),
+                    self.spacing.unwrap_or(0),
+                );
+                let imagewidth = match self._texture {
+                    Some(ref texture) => texture.dimensions().0 as i32,
+                    None => self.imagewidth.unwrap_or(0),
+                };
+                (imagewidth - 2 * margin + spacing) / (tilewidth + spacing)
+            }
         }
     }
+
+    /// Texture coordinates of a tile, in top left, top right, bottom right, bottom left order.
+    /// Takes the flip flags of the GID into account.
+    pub fn tile_uvs(&self, gid: u32) -> [[f32; 2]; 4] {
+        let id = (gid & GID_MASK) as i32 - self.firstgid;
+        let columns = self.get_columns().max(1);
+        let (tilewidth, tileheight) = (self.tilewidth.unwrap_or(0), self.tileheight.unwrap_or(0));
+        let (margin, spacing) = (self.margin.unwrap_or(0), self.spacing.unwrap_or(0));
+
+        // Prefer the real size of the image, in case the file is out of date
+        let (imagewidth, imageheight) = match self._texture {
+            Some(ref texture) => {
+                let (w, h) = texture.dimensions();
+                (w as f32, h as f32)
+            }
+            None => (
+                self.imagewidth.unwrap_or(1) as f32,
+                self.imageheight.unwrap_or(1) as f32,
+            ),
+        };
+
+        let x = (margin + (id % columns) * (tilewidth + spacing)) as f32;
+        let y = (margin + (id / columns) * (tileheight + spacing)) as f32;
+        let (u0, v0) = (x / imagewidth, y / imageheight);
+        let (u1, v1) = (
+            (x + tilewidth as f32) / imagewidth,
+            (y + tileheight as f32) / imageheight,
+        );
+
+        let mut uvs = [[u0, v0], [u1, v0], [u1, v1], [u0, v1]];
+        // Tiled does the diagonal flip first, then horizontal, then vertical
+        if gid & FLIPPED_DIAGONALLY != 0 {
+            uvs.swap(1, 3);
+        }
+        if gid & FLIPPED_HORIZONTALLY != 0 {
+            uvs.swap(0, 1);
+            uvs.swap(2, 3);
+        }
+        if gid & FLIPPED_VERTICALLY != 0 {
+            uvs.swap(0, 3);
+            uvs.swap(1, 2);
+        }
+        uvs
+    }
+}
+
+/// Which axis is staggered on staggered and hexagonal maps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StaggerAxis {
+    X,
+    Y,
+}
+
+/// Whether the odd or the even rows (or columns) are shifted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StaggerIndex {
+    Odd,
+    Even,
+}
+
+/// How the tiles of a map are laid out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Orthogonal,
+    Isometric,
+    /// Isometric tiles in a zig-zag, so the map stays rectangular
+    Staggered { axis: StaggerAxis, index: StaggerIndex },
+    /// `side_length` is the length of the flat side of the hexagons, in pixels
+    Hexagonal {
+        axis: StaggerAxis,
+        index: StaggerIndex,
+        side_length: i32,
+    },
+}
+
+impl Default for Orientation {
+    fn default() -> Orientation {
+        Orientation::Orthogonal
+    }
+}
+
+impl Orientation {
+    /// Builds the orientation from the map attributes. Tiled leaves the stagger
+    /// attributes out of orthogonal and isometric maps, so they default to what Tiled uses.
+    pub fn parse(
+        orientation: &str,
+        staggeraxis: Option<&str>,
+        staggerindex: Option<&str>,
+        hexsidelength: Option<i32>,
+    ) -> Result<Orientation, String> {
+        let axis = match staggeraxis {
+            Some("y") | None => StaggerAxis::Y,
+            Some("x") => StaggerAxis::X,
+            Some(other) => return Err(format!("Unknown staggeraxis {:?}", other)),
+        };
+        let index = match staggerindex {
+            Some("odd") | None => StaggerIndex::Odd,
+            Some("even") => StaggerIndex::Even,
+            Some(other) => return Err(format!("Unknown staggerindex {:?}", other)),
+        };
+
+        match orientation {
+            "orthogonal" => Ok(Orientation::Orthogonal),
+            "isometric" => Ok(Orientation::Isometric),
+            "staggered" => Ok(Orientation::Staggered {
+                axis: axis,
+                index: index,
+            }),
+            "hexagonal" => Ok(Orientation::Hexagonal {
+                axis: axis,
+                index: index,
+                side_length: hexsidelength.unwrap_or(0),
+            }),
+            other => Err(format!("Unknown orientation {:?}", other)),
+        }
+    }
+}
+
+/// Layout of the tile grid of a map, for going between tile and world coordinates.
+///
+/// World coordinates are pixels with Y going up, like everywhere else in the engine.
+/// The top of the map is at y = 0, so the map is drawn below the X axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    pub orientation: Orientation,
+    pub width: i32,
+    pub height: i32,
+    pub tilewidth: i32,
+    pub tileheight: i32,
+}
+
+impl Grid {
+    /// Top left corner of the bounding box of a tile cell, in Tiled pixels (Y down).
+    pub fn cell_origin(&self, x: i32, y: i32) -> (f32, f32) {
+        let (tw, th) = (self.tilewidth as f32, self.tileheight as f32);
+        let (xf, yf) = (x as f32, y as f32);
+
+        match self.orientation {
+            Orientation::Orthogonal => (xf * tw, yf * th),
+            Orientation::Isometric => {
+                // Tile (0, 0) is the top corner of the diamond, so the left
+                // corner of the map ends up at x = 0
+                let origin_x = self.height as f32 * tw / 2.;
+                ((xf - yf) * tw / 2. + origin_x - tw / 2., (xf + yf) * th / 2.)
+            }
+            Orientation::Staggered { axis, index } => self.stagger_origin(x, y, axis, index, 0.),
+            Orientation::Hexagonal {
+                axis,
+                index,
+                side_length,
+            } => self.stagger_origin(x, y, axis, index, side_length as f32),
+        }
+    }
+
+    /// Staggered maps are hexagonal maps with no sides, so they share the math
+    fn stagger_origin(&self, x: i32, y: i32, axis: StaggerAxis, index: StaggerIndex, side: f32) -> (f32, f32) {
+        let (tw, th) = (self.tilewidth as f32, self.tileheight as f32);
+        match axis {
+            StaggerAxis::Y => {
+                let row_height = (th + side) / 2.;
+                let shift = if is_shifted(y, index) { tw / 2. } else { 0. };
+                (x as f32 * tw + shift, y as f32 * row_height)
+            }
+            StaggerAxis::X => {
+                let column_width = (tw + side) / 2.;
+                let shift = if is_shifted(x, index) { th / 2. } else { 0. };
+                (x as f32 * column_width, y as f32 * th + shift)
+            }
+        }
+    }
+
+    /// Center of a tile in world coordinates
+    pub fn tile_to_world(&self, x: i32, y: i32) -> (f32, f32) {
+        let (left, top) = self.cell_origin(x, y);
+        (
+            left + self.tilewidth as f32 / 2.,
+            -(top + self.tileheight as f32 / 2.),
+        )
+    }
+
+    /// The tile under a world position. Doesn't care about the map bounds,
+    /// so the result can be outside the map.
+    pub fn world_to_tile(&self, wx: f32, wy: f32) -> (i32, i32) {
+        let (tw, th) = (self.tilewidth as f32, self.tileheight as f32);
+        let (px, py) = (wx, -wy);
+
+        match self.orientation {
+            Orientation::Orthogonal => ((px / tw).floor() as i32, (py / th).floor() as i32),
+            Orientation::Isometric => {
+                let origin_x = self.height as f32 * tw / 2.;
+                let (tx, ty) = (py / th + (px - origin_x) / tw, py / th - (px - origin_x) / tw);
+                (tx.floor() as i32, ty.floor() as i32)
+            }
+            Orientation::Staggered { .. } | Orientation::Hexagonal { .. } => {
+                self.nearest_stagger_tile(wx, wy)
+            }
+        }
+    }
+
+    /// Staggered cells overlap each others bounding boxes, so we make a rough
+    /// guess and then pick the neighbour whose center is closest.
+    fn nearest_stagger_tile(&self, wx: f32, wy: f32) -> (i32, i32) {
+        let (tw, th) = (self.tilewidth as f32, self.tileheight as f32);
+        let (axis, side, diamonds) = match self.orientation {
+            Orientation::Staggered { axis, .. } => (axis, 0., true),
+            Orientation::Hexagonal {
+                axis, side_length, ..
+            } => (axis, side_length as f32, false),
+            _ => unreachable!(),
+        };
+
+        let (px, py) = (wx, -wy);
+        let (gx, gy) = match axis {
+            StaggerAxis::Y => ((px / tw).floor() as i32, (py / ((th + side) / 2.)).floor() as i32),
+            StaggerAxis::X => ((px / ((tw + side) / 2.)).floor() as i32, (py / th).floor() as i32),
+        };
+
+        let mut best = (gx, gy);
+        let mut best_distance = ::std::f32::INFINITY;
+        for y in gy - 1..gy + 2 {
+            for x in gx - 1..gx + 2 {
+                let (cx, cy) = self.tile_to_world(x, y);
+                // Measured in half tiles, so wide tiles don't win every time
+                let (dx, dy) = ((wx - cx).abs() / (tw / 2.), (wy - cy).abs() / (th / 2.));
+                let distance = if diamonds { dx + dy } else { dx * dx + dy * dy };
+                if distance < best_distance {
+                    best = (x, y);
+                    best_distance = distance;
+                }
+            }
+        }
+        best
+    }
+
+    /// Turns object coordinates into world coordinates. Objects on isometric maps
+    /// are stored in "unprojected" pixels, where a tile is tileheight wide and high.
+    pub fn object_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        match self.orientation {
+            Orientation::Isometric => {
+                let (tw, th) = (self.tilewidth as f32, self.tileheight as f32);
+                let (tx, ty) = (x / th, y / th);
+                let origin_x = self.height as f32 * tw / 2.;
+                ((tx - ty) * tw / 2. + origin_x, -(tx + ty) * th / 2.)
+            }
+            _ => (x, -y),
+        }
+    }
+}
+
+fn is_shifted(i: i32, index: StaggerIndex) -> bool {
+    match index {
+        StaggerIndex::Odd => i & 1 == 1,
+        StaggerIndex::Even => i & 1 == 0,
+    }
 }
 
 /*
@@ -229,7 +964,10 @@ impl Tileset {
     height          int     Number of tile rows
     tilewidth       int     Map grid width.
     tileheight      int     Map grid height.
-    orientation     string  Orthogonal, isometric, or staggered
+    orientation     string  Orthogonal, isometric, staggered or hexagonal
+    staggeraxis     string  x or y (staggered / hexagonal maps only)
+    staggerindex    string  odd or even (staggered / hexagonal maps only)
+    hexsidelength   int     Length of the side of a hex tile in pixels
     layers          array   Array of Layers
     tilesets        array   Array of Tilesets
     backgroundcolor string  Hex-formatted color (#RRGGBB or #AARRGGBB) (optional)
@@ -247,7 +985,13 @@ pub struct Tilemap {
     pub height: i32,
     pub tilewidth: i32,
     pub tileheight: i32,
-    pub orientation: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub orientation: Orientation,
+    #[serde(rename = "orientation")]
+    _orientation: String,
+    staggeraxis: Option<String>,
+    staggerindex: Option<String>,
+    hexsidelength: Option<i32>,
     pub layers: Vec<Layer>,
     pub tilesets: Vec<Tileset>,
     pub properties: Option<Map<String, Value>>,
@@ -264,7 +1008,11 @@ impl Tilemap {
             height: 0,
             tilewidth: 0,
             tileheight: 0,
-            orientation: String::new(),
+            orientation: Orientation::Orthogonal,
+            _orientation: "orthogonal".to_owned(),
+            staggeraxis: None,
+            staggerindex: None,
+            hexsidelength: None,
             layers: Vec::new(),
             tilesets: Vec::new(),
             properties: None,
@@ -273,73 +1021,303 @@ impl Tilemap {
     }
 
 
+    /// Loads a map and its tilesets, picking the format from the file extension.
+    /// Reads through `AssetRegistry::with_defaults`, see `load_with`.
+    pub fn load(filename: &str, factory: &mut graphics::Factory) -> Result<Tilemap, Error> {
+        Tilemap::load_with(&AssetRegistry::with_defaults(), filename, factory)
+    }
+
+    /// Loads a map, its tilesets and their images from `registry`, right away.
+    pub fn load_with(
+        registry: &AssetRegistry,
+        filename: &str,
+        factory: &mut graphics::Factory,
+    ) -> Result<Tilemap, Error> {
+        let mut map: Tilemap = registry.load(filename)?;
+        map.load_tileset_images(registry, factory)?;
+        Ok(map)
+    }
+
+    /// Same as `load`, kept around for old code. Despite the name, TMX works too.
     pub fn from_tiled_json(
         filename: &str,
         factory: &mut graphics::Factory,
-    ) -> Result<Tilemap, String> {
+    ) -> Result<Tilemap, Error> {
+        Tilemap::load(filename, factory)
+    }
+
+    /// Parses a .json or .tmx map and resolves its external tilesets,
+    /// without loading any textures.
+    pub fn parse_file(filename: &str) -> Result<Tilemap, Error> {
         println!("Loading Tilemap from {}", filename);
 
-        let mut f = File::open(filename).expect("file not found");
-        let mut contents = String::new();
-        f.read_to_string(&mut contents).expect(
-            "something went wrong reading the file",
-        );
+        let contents = read_file(filename)?;
+        let mut map = Tilemap::parse(filename, &contents)?;
+        map.resolve_tilesets()?;
+        Ok(map)
+    }
 
-        let mut map: Tilemap = serde_json::from_str(&contents).unwrap();
+    /// Parses a map that was already read into memory. The format is picked from the
+    /// extension of `filename`, which is also where external tilesets are looked up from.
+    /// Doesn't touch the tilesets, see `resolve_tilesets`.
+    pub fn parse(filename: &str, contents: &str) -> Result<Tilemap, Error> {
+        let mut map: Tilemap = match extension(filename) {
+            "json" => serde_json::from_str(contents).map_err(|e| Error::tilemap(filename, e.to_string()))?,
+            "tmx" => {
+                let json = tmx::map_to_json(contents).map_err(|e| Error::tilemap(filename, e))?;
+                serde_json::from_value(json).map_err(|e| Error::tilemap(filename, e.to_string()))?
+            }
+            ext => return Err(Error::tilemap(filename, format!("unknown tilemap format {:?}", ext))),
+        };
         map.filename = filename.to_owned();
-        map.load_tilesets(factory);
+        if map.width <= 0 || map.height <= 0 {
+            return Err(Error::tilemap(
+                filename,
+                format!("map is {}x{} tiles", map.width, map.height),
+            ));
+        }
+        map.orientation = Orientation::parse(
+            &map._orientation,
+            map.staggeraxis.as_ref().map(|s| &s[..]),
+            map.staggerindex.as_ref().map(|s| &s[..]),
+            map.hexsidelength,
+        ).map_err(|e| Error::tilemap(filename, e))?;
+        for layer in &mut map.layers {
+            layer.resolve_data().map_err(|e| Error::tilemap(filename, e))?;
+        }
         Ok(map)
     }
 
-    pub fn load_tilesets(&mut self, factory: &mut graphics::Factory) -> () {
-        let root = Path::new(&self.filename)
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap();
-        let loaded: Vec<Tileset> = self.tilesets
-            .iter()
-            .map(|ts| {
-                let mut newts = match ts.source {
-                    Some(ref source) => {
-                        let path: PathBuf = [root, source].into_iter().collect();
-                        let mut loaded = load_tileset(path.to_str().unwrap()).unwrap();
-                        loaded.firstgid = ts.firstgid;
-                        loaded.root = path.parent().unwrap().to_str().unwrap().to_owned();
-                        loaded.name = match loaded._name {
-                            Some(ref name) => (*name).clone(),
-                            None => panic!("Tileset needs a name {:?}", loaded),
-                        };
-                        loaded
-                    }
-                    None => {
-                        let mut newts = (*ts).clone();
-                        newts.firstgid = newts._firstgid.unwrap();
-                        newts.root = root.to_owned();
-                        newts.name = match newts._name {
-                            Some(ref name) => (*name).clone(),
-                            None => panic!("Tileset needs a name {:?}", newts),
-                        };
-                        newts
-                    }
-                };
-                newts.load_image(factory);
-                newts
-            })
-            .collect();
+    /// Loads external tilesets, and fills in the fields that are only known after loading.
+    pub fn resolve_tilesets(&mut self) -> Result<(), Error> {
+        self.resolve_tilesets_with(read_file)
+    }
+
+    /// Same as `resolve_tilesets`, but external tilesets are read with `read`.
+    /// For loading maps from somewhere else than the file system.
+    pub fn resolve_tilesets_with<F>(&mut self, mut read: F) -> Result<(), Error>
+    where
+        F: FnMut(&str) -> Result<String, Error>,
+    {
+        let root = match Path::new(&self.filename).parent() {
+            Some(root) => utf8_path(&self.filename, root)?.to_owned(),
+            None => String::new(),
+        };
+
+        let mut loaded: Vec<Tileset> = Vec::new();
+        for ts in &self.tilesets {
+            let mut newts = match ts.source {
+                Some(ref source) => {
+                    let path: PathBuf = [&root, source].into_iter().collect();
+                    let path_str = utf8_path(&self.filename, &path)?;
+                    let mut loaded = parse_tileset(path_str, &read(path_str)?)?;
+                    loaded.path = Some(path_str.to_owned());
+                    loaded.firstgid = ts._firstgid.unwrap_or(ts.firstgid);
+                    loaded.root = match path.parent() {
+                        Some(parent) => utf8_path(&self.filename, parent)?.to_owned(),
+                        None => String::new(),
+                    };
+                    loaded
+                }
+                None => {
+                    let mut newts = (*ts).clone();
+                    newts.firstgid = match newts._firstgid {
+                        Some(firstgid) => firstgid,
+                        None => {
+                            return Err(Error::tilemap(
+                                &self.filename,
+                                format!("tileset needs a firstgid {:?}", newts),
+                            ))
+                        }
+                    };
+                    newts.root = root.clone();
+                    newts
+                }
+            };
+            newts.name = match newts._name {
+                Some(ref name) => (*name).clone(),
+                None => {
+                    return Err(Error::tilemap(&self.filename, format!("tileset needs a name {:?}", newts)))
+                }
+            };
+            newts.resolve_tiles().map_err(|e| Error::tilemap(&self.filename, e))?;
+            loaded.push(newts);
+        }
+
+        loaded.sort_by_key(|ts| ts.firstgid);
         self.tilesets = loaded;
+        Ok(())
     }
-}
 
-pub fn load_tileset(filename: &str) -> Result<Tileset, String> {
-    println!("Loading Tileset from {}", filename);
+    pub fn load_tileset_images(
+        &mut self,
+        registry: &AssetRegistry,
+        factory: &mut graphics::Factory,
+    ) -> Result<(), Error> {
+        for tileset in &mut self.tilesets {
+            tileset.load_image(registry, factory)?;
+        }
+        Ok(())
+    }
+
+    /// Asks the AssetServer for the tileset images, they are loaded in the background.
+    /// `update_textures` picks them up once they are on the GPU.
+    pub fn request_textures(&mut self, assets: &mut AssetServer) -> Result<(), Error> {
+        for tileset in &mut self.tilesets {
+            let path = tileset.image_path()?;
+            tileset._texture_handle = Some(assets.load_texture(&path));
+        }
+        Ok(())
+    }
 
-    let mut f = File::open(filename).expect("file not found");
+    /// Takes the tileset textures that the AssetServer has uploaded, or reloaded, since
+    /// the last call. True once every tileset has its texture, and the map can be drawn.
+    pub fn update_textures(&mut self, assets: &AssetServer) -> bool {
+        let mut ready = true;
+        let mut changed = false;
+        for tileset in &mut self.tilesets {
+            let handle = match tileset._texture_handle {
+                Some(handle) => handle,
+                None => continue,
+            };
+            match assets.texture(handle) {
+                Some(texture) => {
+                    let current = tileset._texture.as_ref().map(|texture| texture.id());
+                    if current != Some(texture.id()) {
+                        tileset._texture = Some(texture);
+                        changed = true;
+                    }
+                }
+                None => ready = false,
+            }
+        }
+        if changed {
+            // The UVs depend on the size of the image
+            for layer in &mut self.layers {
+                layer.mesh_opacity = None;
+            }
+        }
+        ready
+    }
+
+    /// The map file and external tilesets, as asset paths. What to watch for hot reloading,
+    /// the AssetServer watches the tileset images.
+    pub fn files(&self) -> Vec<String> {
+        let mut files = vec![self.filename.clone()];
+        for tileset in &self.tilesets {
+            if let Some(ref path) = tileset.path {
+                files.push(path.clone());
+            }
+        }
+        files
+    }
+
+    /// Layout of the tile grid
+    pub fn grid(&self) -> Grid {
+        Grid {
+            orientation: self.orientation,
+            width: self.width,
+            height: self.height,
+            tilewidth: self.tilewidth,
+            tileheight: self.tileheight,
+        }
+    }
+
+    /// Center of a tile in world coordinates, see `Grid::tile_to_world`
+    pub fn tile_to_world(&self, x: i32, y: i32) -> (f32, f32) {
+        self.grid().tile_to_world(x, y)
+    }
+
+    /// The tile under a world position, see `Grid::world_to_tile`
+    pub fn world_to_tile(&self, wx: f32, wy: f32) -> (i32, i32) {
+        self.grid().world_to_tile(wx, wy)
+    }
+
+    /// The tileset a GID belongs to
+    pub fn tileset_for_gid(&self, gid: u32) -> Option<&Tileset> {
+        match tileset_for_gid(&self.tilesets, gid) {
+            Some(index) => Some(&self.tilesets[index]),
+            None => None,
+        }
+    }
+
+    /// All the objects in all object layers, in layer order
+    pub fn objects(&self) -> Vec<&MapObject> {
+        let mut objects = Vec::new();
+        for layer in &self.layers {
+            if let LayerData::ObjectData(ref layer_objects) = layer.data {
+                objects.extend(layer_objects.iter());
+            }
+        }
+        objects
+    }
+
+    /// (Re)builds the vertex buffers of layers that changed since last time,
+    /// and moves animated tiles to their current frame.
+    pub fn update_meshes(&mut self, elapsed: Duration, renderer: &mut graphics::Renderer) -> () {
+        let grid = self.grid();
+        for layer in &mut self.layers {
+            if layer.mesh_opacity != Some(layer.opacity) {
+                layer.build_meshes(&grid, &self.tilesets, renderer);
+            }
+            layer.animate(&self.tilesets, elapsed, renderer);
+        }
+    }
+
+    /// Draws the visible layers, bottom layer first.
+    /// `elapsed` drives the animated tiles, usually the ElapsedTime of the world.
+    pub fn draw(&mut self, elapsed: Duration, renderer: &mut graphics::Renderer) -> () {
+        self.update_meshes(elapsed, renderer);
+        for layer in &self.layers {
+            if layer.visible && layer.opacity > 0. {
+                layer.draw(&self.tilesets, renderer);
+            }
+        }
+    }
+}
+
+fn read_file(filename: &str) -> Result<String, Error> {
+    let io_error = |error| Error::Io {
+        path: filename.to_owned(),
+        error: error,
+    };
+    let mut f = File::open(filename).map_err(&io_error)?;
     let mut contents = String::new();
-    f.read_to_string(&mut contents).expect(
-        "something went wrong reading the file",
-    );
+    f.read_to_string(&mut contents).map_err(&io_error)?;
+    Ok(contents)
+}
+
+/// Paths are passed around as strings, so files the map refers to need UTF-8 names.
+/// `owner` is the map or tileset, for the error message.
+fn utf8_path<'a>(owner: &str, path: &'a Path) -> Result<&'a str, Error> {
+    match path.to_str() {
+        Some(path) => Ok(path),
+        None => Err(Error::tilemap(owner, format!("{} is not a UTF-8 path", path.display()))),
+    }
+}
 
-    let tileset: Tileset = serde_json::from_str(&contents).unwrap();
+fn extension(filename: &str) -> &str {
+    match Path::new(filename).extension() {
+        Some(ext) => ext.to_str().unwrap_or(""),
+        None => "",
+    }
+}
+
+/// Loads an external tileset, .json or .tsx
+pub fn load_tileset(filename: &str) -> Result<Tileset, Error> {
+    println!("Loading Tileset from {}", filename);
+    parse_tileset(filename, &read_file(filename)?)
+}
+
+/// Parses an external tileset that was already read into memory, see `load_tileset`
+pub fn parse_tileset(filename: &str, contents: &str) -> Result<Tileset, Error> {
+    let tileset: Tileset = match extension(filename) {
+        "json" => serde_json::from_str(contents).map_err(|e| Error::tilemap(filename, e.to_string()))?,
+        "tsx" => {
+            let json = tmx::tileset_to_json(contents).map_err(|e| Error::tilemap(filename, e))?;
+            serde_json::from_value(json).map_err(|e| Error::tilemap(filename, e.to_string()))?
+        }
+        ext => return Err(Error::tilemap(filename, format!("unknown tileset format {:?}", ext))),
+    };
     Ok(tileset)
 }
@@ -0,0 +1,461 @@
+//! Tiled TMX (XML) maps and TSX tilesets
+//!
+//! Instead of having a second set of structs, the XML is converted into the same shape
+//! Tiled uses for its JSON export, and then deserialized like any other JSON map. This
+//! way a .tmx and its JSON export end up as exactly the same Tilemap.
+
+use std::collections::HashMap;
+use std::io::prelude::*;
+
+use base64;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use serde_json::{Number, Value};
+use serde_json::map::Map;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Minimal DOM, xml-rs only gives us events
+#[derive(Debug)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|s| &s[..])
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> Box<Iterator<Item = &'a Element> + 'a> {
+        Box::new(self.children.iter().filter(move |c| c.name == name))
+    }
+}
+
+fn parse_document(contents: &str) -> Result<Element, String> {
+    let mut stack: Vec<Element> = Vec::new();
+
+    for event in EventReader::from_str(contents) {
+        match event.map_err(|e| format!("{}", e))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|a| (a.name.local_name, a.value))
+                        .collect(),
+                    children: Vec::new(),
+                    text: String::new(),
+                });
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err("Unexpected end of document".to_owned())
+}
+
+/// Converts a .tmx document to the JSON map format
+pub fn map_to_json(contents: &str) -> Result<Value, String> {
+    let map = parse_document(contents)?;
+    if map.name != "map" {
+        return Err(format!("Expected <map>, found <{}>", map.name));
+    }
+
+    let mut json = Map::new();
+    json.insert(
+        "version".to_owned(),
+        float(map.attr("version").unwrap_or("1.0"))?,
+    );
+    copy_string(&map, &mut json, "tiledversion");
+    copy_string(&map, &mut json, "orientation");
+    copy_string(&map, &mut json, "renderorder");
+    copy_string(&map, &mut json, "backgroundcolor");
+    copy_string(&map, &mut json, "staggeraxis");
+    copy_string(&map, &mut json, "staggerindex");
+    copy_int(&map, &mut json, "hexsidelength")?;
+    copy_int(&map, &mut json, "width")?;
+    copy_int(&map, &mut json, "height")?;
+    copy_int(&map, &mut json, "tilewidth")?;
+    copy_int(&map, &mut json, "tileheight")?;
+    copy_int(&map, &mut json, "nextobjectid")?;
+    copy_properties(&map, &mut json)?;
+
+    let mut tilesets = Vec::new();
+    for tileset in map.children_named("tileset") {
+        let mut ts = match tileset.attr("source") {
+            Some(source) => {
+                let mut ts = Map::new();
+                ts.insert("source".to_owned(), Value::String(source.to_owned()));
+                ts
+            }
+            None => convert_tileset(tileset)?,
+        };
+        copy_int(tileset, &mut ts, "firstgid")?;
+        tilesets.push(Value::Object(ts));
+    }
+    json.insert("tilesets".to_owned(), Value::Array(tilesets));
+
+    let mut layers = Vec::new();
+    for child in &map.children {
+        match &child.name[..] {
+            "layer" => layers.push(convert_tile_layer(child)?),
+            "objectgroup" => layers.push(convert_object_group(child)?),
+            "imagelayer" | "group" => {
+                println!("Skipping unsupported <{}> in map", child.name);
+            }
+            _ => {}
+        }
+    }
+    json.insert("layers".to_owned(), Value::Array(layers));
+
+    Ok(Value::Object(json))
+}
+
+/// Converts a .tsx document to the JSON tileset format
+pub fn tileset_to_json(contents: &str) -> Result<Value, String> {
+    let tileset = parse_document(contents)?;
+    if tileset.name != "tileset" {
+        return Err(format!("Expected <tileset>, found <{}>", tileset.name));
+    }
+    Ok(Value::Object(convert_tileset(&tileset)?))
+}
+
+fn convert_tileset(tileset: &Element) -> Result<Map<String, Value>, String> {
+    let mut json = Map::new();
+    copy_string(tileset, &mut json, "name");
+    copy_int(tileset, &mut json, "tilewidth")?;
+    copy_int(tileset, &mut json, "tileheight")?;
+    copy_int(tileset, &mut json, "tilecount")?;
+    copy_int(tileset, &mut json, "columns")?;
+    json.insert("spacing".to_owned(), int(tileset.attr("spacing").unwrap_or("0"))?);
+    json.insert("margin".to_owned(), int(tileset.attr("margin").unwrap_or("0"))?);
+    copy_properties(tileset, &mut json)?;
+
+    if let Some(image) = tileset.child("image") {
+        if let Some(source) = image.attr("source") {
+            json.insert("image".to_owned(), Value::String(source.to_owned()));
+        }
+        if let Some(width) = image.attr("width") {
+            json.insert("imagewidth".to_owned(), int(width)?);
+        }
+        if let Some(height) = image.attr("height") {
+            json.insert("imageheight".to_owned(), int(height)?);
+        }
+    }
+
+    let mut tiles = Map::new();
+    let mut tileproperties = Map::new();
+    let mut tilepropertytypes = Map::new();
+    for tile in tileset.children_named("tile") {
+        let id = match tile.attr("id") {
+            Some(id) => id.to_owned(),
+            None => return Err("<tile> without an id".to_owned()),
+        };
+        if let Some((properties, types)) = convert_properties(tile)? {
+            tileproperties.insert(id.clone(), Value::Object(properties));
+            tilepropertytypes.insert(id.clone(), Value::Object(types));
+        }
+
+        let mut tile_json = Map::new();
+        copy_string(tile, &mut tile_json, "type");
+        if let Some(animation) = tile.child("animation") {
+            let mut frames = Vec::new();
+            for frame in animation.children_named("frame") {
+                let mut frame_json = Map::new();
+                copy_int(frame, &mut frame_json, "tileid")?;
+                copy_int(frame, &mut frame_json, "duration")?;
+                frames.push(Value::Object(frame_json));
+            }
+            tile_json.insert("animation".to_owned(), Value::Array(frames));
+        }
+        if let Some(group) = tile.child("objectgroup") {
+            tile_json.insert("objectgroup".to_owned(), convert_object_group(group)?);
+        }
+        if !tile_json.is_empty() {
+            tiles.insert(id, Value::Object(tile_json));
+        }
+    }
+    if !tiles.is_empty() {
+        json.insert("tiles".to_owned(), Value::Object(tiles));
+    }
+    if !tileproperties.is_empty() {
+        json.insert("tileproperties".to_owned(), Value::Object(tileproperties));
+        json.insert(
+            "tilepropertytypes".to_owned(),
+            Value::Object(tilepropertytypes),
+        );
+    }
+
+    Ok(json)
+}
+
+fn convert_tile_layer(layer: &Element) -> Result<Value, String> {
+    let mut json = Map::new();
+    json.insert("type".to_owned(), Value::String("tilelayer".to_owned()));
+    copy_layer_attributes(layer, &mut json)?;
+
+    let data = match layer.child("data") {
+        Some(data) => decode_tile_data(data)?,
+        None => return Err(format!("Layer {:?} has no <data>", layer.attr("name"))),
+    };
+    json.insert(
+        "data".to_owned(),
+        Value::Array(data.into_iter().map(Value::from).collect()),
+    );
+
+    Ok(Value::Object(json))
+}
+
+fn convert_object_group(group: &Element) -> Result<Value, String> {
+    let mut json = Map::new();
+    json.insert("type".to_owned(), Value::String("objectgroup".to_owned()));
+    copy_layer_attributes(group, &mut json)?;
+    json.insert(
+        "draworder".to_owned(),
+        Value::String(group.attr("draworder").unwrap_or("topdown").to_owned()),
+    );
+
+    let mut objects = Vec::new();
+    for object in group.children_named("object") {
+        objects.push(convert_object(object)?);
+    }
+    json.insert("objects".to_owned(), Value::Array(objects));
+
+    Ok(Value::Object(json))
+}
+
+fn convert_object(object: &Element) -> Result<Value, String> {
+    let mut json = Map::new();
+    copy_int(object, &mut json, "id")?;
+    copy_int(object, &mut json, "gid")?;
+    json.insert(
+        "name".to_owned(),
+        Value::String(object.attr("name").unwrap_or("").to_owned()),
+    );
+    json.insert(
+        "type".to_owned(),
+        Value::String(object.attr("type").unwrap_or("").to_owned()),
+    );
+    for &key in &["x", "y", "width", "height", "rotation"] {
+        json.insert(key.to_owned(), float(object.attr(key).unwrap_or("0"))?);
+    }
+    json.insert(
+        "visible".to_owned(),
+        Value::Bool(object.attr("visible").unwrap_or("1") == "1"),
+    );
+    copy_properties(object, &mut json)?;
+
+    if object.child("ellipse").is_some() {
+        json.insert("ellipse".to_owned(), Value::Bool(true));
+    }
+    if object.child("point").is_some() {
+        json.insert("point".to_owned(), Value::Bool(true));
+    }
+    for &key in &["polygon", "polyline"] {
+        if let Some(shape) = object.child(key) {
+            json.insert(key.to_owned(), convert_points(shape.attr("points").unwrap_or(""))?);
+        }
+    }
+    if let Some(text) = object.child("text") {
+        let mut text_json = Map::new();
+        text_json.insert("text".to_owned(), Value::String(text.text.clone()));
+        for (key, value) in &text.attributes {
+            let value = match &key[..] {
+                "wrap" | "bold" | "italic" | "underline" | "strikeout" | "kerning" => {
+                    Value::Bool(value == "1")
+                }
+                "pixelsize" => int(value)?,
+                _ => Value::String(value.clone()),
+            };
+            text_json.insert(key.clone(), value);
+        }
+        json.insert("text".to_owned(), Value::Object(text_json));
+    }
+
+    Ok(Value::Object(json))
+}
+
+/// "0,0 32,0 32,32" into [{"x": 0, "y": 0}, ...]
+fn convert_points(points: &str) -> Result<Value, String> {
+    let mut json = Vec::new();
+    for point in points.split_whitespace() {
+        let mut coords = point.split(',');
+        match (coords.next(), coords.next()) {
+            (Some(x), Some(y)) => {
+                let mut p = Map::new();
+                p.insert("x".to_owned(), float(x)?);
+                p.insert("y".to_owned(), float(y)?);
+                json.push(Value::Object(p));
+            }
+            _ => return Err(format!("Invalid point {:?}", point)),
+        }
+    }
+    Ok(Value::Array(json))
+}
+
+fn copy_layer_attributes(layer: &Element, json: &mut Map<String, Value>) -> Result<(), String> {
+    json.insert(
+        "name".to_owned(),
+        Value::String(layer.attr("name").unwrap_or("").to_owned()),
+    );
+    json.insert("width".to_owned(), int(layer.attr("width").unwrap_or("0"))?);
+    json.insert("height".to_owned(), int(layer.attr("height").unwrap_or("0"))?);
+    json.insert("x".to_owned(), Value::from(0));
+    json.insert("y".to_owned(), Value::from(0));
+    json.insert(
+        "visible".to_owned(),
+        Value::Bool(layer.attr("visible").unwrap_or("1") == "1"),
+    );
+    json.insert("opacity".to_owned(), float(layer.attr("opacity").unwrap_or("1"))?);
+    copy_properties(layer, json)
+}
+
+/// Decodes the GIDs of a <data> element, in any of the encodings Tiled supports.
+fn decode_tile_data(data: &Element) -> Result<Vec<u32>, String> {
+    match (data.attr("encoding"), data.attr("compression")) {
+        (None, _) => data.children_named("tile")
+            .map(|tile| {
+                tile.attr("gid")
+                    .unwrap_or("0")
+                    .parse::<u32>()
+                    .map_err(|e| format!("Invalid GID: {}", e))
+            })
+            .collect(),
+        (Some("csv"), _) => data.text
+            .split(',')
+            .map(|gid| {
+                gid.trim()
+                    .parse::<u32>()
+                    .map_err(|e| format!("Invalid GID {:?}: {}", gid, e))
+            })
+            .collect(),
+        (Some("base64"), compression) => {
+            let text: String = data.text.chars().filter(|c| !c.is_whitespace()).collect();
+            let raw = base64::decode(&text).map_err(|e| format!("Invalid base64: {}", e))?;
+
+            let bytes = match compression {
+                None => raw,
+                Some("zlib") => {
+                    let mut bytes = Vec::new();
+                    ZlibDecoder::new(&raw[..])
+                        .read_to_end(&mut bytes)
+                        .map_err(|e| format!("Invalid zlib data: {}", e))?;
+                    bytes
+                }
+                Some("gzip") => {
+                    let mut bytes = Vec::new();
+                    GzDecoder::new(&raw[..])
+                        .read_to_end(&mut bytes)
+                        .map_err(|e| format!("Invalid gzip data: {}", e))?;
+                    bytes
+                }
+                Some(other) => return Err(format!("Unsupported compression {}", other)),
+            };
+
+            if bytes.len() % 4 != 0 {
+                return Err(format!("Tile data length {} is not divisible by 4", bytes.len()));
+            }
+            Ok(bytes
+                .chunks(4)
+                .map(|b| {
+                    b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+                })
+                .collect())
+        }
+        (Some(other), _) => Err(format!("Unsupported encoding {}", other)),
+    }
+}
+
+/// Tiled JSON stores properties as two objects, values and their types
+fn convert_properties(
+    element: &Element,
+) -> Result<Option<(Map<String, Value>, Map<String, Value>)>, String> {
+    let properties = match element.child("properties") {
+        Some(properties) => properties,
+        None => return Ok(None),
+    };
+
+    let mut values = Map::new();
+    let mut types = Map::new();
+    for property in properties.children_named("property") {
+        let name = match property.attr("name") {
+            Some(name) => name.to_owned(),
+            None => return Err("<property> without a name".to_owned()),
+        };
+        let kind = property.attr("type").unwrap_or("string");
+        // Multiline strings are stored as text instead of the value attribute
+        let raw = match property.attr("value") {
+            Some(value) => value,
+            None => &property.text[..],
+        };
+        let value = match kind {
+            "int" => int(raw)?,
+            "float" => float(raw)?,
+            "bool" => Value::Bool(raw == "true"),
+            _ => Value::String(raw.to_owned()),
+        };
+        values.insert(name.clone(), value);
+        types.insert(name, Value::String(kind.to_owned()));
+    }
+
+    Ok(Some((values, types)))
+}
+
+fn copy_properties(element: &Element, json: &mut Map<String, Value>) -> Result<(), String> {
+    if let Some((values, types)) = convert_properties(element)? {
+        json.insert("properties".to_owned(), Value::Object(values));
+        json.insert("propertytypes".to_owned(), Value::Object(types));
+    }
+    Ok(())
+}
+
+fn copy_string(element: &Element, json: &mut Map<String, Value>, key: &str) -> () {
+    if let Some(value) = element.attr(key) {
+        json.insert(key.to_owned(), Value::String(value.to_owned()));
+    }
+}
+
+fn copy_int(element: &Element, json: &mut Map<String, Value>, key: &str) -> Result<(), String> {
+    if let Some(value) = element.attr(key) {
+        json.insert(key.to_owned(), int(value)?);
+    }
+    Ok(())
+}
+
+fn int(value: &str) -> Result<Value, String> {
+    value
+        .parse::<i64>()
+        .map(Value::from)
+        .map_err(|e| format!("Expected an integer, found {:?}: {}", value, e))
+}
+
+fn float(value: &str) -> Result<Value, String> {
+    let f = value
+        .parse::<f64>()
+        .map_err(|e| format!("Expected a number, found {:?}: {}", value, e))?;
+    // Keep whole numbers as integers, like the JSON export does
+    if f.fract() == 0. && f.abs() < 1e15 {
+        return Ok(Value::from(f as i64));
+    }
+    match Number::from_f64(f) {
+        Some(n) => Ok(Value::Number(n)),
+        None => Err(format!("Invalid number {:?}", value)),
+    }
+}
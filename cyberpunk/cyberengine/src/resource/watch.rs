@@ -0,0 +1,110 @@
+//! Watching files for changes, for hot reloading
+//!
+//! Polls modification times instead of using filesystem notifications. It's only
+//! meant for development, and polling works the same everywhere (network drives,
+//! editors that replace files instead of writing them...).
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct FileWatcher {
+    /// Last seen modification time, None if the file didn't exist
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    /// Directories whose new files are picked up too
+    dirs: Vec<PathBuf>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    /// `interval` is how often `poll` actually looks at the files
+    pub fn new(interval: Duration) -> FileWatcher {
+        FileWatcher {
+            files: HashMap::new(),
+            dirs: Vec::new(),
+            interval: interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P) -> () {
+        let path = path.as_ref().to_owned();
+        if !self.files.contains_key(&path) {
+            let modified = modified(&path);
+            self.files.insert(path, modified);
+        }
+    }
+
+    /// Watches every file in `dir` and its subdirectories, including files created later
+    pub fn watch_dir<P: AsRef<Path>>(&mut self, dir: P) -> () {
+        let dir = dir.as_ref().to_owned();
+        for file in files_in(&dir) {
+            self.watch(file);
+        }
+        self.dirs.push(dir);
+    }
+
+    pub fn is_watching<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.files.contains_key(path.as_ref())
+    }
+
+    /// Files that changed since the last poll. Returns nothing if the last poll was
+    /// less than `interval` ago, so this is fine to call every frame.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < self.interval {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut new_files = Vec::new();
+        for dir in &self.dirs {
+            for file in files_in(dir) {
+                if !self.files.contains_key(&file) {
+                    new_files.push(file);
+                }
+            }
+        }
+        for file in new_files {
+            self.files.insert(file, None);
+        }
+
+        let mut changed = Vec::new();
+        for (path, last) in self.files.iter_mut() {
+            let now = modified(path);
+            if now != *last {
+                *last = now;
+                // Deleted files aren't interesting, they'll show up again when recreated
+                if now.is_some() {
+                    changed.push(path.clone());
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return files,
+    };
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(_) => continue,
+        };
+        if path.is_dir() {
+            files.extend(files_in(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
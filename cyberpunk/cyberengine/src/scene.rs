@@ -0,0 +1,735 @@
+//! Saving and loading worlds
+//!
+//! A scene is the entities of a World and the components that have been registered
+//! with a SceneRegistry, written out as JSON or RON (picked by the file extension).
+//! Save games and hand-made levels both work:
+//!
+//! ```ignore
+//! let scenes = SceneRegistry::with_defaults()
+//!     .with::<Health>("Health")
+//!     .with_version(2)
+//!     .with_migration(1, |scene| {
+//!         // Version 1 called it "Hp"
+//!         for entity in scene.entities.iter_mut() {
+//!             if let Some(hp) = entity.components.remove("Hp") {
+//!                 entity.components.insert("Health".to_owned(), hp);
+//!             }
+//!         }
+//!         Ok(())
+//!     });
+//! scenes.save(&world, "saves/quick.ron")?;
+//! let spawned = scenes.load(&mut world, "saves/quick.ron")?;
+//! ```
+//!
+//! Loading adds new entities to the world, so components that point to other
+//! entities have to be remapped. Mark those fields with
+//! `#[serde(with = "cyberengine::scene::entity")]` (or `scene::entity::option` for
+//! `Option<Entity>`), and they are saved as scene ids and turned back into the new
+//! entities on load.
+//!
+//! Like with the Inspector, the components need to be registered in the World.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::prelude::*;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use ron;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+use specs::{Component, Entity, Join, World};
+
+use error::{Error, Result};
+use graphics::BlendMode;
+use resource::assets::AssetServer;
+use resource::Identifier;
+use resource::loader;
+use systems::sprite::{PendingSprite, Position, Sprite, SpriteSpawn};
+
+/// Version of scenes saved by a SceneRegistry that hasn't been given one
+pub const DEFAULT_VERSION: u32 = 1;
+
+/// The file, before any components have been deserialized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scene {
+    pub version: u32,
+    pub entities: Vec<SceneEntity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneEntity {
+    /// What other entities in the scene call this one
+    pub id: u32,
+    /// By the name the component was registered with
+    pub components: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneFormat {
+    Json,
+    Ron,
+}
+
+impl SceneFormat {
+    /// .ron is RON, everything else JSON
+    pub fn from_path(path: &str) -> SceneFormat {
+        match loader::extension(path).as_str() {
+            "ron" => SceneFormat::Ron,
+            _ => SceneFormat::Json,
+        }
+    }
+}
+
+impl Scene {
+    pub fn parse(contents: &str, format: SceneFormat) -> ::std::result::Result<Scene, String> {
+        match format {
+            SceneFormat::Json => serde_json::from_str(contents).map_err(|e| e.to_string()),
+            SceneFormat::Ron => ron::de::from_str(contents).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn to_string(&self, format: SceneFormat) -> ::std::result::Result<String, String> {
+        match format {
+            SceneFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            SceneFormat::Ron => {
+                ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Upgrades a scene from one version to the next
+pub type Migration = Box<Fn(&mut Scene) -> ::std::result::Result<(), String>>;
+
+/// Type erased component, so different components fit in the same Vec
+trait SceneComponent {
+    fn name(&self) -> &'static str;
+    /// None if the entity doesn't have the component, or it can't be saved
+    fn save(&self, world: &World, entity: Entity) -> Option<::std::result::Result<Value, String>>;
+    fn load(&self, world: &mut World, entity: Entity, value: Value) -> ::std::result::Result<(), String>;
+}
+
+struct Serialized<T> {
+    name: &'static str,
+    marker: PhantomData<T>,
+}
+
+impl<T> SceneComponent for Serialized<T>
+where
+    T: Component + Serialize + DeserializeOwned,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn save(&self, world: &World, entity: Entity) -> Option<::std::result::Result<Value, String>> {
+        world
+            .read::<T>()
+            .get(entity)
+            .map(|component| serde_json::to_value(component).map_err(|e| e.to_string()))
+    }
+
+    fn load(&self, world: &mut World, entity: Entity, value: Value) -> ::std::result::Result<(), String> {
+        let component: T = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        world.write::<T>().insert(entity, component);
+        Ok(())
+    }
+}
+
+/// Sprites can't be serialized, textures live on the GPU. Saved as where the
+/// texture came from, and loaded back through the AssetServer.
+#[derive(Serialize, Deserialize)]
+struct SavedSprite {
+    texture: Identifier,
+    layer: i32,
+    color: [f32; 4],
+    blend: BlendMode,
+}
+
+struct SpriteComponent;
+
+impl SceneComponent for SpriteComponent {
+    fn name(&self) -> &'static str {
+        "Sprite"
+    }
+
+    fn save(&self, world: &World, entity: Entity) -> Option<::std::result::Result<Value, String>> {
+        let saved = match world.read::<Sprite>().get(entity) {
+            Some(sprite) => match sprite.source {
+                Some(ref source) => SavedSprite {
+                    texture: source.clone(),
+                    layer: sprite.layer,
+                    color: sprite.color,
+                    blend: sprite.blend,
+                },
+                // Made with Sprite::new instead of through the SpriteLoader. The rest of
+                // the entity is still worth saving.
+                None => {
+                    println!(
+                        "Not saving Sprite of entity {}, it doesn't know where its texture came from",
+                        entity.id()
+                    );
+                    return None;
+                }
+            },
+            // Still loading, but it should be saved all the same
+            None => match world.read::<PendingSprite>().get(entity) {
+                Some(pending) => SavedSprite {
+                    texture: pending.source.clone(),
+                    layer: pending.layer,
+                    color: pending.color,
+                    blend: pending.blend,
+                },
+                None => return None,
+            },
+        };
+        Some(serde_json::to_value(saved).map_err(|e| e.to_string()))
+    }
+
+    fn load(&self, world: &mut World, entity: Entity, value: Value) -> ::std::result::Result<(), String> {
+        let saved: SavedSprite = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        let texture = match saved.texture {
+            Identifier::Image(ref filename) => world.write_resource::<AssetServer>().load_texture(filename),
+        };
+        let mut pending = PendingSprite::new(texture, saved.texture.clone());
+        pending.layer = saved.layer;
+        pending.color = saved.color;
+        pending.blend = saved.blend;
+        world.write::<PendingSprite>().insert(entity, pending);
+        Ok(())
+    }
+}
+
+/// Which components go into scenes, and how old scenes are upgraded
+pub struct SceneRegistry {
+    components: Vec<Box<SceneComponent>>,
+    version: u32,
+    /// By the version they upgrade from
+    migrations: HashMap<u32, Migration>,
+}
+
+impl SceneRegistry {
+    pub fn new() -> SceneRegistry {
+        SceneRegistry {
+            components: Vec::new(),
+            version: DEFAULT_VERSION,
+            migrations: HashMap::new(),
+        }
+    }
+
+    /// Position, Sprite and SpriteSpawn. Sprites go through the AssetServer on load,
+    /// so the world needs a SpriteLoader, and PendingSprite registered.
+    pub fn with_defaults() -> SceneRegistry {
+        let mut registry = SceneRegistry::new()
+            .with::<Position>("Position")
+            .with::<SpriteSpawn>("SpriteSpawn");
+        registry.components.push(Box::new(SpriteComponent));
+        registry
+    }
+
+    /// Saves T in scenes, under `name`. The name is what ends up in the file, so
+    /// renaming it needs a migration.
+    pub fn with<T>(mut self, name: &'static str) -> SceneRegistry
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        self.components.push(Box::new(Serialized::<T> {
+            name: name,
+            marker: PhantomData,
+        }));
+        self
+    }
+
+    /// Version written to saved scenes. Bump it when the components change in a way
+    /// old scenes can't be read as, and add a migration from the old version.
+    pub fn with_version(mut self, version: u32) -> SceneRegistry {
+        self.version = version;
+        self
+    }
+
+    /// Upgrades scenes of version `from` to `from + 1`
+    pub fn with_migration<F>(mut self, from: u32, migration: F) -> SceneRegistry
+    where
+        F: Fn(&mut Scene) -> ::std::result::Result<(), String> + 'static,
+    {
+        self.migrations.insert(from, Box::new(migration));
+        self
+    }
+
+    /// Collects every entity that has at least one of the registered components
+    pub fn to_scene(&self, world: &World) -> Result<Scene> {
+        let mut entities = Vec::new();
+        for entity in (&*world.entities()).join() {
+            let mut components = BTreeMap::new();
+            for component in self.components.iter() {
+                match component.save(world, entity) {
+                    Some(Ok(value)) => {
+                        components.insert(component.name().to_owned(), value);
+                    }
+                    Some(Err(e)) => {
+                        return Err(Error::scene(
+                            "<scene>",
+                            format!("{} of entity {}: {}", component.name(), entity.id(), e),
+                        ))
+                    }
+                    None => {}
+                }
+            }
+            if !components.is_empty() {
+                entities.push(SceneEntity {
+                    id: entity.id(),
+                    components: components,
+                });
+            }
+        }
+
+        Ok(Scene {
+            version: self.version,
+            entities: entities,
+        })
+    }
+
+    /// Brings an older scene up to the current version
+    pub fn migrate(&self, scene: &mut Scene) -> Result<()> {
+        if scene.version > self.version {
+            return Err(Error::scene(
+                "<scene>",
+                format!("version {} is newer than {}", scene.version, self.version),
+            ));
+        }
+        while scene.version < self.version {
+            let migration = match self.migrations.get(&scene.version) {
+                Some(migration) => migration,
+                None => {
+                    return Err(Error::scene(
+                        "<scene>",
+                        format!("no migration from version {}", scene.version),
+                    ))
+                }
+            };
+            let from = scene.version;
+            migration(scene).map_err(|e| Error::scene("<scene>", format!("migrating from {}: {}", from, e)))?;
+            scene.version = from + 1;
+        }
+        Ok(())
+    }
+
+    /// Migrates the scene and spawns its entities. Gives the new entities, in the order
+    /// of the scene. If something is broken, none of the entities are left in the world.
+    pub fn from_scene(&self, world: &mut World, mut scene: Scene) -> Result<Vec<Entity>> {
+        self.migrate(&mut scene)?;
+
+        let mut map = HashMap::new();
+        let mut spawned = Vec::new();
+        for saved in scene.entities.iter() {
+            let entity = world.create_entity().build();
+            if map.insert(saved.id, entity).is_some() {
+                Self::despawn(world, &spawned);
+                return Err(Error::scene("<scene>", format!("entity {} is in the scene twice", saved.id)));
+            }
+            spawned.push(entity);
+        }
+
+        let result = entity::with_map(map, || self.load_components(world, &scene, &spawned));
+        match result {
+            Ok(()) => Ok(spawned),
+            Err(e) => {
+                Self::despawn(world, &spawned);
+                Err(Error::scene("<scene>", e))
+            }
+        }
+    }
+
+    fn load_components(
+        &self,
+        world: &mut World,
+        scene: &Scene,
+        spawned: &[Entity],
+    ) -> ::std::result::Result<(), String> {
+        for (saved, &entity) in scene.entities.iter().zip(spawned.iter()) {
+            for (name, value) in saved.components.iter() {
+                match self.components.iter().find(|component| component.name() == name) {
+                    Some(component) => component
+                        .load(world, entity, value.clone())
+                        .map_err(|e| format!("{} of entity {}: {}", name, saved.id, e))?,
+                    // Could be from a newer build, or a component that was dropped
+                    None => println!("Skipping unknown component {} of entity {}", name, saved.id),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn despawn(world: &mut World, entities: &[Entity]) -> () {
+        {
+            let all = world.entities();
+            for &entity in entities {
+                let _ = all.delete(entity);
+            }
+        }
+        world.maintain();
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, world: &World, path: P) -> Result<()> {
+        let name = path.as_ref().display().to_string();
+        let scene = self.to_scene(world).map_err(|e| with_path(e, &name))?;
+        let contents = scene
+            .to_string(SceneFormat::from_path(&name))
+            .map_err(|e| Error::scene(&name, e))?;
+
+        let io_error = |error| Error::Io {
+            path: name.clone(),
+            error: error,
+        };
+        let mut f = File::create(path.as_ref()).map_err(&io_error)?;
+        f.write_all(contents.as_bytes()).map_err(&io_error)?;
+        println!("Saved {} entities to {}", scene.entities.len(), name);
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(&self, world: &mut World, path: P) -> Result<Vec<Entity>> {
+        let name = path.as_ref().display().to_string();
+        let io_error = |error| Error::Io {
+            path: name.clone(),
+            error: error,
+        };
+        let mut f = File::open(path.as_ref()).map_err(&io_error)?;
+        let mut contents = String::new();
+        f.read_to_string(&mut contents).map_err(&io_error)?;
+
+        let scene = Scene::parse(&contents, SceneFormat::from_path(&name)).map_err(|e| Error::scene(&name, e))?;
+        self.from_scene(world, scene).map_err(|e| with_path(e, &name))
+    }
+}
+
+/// Puts the file name in errors that only knew they were about "<scene>"
+fn with_path(error: Error, path: &str) -> Error {
+    match error {
+        Error::Scene { message, .. } => Error::scene(path, message),
+        other => other,
+    }
+}
+
+/// Serde helpers for components that refer to other entities:
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Follow {
+///     #[serde(with = "cyberengine::scene::entity")]
+///     target: Entity,
+/// }
+/// ```
+///
+/// Entities are written as their id. Reading them only works while a scene is being
+/// loaded, that's when the new entities are known.
+pub mod entity {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use specs::Entity;
+
+    thread_local! {
+        /// Scene ids to the entities spawned for them, while loading
+        static ENTITY_MAP: RefCell<Option<HashMap<u32, Entity>>> = RefCell::new(None);
+    }
+
+    /// Runs `f` with the entity map in place
+    pub fn with_map<T, F: FnOnce() -> T>(map: HashMap<u32, Entity>, f: F) -> T {
+        ENTITY_MAP.with(|current| *current.borrow_mut() = Some(map));
+        let result = f();
+        ENTITY_MAP.with(|current| *current.borrow_mut() = None);
+        result
+    }
+
+    fn lookup(id: u32) -> ::std::result::Result<Entity, String> {
+        ENTITY_MAP.with(|current| match *current.borrow() {
+            Some(ref map) => match map.get(&id) {
+                Some(&entity) => Ok(entity),
+                None => Err(format!("entity {} is not in the scene", id)),
+            },
+            None => Err("entities can only be read while loading a scene".to_owned()),
+        })
+    }
+
+    pub fn serialize<S: Serializer>(entity: &Entity, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(entity.id())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Entity, D::Error> {
+        let id = u32::deserialize(deserializer)?;
+        lookup(id).map_err(D::Error::custom)
+    }
+
+    /// Same for `Option<Entity>`
+    pub mod option {
+        use serde::de::Error;
+        use serde::{Deserialize, Deserializer, Serializer};
+        use specs::Entity;
+
+        pub fn serialize<S: Serializer>(
+            entity: &Option<Entity>,
+            serializer: S,
+        ) -> ::std::result::Result<S::Ok, S::Error> {
+            match *entity {
+                Some(entity) => serializer.serialize_some(&entity.id()),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> ::std::result::Result<Option<Entity>, D::Error> {
+            match Option::<u32>::deserialize(deserializer)? {
+                Some(id) => super::lookup(id).map(Some).map_err(D::Error::custom),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::{self, Value};
+    use specs::{Component, Entity, Join, VecStorage, World};
+
+    use error::Error;
+    use resource::Identifier;
+    use scene::entity;
+    use systems::sprite::{Position, SpriteSpawn};
+    use super::{Scene, SceneEntity, SceneFormat, SceneRegistry};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Follow {
+        #[serde(with = "entity")]
+        target: Entity,
+        #[serde(with = "entity::option")]
+        fallback: Option<Entity>,
+    }
+
+    impl Component for Follow {
+        type Storage = VecStorage<Self>;
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<SpriteSpawn>();
+        world.register::<Follow>();
+        world
+    }
+
+    fn registry() -> SceneRegistry {
+        SceneRegistry::new()
+            .with::<Position>("Position")
+            .with::<SpriteSpawn>("SpriteSpawn")
+            .with::<Follow>("Follow")
+    }
+
+    fn alive(world: &World) -> usize {
+        (&*world.entities()).join().count()
+    }
+
+    fn round_trip(format: SceneFormat) -> () {
+        let mut saved = world();
+        saved.create_entity().with(Position { x: 1.5, y: -2. }).build();
+        // Nothing on this one gets saved, so it's left out
+        saved.create_entity().build();
+        saved
+            .create_entity()
+            .with(Position { x: 0., y: 10. })
+            .with(SpriteSpawn {
+                texture_identifier: Identifier::Image("test.jpg".to_owned()),
+            })
+            .build();
+
+        let contents = registry().to_scene(&saved).unwrap().to_string(format).unwrap();
+        let scene = Scene::parse(&contents, format).unwrap();
+        assert_eq!(scene.entities.len(), 2);
+
+        let mut loaded = world();
+        let spawned = registry().from_scene(&mut loaded, scene).unwrap();
+        assert_eq!(spawned.len(), 2);
+
+        let positions = loaded.read::<Position>();
+        let first = positions.get(spawned[0]).unwrap();
+        assert_eq!((first.x, first.y), (1.5, -2.));
+        let second = positions.get(spawned[1]).unwrap();
+        assert_eq!((second.x, second.y), (0., 10.));
+
+        let spawns = loaded.read::<SpriteSpawn>();
+        assert!(spawns.get(spawned[0]).is_none());
+        assert_eq!(
+            spawns.get(spawned[1]).unwrap().texture_identifier,
+            Identifier::Image("test.jpg".to_owned())
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(SceneFormat::Json);
+    }
+
+    #[test]
+    fn ron_round_trip() {
+        round_trip(SceneFormat::Ron);
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(SceneFormat::from_path("saves/quick.ron"), SceneFormat::Ron);
+        assert_eq!(SceneFormat::from_path("saves/quick.json"), SceneFormat::Json);
+        assert_eq!(SceneFormat::from_path("saves/quick"), SceneFormat::Json);
+    }
+
+    #[test]
+    fn entity_references_are_remapped() {
+        let mut saved = world();
+        let a = saved.create_entity().with(Position { x: 1., y: 1. }).build();
+        let b = saved
+            .create_entity()
+            .with(Follow {
+                target: a,
+                fallback: None,
+            })
+            .build();
+        saved
+            .create_entity()
+            .with(Follow {
+                target: b,
+                fallback: Some(a),
+            })
+            .build();
+        let scene = registry().to_scene(&saved).unwrap();
+
+        // So the new entities don't get the same ids as the saved ones
+        let mut loaded = world();
+        for _ in 0..5 {
+            loaded.create_entity().build();
+        }
+        let spawned = registry().from_scene(&mut loaded, scene).unwrap();
+        assert_eq!(spawned.len(), 3);
+        assert!(spawned[0].id() != a.id());
+
+        let follows = loaded.read::<Follow>();
+        let follow_b = follows.get(spawned[1]).unwrap();
+        assert_eq!(follow_b.target, spawned[0]);
+        assert_eq!(follow_b.fallback, None);
+        let follow_c = follows.get(spawned[2]).unwrap();
+        assert_eq!(follow_c.target, spawned[1]);
+        assert_eq!(follow_c.fallback, Some(spawned[0]));
+    }
+
+    #[test]
+    fn entities_only_load_with_a_scene() {
+        let follow: Result<Follow, _> = serde_json::from_str(r#"{"target": 0, "fallback": null}"#);
+        assert!(follow.is_err());
+    }
+
+    fn old_scene(version: u32, component: &str) -> Scene {
+        let mut components = BTreeMap::new();
+        components.insert(component.to_owned(), Value::from(5));
+        Scene {
+            version: version,
+            entities: vec![SceneEntity {
+                id: 0,
+                components: components,
+            }],
+        }
+    }
+
+    /// Version 1 called Health "Hp", and version 2 had it ten times smaller
+    fn migrating_registry() -> SceneRegistry {
+        SceneRegistry::new()
+            .with_version(3)
+            .with_migration(1, |scene| {
+                for entity in scene.entities.iter_mut() {
+                    if let Some(hp) = entity.components.remove("Hp") {
+                        entity.components.insert("Health".to_owned(), hp);
+                    }
+                }
+                Ok(())
+            })
+            .with_migration(2, |scene| {
+                for entity in scene.entities.iter_mut() {
+                    let health = match entity.components.get("Health").and_then(Value::as_i64) {
+                        Some(health) => health,
+                        None => return Err("no Health".to_owned()),
+                    };
+                    entity.components.insert("Health".to_owned(), Value::from(health * 10));
+                }
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn migrations_run_in_order() {
+        let mut scene = old_scene(1, "Hp");
+        migrating_registry().migrate(&mut scene).unwrap();
+        assert_eq!(scene.version, 3);
+        assert!(!scene.entities[0].components.contains_key("Hp"));
+        assert_eq!(scene.entities[0].components.get("Health"), Some(&Value::from(50)));
+
+        // Only the migrations it needs
+        let mut scene = old_scene(2, "Health");
+        migrating_registry().migrate(&mut scene).unwrap();
+        assert_eq!(scene.entities[0].components.get("Health"), Some(&Value::from(50)));
+
+        let mut scene = old_scene(3, "Health");
+        migrating_registry().migrate(&mut scene).unwrap();
+        assert_eq!(scene.entities[0].components.get("Health"), Some(&Value::from(5)));
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut scene = old_scene(4, "Health");
+        match migrating_registry().migrate(&mut scene) {
+            Err(Error::Scene { message, .. }) => assert!(message.contains("newer"), "{}", message),
+            other => panic!("expected a scene error, got {:?}", other),
+        }
+
+        let mut world = world();
+        assert!(migrating_registry().from_scene(&mut world, old_scene(4, "Health")).is_err());
+        assert_eq!(alive(&world), 0);
+    }
+
+    #[test]
+    fn missing_migration_is_rejected() {
+        let registry = SceneRegistry::new().with_version(3).with_migration(1, |_| Ok(()));
+        let mut scene = old_scene(1, "Hp");
+        match registry.migrate(&mut scene) {
+            Err(Error::Scene { message, .. }) => {
+                assert!(message.contains("no migration from version 2"), "{}", message)
+            }
+            other => panic!("expected a scene error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn broken_component_spawns_nothing() {
+        let scene = Scene::parse(
+            r#"{
+                "version": 1,
+                "entities": [
+                    { "id": 0, "components": { "Position": { "x": 1.0, "y": 2.0 } } },
+                    { "id": 1, "components": { "Position": "somewhere" } }
+                ]
+            }"#,
+            SceneFormat::Json,
+        ).unwrap();
+
+        let mut world = world();
+        world.create_entity().with(Position { x: 0., y: 0. }).build();
+        assert!(registry().from_scene(&mut world, scene).is_err());
+
+        // Only the entity that was there before is left
+        assert_eq!(alive(&world), 1);
+        let positions = world.read::<Position>();
+        assert_eq!((&positions).join().count(), 1);
+    }
+}
@@ -1,33 +1,75 @@
 //! Holds rendering context and stuff, IDK
 use std::time::Duration;
 
+use glutin;
+
 use graphics;
+use input::Input;
 use state;
+use ui::DebugUi;
 
 pub struct Screen {
     renderer: graphics::Renderer,
     statemanager: state::Manager,
+    debug_ui: Option<DebugUi>,
 }
 
 impl Screen {
-    pub fn new(renderer: graphics::Renderer) -> Screen {
+    pub fn new(mut renderer: graphics::Renderer, statemanager: state::Manager) -> Screen {
+        let debug_ui = match DebugUi::new(&mut renderer) {
+            Ok(debug_ui) => Some(debug_ui),
+            Err(e) => {
+                println!("No debug UI: {}", e);
+                None
+            }
+        };
+
         Screen {
             renderer: renderer,
-            statemanager: state::Manager::new(),
+            statemanager: statemanager,
+            debug_ui: debug_ui,
         }
     }
 
-    pub fn update(&mut self, delta: Duration) {
-        self.statemanager.update(delta);
+    pub fn update(&mut self, delta: Duration, input: &Input) {
+        self.statemanager.update(delta, input);
     }
 
-    pub fn render(mut self) -> Screen {
+    /// Renders the active state, and the debug UI on top of it.
+    /// `dt` is the real time since the last frame, in seconds.
+    pub fn render(mut self, alpha: f32, window: &glutin::GlWindow, dt: f32) -> Screen {
+        self.renderer.reload_shaders();
         self.renderer.clear();
-        self.renderer = self.statemanager.render(self.renderer);
+        self.renderer = self.statemanager.render(self.renderer, alpha);
+
+        if let Some(ref mut debug_ui) = self.debug_ui {
+            let statemanager = &mut self.statemanager;
+            debug_ui.render(&mut self.renderer, window, dt, |ui| statemanager.render_ui(ui));
+        }
+
         self.renderer.flush();
         self
     }
 
+    /// Updates the render targets to the new window size, and tells the states about it.
+    pub fn resize(&mut self, window: &glutin::GlWindow, width: u32, height: u32) -> () {
+        self.renderer.resize(window);
+        if let Some(ref mut debug_ui) = self.debug_ui {
+            debug_ui.resize(&mut self.renderer, width, height);
+        }
+        self.statemanager.resize(width, height);
+    }
+
+    /// Reloads shaders and assets when they change, checking every `interval`
+    pub fn enable_hot_reload(&mut self, interval: Duration) -> () {
+        self.renderer.enable_hot_reload(interval);
+        self.statemanager.enable_hot_reload(interval);
+    }
+
+    pub fn debug_ui(&mut self) -> Option<&mut DebugUi> {
+        self.debug_ui.as_mut()
+    }
+
     pub fn cleanup(&mut self) -> () {
         self.renderer.cleanup();
     }
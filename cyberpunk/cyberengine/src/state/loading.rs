@@ -0,0 +1,162 @@
+//! Preloading states in the background
+//!
+//! A state with a preload hook isn't entered right away. The hook starts the loading,
+//! by asking the AssetServer for textures and spawning jobs for anything else, and
+//! the loading screen of the state (if it has one) is shown until all of it is done.
+//!
+//! ```ignore
+//! GameState::new("level", init, systems)
+//!     .with_loading_screen("loading")
+//!     .with_preload(|world, preloader| {
+//!         world.write_resource::<AssetServer>().load_texture("tiles.png");
+//!         preloader.spawn(
+//!             || registry.load::<Tilemap>("maps/offices.tmx"),
+//!             |map, world| match map {
+//!                 Ok(map) => world.add_resource(map),
+//!                 Err(e) => println!("No map for the level: {}", e),
+//!             },
+//!         );
+//!     })
+//! ```
+//!
+//! `then` runs on the main thread, so it shouldn't panic. A job that panics is counted
+//! as done, so the state still gets entered, just without whatever the job was loading.
+//!
+//! Loading screens read LoadingProgress from their world to draw a progress bar.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rayon;
+use specs;
+
+use resource::assets::AssetServer;
+
+/// Runs on the main thread with the world of the finished job
+type Finisher = Box<FnMut(&mut specs::World) + Send>;
+
+/// Hands out background jobs for a preload hook, and keeps track of them
+pub struct Preloader {
+    jobs: usize,
+    done: Arc<AtomicUsize>,
+    finished: Arc<Mutex<Vec<Finisher>>>,
+    /// Why the jobs that panicked did
+    failed: Arc<Mutex<Vec<String>>>,
+    /// Textures the AssetServer was loading when the hook returned
+    textures: usize,
+}
+
+impl Preloader {
+    pub fn new() -> Preloader {
+        Preloader {
+            jobs: 0,
+            done: Arc::new(AtomicUsize::new(0)),
+            finished: Arc::new(Mutex::new(Vec::new())),
+            failed: Arc::new(Mutex::new(Vec::new())),
+            textures: 0,
+        }
+    }
+
+    /// Runs `job` on the thread pool, and then `then` with its result and the world of
+    /// the state, on the main thread. Put whatever was loaded into the world in `then`.
+    /// If `job` panics, `then` isn't run and the panic ends up in `failed`.
+    pub fn spawn<T, J, F>(&mut self, job: J, then: F) -> ()
+    where
+        T: Send + 'static,
+        J: FnOnce() -> T + Send + 'static,
+        F: FnOnce(T, &mut specs::World) + Send + 'static,
+    {
+        self.jobs += 1;
+        let done = self.done.clone();
+        let finished = self.finished.clone();
+        let failed = self.failed.clone();
+        rayon::spawn(move || {
+            // Panics would abort the whole game on the thread pool
+            match panic::catch_unwind(AssertUnwindSafe(job)) {
+                Ok(result) => {
+                    let mut result = Some(result);
+                    let mut then = Some(then);
+                    finished.lock().unwrap().push(Box::new(move |world: &mut specs::World| {
+                        if let (Some(result), Some(then)) = (result.take(), then.take()) {
+                            then(result, world);
+                        }
+                    }));
+                }
+                Err(cause) => {
+                    let message = panic_message(&*cause);
+                    println!("Preload job panicked: {}", message);
+                    failed.lock().unwrap().push(message);
+                }
+            }
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Called once the hook has returned, so the textures it asked for get counted
+    pub fn started(&mut self, world: &specs::World) -> () {
+        self.textures = world.read_resource::<AssetServer>().loading();
+    }
+
+    /// Runs the `then`s of the jobs that have finished
+    pub fn finish_jobs(&mut self, world: &mut specs::World) -> () {
+        let finished: Vec<Finisher> = self.finished.lock().unwrap().drain(..).collect();
+        for mut finisher in finished {
+            finisher(world);
+        }
+    }
+
+    /// Why the jobs that panicked did. They count as done.
+    pub fn failed(&self) -> Vec<String> {
+        self.failed.lock().unwrap().clone()
+    }
+
+    /// 0.0 to 1.0
+    pub fn progress(&self, world: &specs::World) -> f32 {
+        let total = self.jobs + self.textures;
+        if total == 0 {
+            return 1.;
+        }
+        let textures_left = world.read_resource::<AssetServer>().loading().min(self.textures);
+        let done = self.done.load(Ordering::SeqCst) + self.textures - textures_left;
+        done as f32 / total as f32
+    }
+
+    /// Every job has finished and every texture is on the GPU
+    pub fn is_done(&self, world: &specs::World) -> bool {
+        self.done.load(Ordering::SeqCst) == self.jobs && self.finished.lock().unwrap().is_empty()
+            && world.read_resource::<AssetServer>().loading() == 0
+    }
+}
+
+fn panic_message(cause: &(Any + Send)) -> String {
+    match cause.downcast_ref::<&str>() {
+        Some(message) => (*message).to_owned(),
+        None => match cause.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_owned(),
+        },
+    }
+}
+
+/// How the loading is going, for loading screens
+#[derive(Debug, Clone)]
+pub struct LoadingProgress {
+    /// The state being loaded, None when nothing is
+    pub state: Option<&'static str>,
+    /// 0.0 to 1.0
+    pub progress: f32,
+    /// Jobs that panicked, the state will be missing whatever they were loading
+    pub failed: usize,
+}
+
+impl LoadingProgress {
+    pub fn new() -> LoadingProgress {
+        LoadingProgress {
+            state: None,
+            progress: 0.,
+            failed: 0,
+        }
+    }
+}
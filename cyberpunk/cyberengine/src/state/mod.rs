@@ -0,0 +1,889 @@
+//! GameStates, and the stack they live in.
+//!
+//! The Manager keeps a stack of states. Only the top one gets input, but states can
+//! let the ones below keep updating or rendering, so a pause menu can be drawn over
+//! the frozen game. States updating below the top see an Input where nothing is
+//! pressed, so they don't react to keys meant for the overlay. Changes to the stack can go through a Transition, like a fade.
+//! Systems ask for changes through the StateTransition resource, see `request`.
+//! States with a preload hook are loaded in the background first, see `loading`.
+
+// TODO: Figure out what is the best way for defining states.
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use imgui::Ui;
+use specs;
+
+use graphics;
+use graphics::camera::Camera2D;
+use input::Input;
+use systems::{DeltaTime, ElapsedTime, HotReload, Interpolation, WindowResized};
+use systems::collision::CollisionGrid;
+use resource::assets::AssetServer;
+use ui::{DebugWindows, Inspector};
+
+pub mod loading;
+pub mod request;
+pub mod transition;
+
+pub use self::loading::{LoadingProgress, Preloader};
+pub use self::request::{StateData, StateRequest, StateTransition};
+pub use self::transition::Transition;
+
+/// Draws debug UI for a state, called every frame the debug overlay is visible.
+pub type UiCallback = Box<FnMut(&Ui, &mut specs::World)>;
+
+/// Lifecycle hook, like spawning the entities of a level on enter
+pub type Hook = Box<FnMut(&mut specs::World)>;
+
+/// Starts the loading of a state, see `loading`
+pub type PreloadHook = Box<FnMut(&mut specs::World, &mut Preloader)>;
+
+pub struct GameState {
+    name: &'static str,
+    loaded: bool,
+    pub world: specs::World,
+    dispatcher: specs::Dispatcher<'static, 'static>,
+    rendering_systems: Vec<Box<graphics::RenderingSystem>>,
+    ui_callbacks: Vec<UiCallback>,
+    /// Do the states below this one keep updating
+    update_below: bool,
+    /// Are the states below this one drawn, under this one
+    render_below: bool,
+    preload_hooks: Vec<PreloadHook>,
+    enter_hooks: Vec<Hook>,
+    leave_hooks: Vec<Hook>,
+    unload_hooks: Vec<Hook>,
+    /// Shown while this state preloads
+    loading_screen: Option<&'static str>,
+    unload_on_leave: bool,
+}
+
+impl GameState {
+    pub fn new<'s, F>(name: &'static str, world_init: F, rendering_systems: Vec<Box<graphics::RenderingSystem>>)
+    ->
+    GameState
+where
+    F: Fn(&mut specs::World) -> specs::Dispatcher<'static, 'static> {
+        let mut world = specs::World::new();
+        world.add_resource(DeltaTime(Duration::new(0, 0)));
+        world.add_resource(ElapsedTime(Duration::new(0, 0)));
+        world.add_resource(Interpolation(0.));
+        world.add_resource(WindowResized(None));
+        world.add_resource(Input::default());
+        world.add_resource(CollisionGrid::new());
+        // Sized to the window when the state is added to the Manager
+        world.add_resource(Camera2D::new(0., 0.));
+        world.add_resource(DebugWindows::new());
+        // The AssetServer is a share of the Manager's, added with the state
+        world.add_resource(HotReload(None));
+        world.add_resource(StateTransition::new());
+        world.add_resource(StateData::new());
+        world.add_resource(LoadingProgress::new());
+        let dispatcher = world_init(&mut world);
+
+        GameState {
+            name: name,
+            loaded: false,
+            world: world,
+            dispatcher: dispatcher,
+            rendering_systems: rendering_systems,
+            ui_callbacks: Vec::new(),
+            update_below: false,
+            render_below: false,
+            preload_hooks: Vec::new(),
+            enter_hooks: Vec::new(),
+            leave_hooks: Vec::new(),
+            unload_hooks: Vec::new(),
+            loading_screen: None,
+            unload_on_leave: false,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Keeps the states below this one updating while this one is on top. They don't get
+    /// any input though, only the top state does.
+    pub fn with_update_below(mut self, update_below: bool) -> GameState {
+        self.update_below = update_below;
+        self
+    }
+
+    /// Draws the states below this one first, for states that don't cover the whole screen
+    pub fn with_render_below(mut self, render_below: bool) -> GameState {
+        self.render_below = render_below;
+        self
+    }
+
+    /// Drawn over the states below it, which are frozen. Like a pause menu.
+    pub fn as_overlay(self) -> GameState {
+        self.with_render_below(true).with_update_below(false)
+    }
+
+    /// Adds a resource to the world of this state, replacing the one that is there
+    pub fn with_resource<R: Any + Send + Sync>(mut self, resource: R) -> GameState {
+        self.world.add_resource(resource);
+        self
+    }
+
+    /// Adds a rendering system, drawn after the ones given to `new`
+    pub fn with_rendering_system<R: graphics::RenderingSystem + 'static>(mut self, system: R) -> GameState {
+        self.rendering_systems.push(Box::new(system));
+        self
+    }
+
+    /// Starts loading the state the first time it is pushed. The state is entered once
+    /// everything the hook started has finished.
+    pub fn with_preload<F>(mut self, f: F) -> GameState
+    where
+        F: FnMut(&mut specs::World, &mut Preloader) + 'static,
+    {
+        self.preload_hooks.push(Box::new(f));
+        self
+    }
+
+    /// Called every time the state is pushed on the stack
+    pub fn with_enter<F: FnMut(&mut specs::World) + 'static>(mut self, f: F) -> GameState {
+        self.enter_hooks.push(Box::new(f));
+        self
+    }
+
+    /// Called every time the state is taken off the stack
+    pub fn with_leave<F: FnMut(&mut specs::World) + 'static>(mut self, f: F) -> GameState {
+        self.leave_hooks.push(Box::new(f));
+        self
+    }
+
+    /// Called when the state is unloaded, before its textures are dropped
+    pub fn with_unload<F: FnMut(&mut specs::World) + 'static>(mut self, f: F) -> GameState {
+        self.unload_hooks.push(Box::new(f));
+        self
+    }
+
+    /// State shown on top of the stack while this one preloads. Loading screens
+    /// themselves are entered without preloading.
+    pub fn with_loading_screen(mut self, name: &'static str) -> GameState {
+        self.loading_screen = Some(name);
+        self
+    }
+
+    /// Unloads the state when it is left, so it is loaded again the next time
+    pub fn with_unload_on_leave(mut self, unload_on_leave: bool) -> GameState {
+        self.unload_on_leave = unload_on_leave;
+        self
+    }
+
+    /// Adds a callback that draws debug UI with imgui
+    pub fn with_ui<F>(mut self, f: F) -> GameState
+    where
+        F: FnMut(&Ui, &mut specs::World) + 'static,
+    {
+        self.ui_callbacks.push(Box::new(f));
+        self
+    }
+
+    /// Adds an entity inspector window for the world of this state
+    pub fn with_inspector(self, mut inspector: Inspector) -> GameState {
+        self.with_ui(move |ui, world| inspector.draw(ui, world))
+    }
+
+    /// Starts loading the state. Gives the Preloader to keep an eye on, or None if
+    /// there was nothing to load and the state is ready.
+    fn preload(&mut self) -> Option<Preloader> {
+        if self.preload_hooks.is_empty() {
+            self.loaded = true;
+            return None;
+        }
+
+        println!("Preloading {}", self.name);
+        let mut preloader = Preloader::new();
+        for hook in self.preload_hooks.iter_mut() {
+            hook(&mut self.world, &mut preloader);
+        }
+        preloader.started(&self.world);
+        Some(preloader)
+    }
+
+    /// Initializes the State, called every time the state is pushed on the stack.
+    fn enter(&mut self) -> () {
+        for hook in self.enter_hooks.iter_mut() {
+            hook(&mut self.world);
+        }
+        self.world.maintain();
+    }
+
+    /// Updates the game state.
+    fn update(&mut self, dt: Duration, input: &Input) -> () {
+        {
+            let mut current = self.world.write_resource::<Input>();
+            *current = input.clone();
+        }
+        {
+            let mut delta = self.world.write_resource::<DeltaTime>();
+            *delta = DeltaTime(dt);
+        }
+        {
+            let mut elapsed = self.world.write_resource::<ElapsedTime>();
+            elapsed.0 += dt;
+        }
+        self.world.write_resource::<DebugWindows>().clear();
+        self.dispatcher.dispatch(&self.world.res);
+        self.world.maintain();
+        self.world.write_resource::<WindowResized>().0 = None;
+    }
+
+    /// Lets the systems know they should reload assets that change.
+    fn enable_hot_reload(&mut self, interval: Duration) -> () {
+        self.world.write_resource::<HotReload>().0 = Some(interval);
+    }
+
+    /// Lets the systems know the window size changed.
+    fn resize(&mut self, width: u32, height: u32) -> () {
+        self.world.write_resource::<WindowResized>().0 = Some((width, height));
+        self.set_viewport(width, height);
+    }
+
+    /// Makes the camera cover a window of this size
+    fn set_viewport(&mut self, width: u32, height: u32) -> () {
+        self.world.write_resource::<Camera2D>().viewport = (width as f32, height as f32);
+    }
+
+    /// Renders the scene. `alpha` is how far we are between the last tick and the next one.
+    fn render(&mut self, mut renderer: graphics::Renderer, alpha: f32) -> graphics::Renderer {
+        {
+            let mut interpolation = self.world.write_resource::<Interpolation>();
+            *interpolation = Interpolation(alpha);
+        }
+        {
+            // The camera always covers the whole window
+            let mut camera = self.world.write_resource::<Camera2D>();
+            let (width, height) = renderer.target_size();
+            camera.viewport = (width as f32, height as f32);
+            renderer.set_camera(&camera);
+        }
+        for rs in self.rendering_systems.iter_mut(){
+            let res = &mut self.world.res;
+            renderer = rs.render_world(res, renderer);
+        }
+        renderer
+    }
+
+    /// Draws the UI callbacks, and whatever the systems put in DebugWindows.
+    fn render_ui(&mut self, ui: &Ui) -> () {
+        for callback in self.ui_callbacks.iter_mut() {
+            callback(ui, &mut self.world);
+        }
+        self.world.read_resource::<DebugWindows>().draw(ui);
+    }
+
+    /// Called when the State is taken off the stack.
+    fn leave(&mut self) -> () {
+        for hook in self.leave_hooks.iter_mut() {
+            hook(&mut self.world);
+        }
+        self.world.maintain();
+        if self.unload_on_leave {
+            self.unload();
+        }
+    }
+
+    /// Runs the unload hooks and drops the textures of the state
+    fn unload(&mut self) -> () {
+        if !self.loaded {
+            return;
+        }
+        for hook in self.unload_hooks.iter_mut() {
+            hook(&mut self.world);
+        }
+        self.world.write_resource::<AssetServer>().unload_all();
+        self.loaded = false;
+    }
+}
+
+impl Drop for GameState {
+    fn drop(&mut self) {
+        self.unload();
+    }
+}
+
+/// A change to the state stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackOp {
+    Push(&'static str),
+    Pop,
+    /// Pops the top state and pushes this one
+    Replace(&'static str),
+    /// Pops every state
+    Clear,
+}
+
+/// A state that is preloading, and where it goes once it is done
+struct Loading {
+    name: &'static str,
+    preloader: Preloader,
+    data: Option<Box<Any + Send>>,
+    screen: Option<&'static str>,
+}
+
+pub struct Manager {
+    states: HashMap<&'static str, GameState>,
+    /// Bottom first
+    stack: Vec<&'static str>,
+    /// Applied after the next update, in order
+    pending: VecDeque<StateRequest>,
+    /// The running transition, and the change it is hiding
+    transition: Option<(transition::Running, Option<StateRequest>)>,
+    /// Changes wait while a state is loading
+    loading: Option<Loading>,
+    hot_reload: Option<Duration>,
+    /// Size of the window, for the cameras of the states
+    window_size: (u32, u32),
+    /// Every state gets a share of this
+    assets: AssetServer,
+}
+
+impl Manager {
+    /// No states, add some with `add_state` and `push` one.
+    /// `width` and `height` are the size of the window.
+    pub fn new(width: u32, height: u32, assets: AssetServer) -> Manager {
+        Manager {
+            states: HashMap::new(),
+            stack: Vec::new(),
+            pending: VecDeque::new(),
+            transition: None,
+            loading: None,
+            hot_reload: None,
+            window_size: (width, height),
+            assets: assets,
+        }
+    }
+
+    /// Adds a state, and gives it a share of the AssetServer. The share is only
+    /// there from now on, so the `world_init` of the state can't load textures.
+    pub fn add_state(&mut self, mut state: GameState) -> () {
+        state.world.add_resource(self.assets.share());
+        if let Some(interval) = self.hot_reload {
+            state.enable_hot_reload(interval);
+        }
+        state.set_viewport(self.window_size.0, self.window_size.1);
+        self.states.insert(state.name, state);
+    }
+
+    /// Drops a state, which unloads it. States on the stack can't be removed.
+    pub fn remove_state(&mut self, name: &'static str) -> () {
+        let loading = match self.loading {
+            Some(ref loading) => loading.name == name || loading.screen == Some(name),
+            None => false,
+        };
+        if loading || self.stack.contains(&name) {
+            println!("State {} is in use, not removing it", name);
+            return;
+        }
+        self.states.remove(name);
+    }
+
+    /// Is a state being preloaded
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Enables hot reloading for every state, including ones added later
+    pub fn enable_hot_reload(&mut self, interval: Duration) -> () {
+        self.hot_reload = Some(interval);
+        self.assets.enable_hot_reload(interval);
+        for state in self.states.values_mut() {
+            state.enable_hot_reload(interval);
+        }
+    }
+
+    /// The state on top of the stack
+    pub fn current(&self) -> Option<&'static str> {
+        self.stack.last().cloned()
+    }
+
+    /// The names of the states on the stack, bottom first
+    pub fn stack(&self) -> &[&'static str] {
+        &self.stack
+    }
+
+    pub fn push(&mut self, name: &'static str) -> () {
+        self.change(StackOp::Push(name), None);
+    }
+
+    pub fn pop(&mut self) -> () {
+        self.change(StackOp::Pop, None);
+    }
+
+    pub fn replace(&mut self, name: &'static str) -> () {
+        self.change(StackOp::Replace(name), None);
+    }
+
+    pub fn clear(&mut self) -> () {
+        self.change(StackOp::Clear, None);
+    }
+
+    /// Same as `replace`
+    pub fn switch_state(&mut self, name: &'static str) -> () {
+        self.replace(name);
+    }
+
+    /// Changes the stack after the next update, behind `transition` if there is one.
+    /// Changes wait for the running transition to finish. Like with `request`,
+    /// changes to states that don't exist are ignored.
+    pub fn change(&mut self, op: StackOp, transition: Option<Transition>) -> () {
+        let mut request = StateRequest::new(op);
+        request.transition = transition;
+        self.request(request);
+    }
+
+    /// Like `change`, but with data for the next state. Requests for states that
+    /// don't exist are ignored.
+    pub fn request(&mut self, request: StateRequest) -> () {
+        match request.op {
+            StackOp::Push(name) | StackOp::Replace(name) => if !self.states.contains_key(name) {
+                println!("No state called {}, ignoring {:?}", name, request.op);
+                return;
+            },
+            StackOp::Pop | StackOp::Clear => {}
+        }
+        self.pending.push_back(request);
+    }
+
+    pub fn update(&mut self, delta: Duration, input: &Input) -> () {
+        // Top down, until a state doesn't let the ones below it update
+        let no_input = Input::default();
+        let mut requests = Vec::new();
+        for (i, &name) in self.stack.iter().rev().enumerate() {
+            let state = self.states.get_mut(name).unwrap();
+            state.update(delta, if i == 0 { input } else { &no_input });
+            requests.extend(state.world.write_resource::<StateTransition>().drain());
+            if !state.update_below {
+                break;
+            }
+        }
+        for request in requests {
+            self.request(request);
+        }
+
+        let mut finished = false;
+        if let Some((ref mut running, ref mut request)) = self.transition {
+            if running.advance(delta) {
+                if let Some(request) = request.take() {
+                    Self::apply(&mut self.states, &mut self.stack, &mut self.loading, request.op, request.data);
+                }
+            }
+            finished = running.is_finished();
+        }
+        if finished {
+            self.transition = None;
+        }
+
+        self.update_loading();
+
+        while self.transition.is_none() && self.loading.is_none() {
+            match self.pending.pop_front() {
+                Some(request) => match request.transition {
+                    Some(transition) => {
+                        self.transition = Some((transition::Running::new(transition), Some(request)));
+                    }
+                    None => Self::apply(&mut self.states, &mut self.stack, &mut self.loading, request.op, request.data),
+                },
+                None => break,
+            }
+        }
+    }
+
+    /// Finishes the jobs of the loading state, lets the stack know how it's going,
+    /// and pushes the state once it is done.
+    fn update_loading(&mut self) -> () {
+        let (name, progress, failed, done) = match self.loading {
+            Some(ref mut loading) => {
+                let state = self.states.get_mut(loading.name).unwrap();
+                loading.preloader.finish_jobs(&mut state.world);
+                (
+                    loading.name,
+                    loading.preloader.progress(&state.world),
+                    loading.preloader.failed(),
+                    loading.preloader.is_done(&state.world),
+                )
+            }
+            None => return,
+        };
+
+        for stacked in self.stack.iter() {
+            let state = self.states.get_mut(stacked).unwrap();
+            let mut loading_progress = state.world.write_resource::<LoadingProgress>();
+            loading_progress.state = Some(name);
+            loading_progress.progress = progress;
+            loading_progress.failed = failed.len();
+        }
+
+        if !done {
+            return;
+        }
+
+        let loading = self.loading.take().unwrap();
+        if failed.is_empty() {
+            println!("{} loaded", name);
+        } else {
+            println!("{} loaded, but {} preload jobs failed:", name, failed.len());
+            for message in failed.iter() {
+                println!("    {}", message);
+            }
+        }
+        self.states.get_mut(name).unwrap().loaded = true;
+        if let Some(screen) = loading.screen {
+            if self.stack.last() == Some(&screen) {
+                self.stack.pop();
+                self.states.get_mut(screen).unwrap().leave();
+            }
+        }
+        for stacked in self.stack.iter().chain(loading.screen.iter()) {
+            let state = self.states.get_mut(stacked).unwrap();
+            *state.world.write_resource::<LoadingProgress>() = LoadingProgress::new();
+        }
+        Self::push_loaded(&mut self.states, &mut self.stack, name, loading.data);
+    }
+
+    /// Changes the stack. `data` goes to the state that ends up on top.
+    fn apply(
+        states: &mut HashMap<&'static str, GameState>,
+        stack: &mut Vec<&'static str>,
+        loading: &mut Option<Loading>,
+        op: StackOp,
+        data: Option<Box<Any + Send>>,
+    ) -> () {
+        match op {
+            StackOp::Push(name) => {
+                if stack.contains(&name) {
+                    println!("State {} is already on the stack", name);
+                    return;
+                }
+                let preloading = {
+                    let state = states.get_mut(name).unwrap();
+                    if state.loaded {
+                        None
+                    } else {
+                        state.preload().map(|preloader| (preloader, state.loading_screen))
+                    }
+                };
+                match preloading {
+                    Some((preloader, screen)) => {
+                        *loading = Some(Loading {
+                            name: name,
+                            preloader: preloader,
+                            data: data,
+                            screen: screen,
+                        });
+                        if let Some(screen) = screen {
+                            Self::push_loaded(states, stack, screen, None);
+                        }
+                    }
+                    None => Self::push_loaded(states, stack, name, data),
+                }
+            }
+            StackOp::Pop => {
+                match stack.pop() {
+                    Some(name) => states.get_mut(name).unwrap().leave(),
+                    None => println!("Tried to pop a state off an empty stack"),
+                }
+                if data.is_some() {
+                    match stack.last() {
+                        Some(name) => states.get_mut(name).unwrap().world.write_resource::<StateData>().set(data),
+                        None => println!("Nobody to give the state data to, dropping it"),
+                    }
+                }
+            }
+            StackOp::Replace(name) => {
+                if let Some(name) = stack.pop() {
+                    states.get_mut(name).unwrap().leave();
+                }
+                Self::apply(states, stack, loading, StackOp::Push(name), data);
+            }
+            StackOp::Clear => {
+                while let Some(name) = stack.pop() {
+                    states.get_mut(name).unwrap().leave();
+                }
+                if data.is_some() {
+                    println!("Nobody to give the state data to, dropping it");
+                }
+            }
+        }
+    }
+
+    /// Enters a state and puts it on top, without preloading
+    fn push_loaded(
+        states: &mut HashMap<&'static str, GameState>,
+        stack: &mut Vec<&'static str>,
+        name: &'static str,
+        data: Option<Box<Any + Send>>,
+    ) -> () {
+        if stack.contains(&name) {
+            println!("State {} is already on the stack", name);
+            return;
+        }
+        let state = states.get_mut(name).unwrap();
+        // Before enter, so it can use the data
+        state.world.write_resource::<StateData>().set(data);
+        state.enter();
+        stack.push(name);
+    }
+
+    /// Index of the lowest state that is drawn
+    fn lowest_visible(&self) -> usize {
+        let mut lowest = self.stack.len();
+        for (i, name) in self.stack.iter().enumerate().rev() {
+            lowest = i;
+            if !self.states[name].render_below {
+                break;
+            }
+        }
+        lowest
+    }
+
+    /// Passes a window resize to every state on the stack. The cameras of the other
+    /// states are resized too, so they are right when the state is pushed.
+    pub fn resize(&mut self, width: u32, height: u32) -> () {
+        self.window_size = (width, height);
+        for (name, state) in self.states.iter_mut() {
+            if self.stack.contains(name) {
+                state.resize(width, height);
+            } else {
+                state.set_viewport(width, height);
+            }
+        }
+    }
+
+    /// Renders the visible states bottom up, and the running transition over them
+    pub fn render(&mut self, mut renderer: graphics::Renderer, alpha: f32) -> graphics::Renderer {
+        // Once for every state, including the loading one that isn't drawn
+        self.assets.upload(&mut renderer.factory);
+
+        let lowest = self.lowest_visible();
+        for name in self.stack[lowest..].iter() {
+            let state = self.states.get_mut(name).unwrap();
+            renderer = state.render(renderer, alpha);
+        }
+        if let Some((ref running, _)) = self.transition {
+            running.render(&mut renderer);
+        }
+        renderer
+    }
+
+    /// Debug UI of every state on the stack, so the frozen ones can be inspected too
+    pub fn render_ui(&mut self, ui: &Ui) -> () {
+        for name in self.stack.iter() {
+            let state = self.states.get_mut(name).unwrap();
+            state.render_ui(ui);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use specs::{DispatcherBuilder, FetchMut, System};
+
+    use input::Input;
+    use resource::AssetRegistry;
+    use resource::assets::AssetServer;
+    use super::{GameState, Manager};
+
+    /// How many times the world of a state was updated
+    struct Updates(u32);
+
+    struct CountUpdates;
+
+    impl<'a> System<'a> for CountUpdates {
+        type SystemData = FetchMut<'a, Updates>;
+
+        fn run(&mut self, mut updates: Self::SystemData) {
+            updates.0 += 1;
+        }
+    }
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// A state that counts its updates, and logs when it is entered and left
+    fn state(name: &'static str, log: &Log) -> GameState {
+        let (enter, leave) = (log.clone(), log.clone());
+        GameState::new(
+            name,
+            |world| {
+                world.add_resource(Updates(0));
+                DispatcherBuilder::new()
+                    .add(CountUpdates, "count_updates", &[])
+                    .build()
+            },
+            Vec::new(),
+        ).with_enter(move |_| enter.borrow_mut().push(format!("enter {}", name)))
+            .with_leave(move |_| leave.borrow_mut().push(format!("leave {}", name)))
+    }
+
+    fn manager(states: Vec<GameState>) -> Manager {
+        let mut manager = Manager::new(1280, 720, AssetServer::with_registry(AssetRegistry::new()));
+        for state in states {
+            manager.add_state(state);
+        }
+        manager
+    }
+
+    fn tick(manager: &mut Manager) -> () {
+        manager.update(Duration::from_millis(10), &Input::default());
+    }
+
+    fn updates(manager: &Manager, name: &'static str) -> u32 {
+        manager.states[name].world.read_resource::<Updates>().0
+    }
+
+    /// Updates once and tells which states were updated
+    fn updated(manager: &mut Manager) -> Vec<&'static str> {
+        let names = ["a", "b", "c"];
+        let before: Vec<u32> = names.iter().map(|name| updates(manager, *name)).collect();
+        tick(manager);
+        let manager = &*manager;
+        names
+            .iter()
+            .zip(before)
+            .filter(|&(name, before)| updates(manager, *name) > before)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    fn visible(manager: &Manager) -> Vec<&'static str> {
+        manager.stack[manager.lowest_visible()..].to_vec()
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        log.borrow_mut().drain(..).collect()
+    }
+
+    #[test]
+    fn stack_operations_enter_and_leave_in_order() {
+        let log = Log::default();
+        let mut manager = manager(vec![state("a", &log), state("b", &log), state("c", &log)]);
+
+        manager.push("a");
+        // Nothing changes until the update
+        assert!(manager.stack().is_empty());
+        tick(&mut manager);
+        assert_eq!(manager.stack(), &["a"]);
+        assert_eq!(take(&log), vec!["enter a"]);
+
+        manager.push("b");
+        manager.replace("c");
+        tick(&mut manager);
+        assert_eq!(manager.stack(), &["a", "c"]);
+        assert_eq!(take(&log), vec!["enter b", "leave b", "enter c"]);
+
+        manager.pop();
+        manager.push("b");
+        tick(&mut manager);
+        assert_eq!(manager.stack(), &["a", "b"]);
+        assert_eq!(manager.current(), Some("b"));
+        assert_eq!(take(&log), vec!["leave c", "enter b"]);
+
+        manager.clear();
+        tick(&mut manager);
+        assert!(manager.stack().is_empty());
+        assert_eq!(manager.current(), None);
+        assert_eq!(take(&log), vec!["leave b", "leave a"]);
+
+        // Popping an empty stack is a no-op
+        manager.pop();
+        tick(&mut manager);
+        assert!(take(&log).is_empty());
+    }
+
+    #[test]
+    fn states_are_only_on_the_stack_once() {
+        let log = Log::default();
+        let mut manager = manager(vec![state("a", &log), state("b", &log)]);
+        manager.push("a");
+        manager.push("b");
+        manager.push("a");
+        tick(&mut manager);
+        assert_eq!(manager.stack(), &["a", "b"]);
+        assert_eq!(take(&log), vec!["enter a", "enter b"]);
+    }
+
+    #[test]
+    fn unknown_states_are_ignored() {
+        let log = Log::default();
+        let mut manager = manager(vec![state("a", &log)]);
+        manager.push("a");
+        tick(&mut manager);
+        take(&log);
+
+        manager.push("nope");
+        // The current state isn't popped for a state that doesn't exist
+        manager.replace("nope");
+        tick(&mut manager);
+        assert_eq!(manager.stack(), &["a"]);
+        assert!(take(&log).is_empty());
+    }
+
+    #[test]
+    fn only_the_top_state_updates_by_default() {
+        let log = Log::default();
+        let mut manager = manager(vec![state("a", &log), state("b", &log), state("c", &log)]);
+        manager.push("a");
+        tick(&mut manager);
+        assert_eq!(updated(&mut manager), vec!["a"]);
+
+        manager.push("b");
+        tick(&mut manager);
+        assert_eq!(updated(&mut manager), vec!["b"]);
+        assert_eq!(visible(&manager), vec!["b"]);
+    }
+
+    #[test]
+    fn overlays_decide_what_goes_on_below_them() {
+        let log = Log::default();
+        let mut manager = manager(vec![
+            state("a", &log),
+            // Like a HUD over the game
+            state("b", &log).with_update_below(true).with_render_below(true),
+            // Like a pause menu
+            state("c", &log).as_overlay(),
+        ]);
+        manager.push("a");
+        manager.push("b");
+        tick(&mut manager);
+        assert_eq!(updated(&mut manager), vec!["a", "b"]);
+        assert_eq!(visible(&manager), vec!["a", "b"]);
+
+        manager.push("c");
+        tick(&mut manager);
+        // Everything is drawn, but the pause menu freezes the rest
+        assert_eq!(updated(&mut manager), vec!["c"]);
+        assert_eq!(visible(&manager), vec!["a", "b", "c"]);
+
+        manager.pop();
+        tick(&mut manager);
+        assert_eq!(updated(&mut manager), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn states_below_the_top_stop_at_the_first_one_that_hides_them() {
+        let log = Log::default();
+        let mut manager = manager(vec![
+            state("a", &log),
+            state("b", &log),
+            state("c", &log).with_update_below(true).with_render_below(true),
+        ]);
+        manager.push("a");
+        manager.push("b");
+        manager.push("c");
+        tick(&mut manager);
+        assert_eq!(updated(&mut manager), vec!["b", "c"]);
+        assert_eq!(visible(&manager), vec!["b", "c"]);
+    }
+}
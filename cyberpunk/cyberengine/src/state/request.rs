@@ -0,0 +1,130 @@
+//! State changes asked for by systems
+//!
+//! Systems can't reach the Manager, so they write to the StateTransition resource of
+//! their world instead. The Manager picks the requests up after the dispatch:
+//!
+//! ```ignore
+//! fn run(&mut self, (input, mut transition): Self::SystemData) {
+//!     if input.just_pressed("start") {
+//!         transition
+//!             .replace("game")
+//!             .with_transition(Transition::fade(Duration::from_millis(500)))
+//!             .with_data(Level(3));
+//!     }
+//! }
+//! ```
+//!
+//! The next state gets the data from its StateData resource, in `enter` or its systems.
+
+use std::any::Any;
+use std::sync::Mutex;
+
+use state::{StackOp, Transition};
+
+/// A change to the stack, with an optional transition and data for the next state
+pub struct StateRequest {
+    pub op: StackOp,
+    pub transition: Option<Transition>,
+    pub data: Option<Box<Any + Send>>,
+}
+
+impl StateRequest {
+    pub fn new(op: StackOp) -> StateRequest {
+        StateRequest {
+            op: op,
+            transition: None,
+            data: None,
+        }
+    }
+
+    pub fn with_transition(&mut self, transition: Transition) -> &mut StateRequest {
+        self.transition = Some(transition);
+        self
+    }
+
+    /// Passed to the state that ends up on top, see StateData
+    pub fn with_data<T: Any + Send>(&mut self, data: T) -> &mut StateRequest {
+        self.data = Some(Box::new(data));
+        self
+    }
+}
+
+/// Requests from the systems of a state, applied in order after the dispatch
+pub struct StateTransition {
+    // Boxed Any isn't Sync, resources have to be
+    requests: Mutex<Vec<StateRequest>>,
+}
+
+impl StateTransition {
+    pub fn new() -> StateTransition {
+        StateTransition {
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn request(&mut self, op: StackOp) -> &mut StateRequest {
+        let requests = self.requests.get_mut().unwrap();
+        requests.push(StateRequest::new(op));
+        requests.last_mut().unwrap()
+    }
+
+    pub fn push(&mut self, name: &'static str) -> &mut StateRequest {
+        self.request(StackOp::Push(name))
+    }
+
+    pub fn pop(&mut self) -> &mut StateRequest {
+        self.request(StackOp::Pop)
+    }
+
+    pub fn replace(&mut self, name: &'static str) -> &mut StateRequest {
+        self.request(StackOp::Replace(name))
+    }
+
+    pub fn clear(&mut self) -> &mut StateRequest {
+        self.request(StackOp::Clear)
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        self.requests.get_mut().unwrap().is_empty()
+    }
+
+    pub fn drain(&mut self) -> Vec<StateRequest> {
+        self.requests.get_mut().unwrap().drain(..).collect()
+    }
+}
+
+/// Data handed over by the request that put this state on top
+pub struct StateData {
+    data: Mutex<Option<Box<Any + Send>>>,
+}
+
+impl StateData {
+    pub fn new() -> StateData {
+        StateData {
+            data: Mutex::new(None),
+        }
+    }
+
+    pub fn set(&mut self, data: Option<Box<Any + Send>>) -> () {
+        *self.data.get_mut().unwrap() = data;
+    }
+
+    pub fn has<T: Any>(&mut self) -> bool {
+        match *self.data.get_mut().unwrap() {
+            Some(ref data) => data.is::<T>(),
+            None => false,
+        }
+    }
+
+    /// Takes the data out, if it is a T. Data of another type is left alone.
+    pub fn take<T: Any>(&mut self) -> Option<T> {
+        if !self.has::<T>() {
+            return None;
+        }
+        let data = self.data.get_mut().unwrap().take().unwrap();
+        match data.downcast::<T>() {
+            Ok(data) => Some(*data),
+            Err(_) => None,
+        }
+    }
+}
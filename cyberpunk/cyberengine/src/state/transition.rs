@@ -0,0 +1,135 @@
+//! Effects between states
+//!
+//! A transition covers the screen, the state change happens when the screen is fully
+//! covered, and then it uncovers the screen again. So half of `duration` is spent on
+//! each side of the change.
+
+use std::time::Duration;
+
+use graphics;
+
+/// Which way a wipe moves across the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Fades to a color and back
+    Fade,
+    /// A block of color sweeps over the screen, and off the other side
+    Wipe(Direction),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    pub effect: Effect,
+    pub duration: Duration,
+    pub color: [f32; 4],
+}
+
+impl Transition {
+    /// Fades to black and back
+    pub fn fade(duration: Duration) -> Transition {
+        Transition {
+            effect: Effect::Fade,
+            duration: duration,
+            color: [0., 0., 0., 1.],
+        }
+    }
+
+    pub fn wipe(direction: Direction, duration: Duration) -> Transition {
+        Transition {
+            effect: Effect::Wipe(direction),
+            duration: duration,
+            color: [0., 0., 0., 1.],
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Transition {
+        self.color = color;
+        self
+    }
+}
+
+/// A transition that is running
+pub struct Running {
+    transition: Transition,
+    elapsed: Duration,
+    /// Has the screen been fully covered yet
+    covered: bool,
+}
+
+impl Running {
+    pub fn new(transition: Transition) -> Running {
+        Running {
+            transition: transition,
+            elapsed: Duration::new(0, 0),
+            covered: false,
+        }
+    }
+
+    /// Advances the transition. Returns true on the tick the screen gets fully covered,
+    /// which is when the state should change.
+    pub fn advance(&mut self, dt: Duration) -> bool {
+        self.elapsed += dt;
+        if !self.covered && self.elapsed >= self.transition.duration / 2 {
+            self.covered = true;
+            return true;
+        }
+        false
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.transition.duration
+    }
+
+    /// How much of the screen is covered, 0.0 to 1.0
+    fn coverage(&self) -> f32 {
+        let half = seconds(self.transition.duration) / 2.;
+        if half <= 0. {
+            return 0.;
+        }
+        let elapsed = seconds(self.elapsed);
+        let coverage = if elapsed < half {
+            elapsed / half
+        } else {
+            1. - (elapsed - half) / half
+        };
+        coverage.max(0.).min(1.)
+    }
+
+    pub fn render(&self, renderer: &mut graphics::Renderer) -> () {
+        let (width, height) = renderer.target_size();
+        let (width, height) = (width as f32, height as f32);
+        let coverage = self.coverage();
+        let mut color = self.transition.color;
+
+        match self.transition.effect {
+            Effect::Fade => {
+                color[3] *= coverage;
+                renderer.fill_rect((0., 0., width, height), color);
+            }
+            Effect::Wipe(direction) => {
+                // Comes in from one edge, and leaves through the other
+                let entering = !self.covered;
+                let (w, h) = (width * coverage, height * coverage);
+                let rect = match (direction, entering) {
+                    (Direction::Left, true) | (Direction::Right, false) => (width - w, 0., w, height),
+                    (Direction::Left, false) | (Direction::Right, true) => (0., 0., w, height),
+                    (Direction::Up, true) | (Direction::Down, false) => (0., height - h, width, h),
+                    (Direction::Up, false) | (Direction::Down, true) => (0., 0., width, h),
+                };
+                renderer.fill_rect(rect, color);
+            }
+        }
+    }
+}
+
+fn seconds(d: Duration) -> f32 {
+    d.as_secs() as f32 + d.subsec_nanos() as f32 / 1_000_000_000.
+}
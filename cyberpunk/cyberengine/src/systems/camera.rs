@@ -0,0 +1,36 @@
+//! Systems for moving the Camera2D around
+
+use specs::{Fetch, FetchMut, ReadStorage, System};
+
+use graphics::camera::Camera2D;
+use systems::DeltaTime;
+use systems::sprite::Position;
+
+/// Moves the camera towards its target entity, if it has one.
+pub struct CameraFollow;
+
+impl<'a> System<'a> for CameraFollow {
+    type SystemData = (FetchMut<'a, Camera2D>, Fetch<'a, DeltaTime>, ReadStorage<'a, Position>);
+
+    fn run(&mut self, (mut camera, delta, positions): Self::SystemData) {
+        let target = match camera.target {
+            Some(entity) => match positions.get(entity) {
+                Some(position) => (position.x, position.y),
+                // Deleted, or doesn't have a position (yet)
+                None => return,
+            },
+            None => return,
+        };
+
+        let dt = delta.0.as_secs() as f32 + delta.0.subsec_nanos() as f32 / 1_000_000_000.;
+        // Exponential smoothing, so it doesn't depend on the tick rate
+        let t = if camera.smoothing > 0. {
+            1. - (-dt / camera.smoothing).exp()
+        } else {
+            1.
+        };
+
+        let (x, y) = camera.position;
+        camera.position = (x + (target.0 - x) * t, y + (target.1 - y) * t);
+    }
+}
@@ -0,0 +1,535 @@
+//! Tile based collision
+//!
+//! The solid tiles of a Tilemap are collected into a CollisionGrid resource. A tile is solid
+//! if it has a `solid = true` property, or any collision shapes drawn in Tiled's collision
+//! editor. The shapes themselves are not used (yet?), a tile is either solid or not.
+//!
+//! The math happens in Tiled pixels (Y down), and is converted from/to world coordinates
+//! (Y up) at the edges. Only orthogonal maps are supported.
+
+use specs::{Component, Fetch, Join, System, VecStorage, WriteStorage};
+
+use resource::tilemap::{Orientation, Tilemap};
+use systems::DeltaTime;
+use systems::sprite::Position;
+use ui::{Field, Inspect};
+
+/// Velocity in pixels per second
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Velocity {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Component for Velocity {
+    type Storage = VecStorage<Self>;
+}
+
+impl Inspect for Velocity {
+    fn fields(&mut self) -> Vec<(&'static str, Field)> {
+        vec![("x", Field::Float(&mut self.x)), ("y", Field::Float(&mut self.y))]
+    }
+}
+
+/// Which sides of a collider touched a solid tile during the last tick
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Contacts {
+    pub left: bool,
+    pub right: bool,
+    pub top: bool,
+    pub bottom: bool,
+}
+
+/// Axis aligned box, centered on the Position of the entity
+#[derive(Debug, Clone, Copy)]
+pub struct Collider {
+    pub half_width: f32,
+    pub half_height: f32,
+    pub contacts: Contacts,
+}
+
+impl Collider {
+    pub fn new(width: f32, height: f32) -> Collider {
+        Collider {
+            half_width: width / 2.,
+            half_height: height / 2.,
+            contacts: Contacts::default(),
+        }
+    }
+}
+
+impl Component for Collider {
+    type Storage = VecStorage<Self>;
+}
+
+impl Inspect for Collider {
+    fn fields(&mut self) -> Vec<(&'static str, Field)> {
+        let contacts = format!("{:?}", self.contacts);
+        vec![
+            ("half_width", Field::Float(&mut self.half_width)),
+            ("half_height", Field::Float(&mut self.half_height)),
+            ("contacts", Field::ReadOnly(contacts)),
+        ]
+    }
+}
+
+/// Result of CollisionGrid::sweep
+#[derive(Debug, Clone, Copy)]
+pub struct Sweep {
+    /// How far the box can actually move, in world coordinates
+    pub delta: (f32, f32),
+    pub contacts: Contacts,
+}
+
+/// Result of CollisionGrid::raycast
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    /// The solid tile that was hit
+    pub tile: (i32, i32),
+    /// Where the ray hit, in world coordinates
+    pub point: (f32, f32),
+    /// Normal of the side of the tile that was hit. (0, 0) if the ray started inside the tile.
+    pub normal: (f32, f32),
+    pub distance: f32,
+}
+
+/// Which tiles of the map are solid. Tiles outside the map are never solid.
+#[derive(Debug, Clone)]
+pub struct CollisionGrid {
+    pub width: i32,
+    pub height: i32,
+    pub tilewidth: f32,
+    pub tileheight: f32,
+    solid: Vec<bool>,
+}
+
+impl CollisionGrid {
+    /// A grid with nothing in it, so systems have something to fetch before a map is loaded
+    pub fn new() -> CollisionGrid {
+        CollisionGrid {
+            width: 0,
+            height: 0,
+            tilewidth: 1.,
+            tileheight: 1.,
+            solid: Vec::new(),
+        }
+    }
+
+    /// Collects the solid tiles from every tile layer of the map.
+    pub fn from_tilemap(map: &Tilemap) -> CollisionGrid {
+        if map.orientation != Orientation::Orthogonal {
+            println!(
+                "Collision only works on orthogonal maps, {} is {:?}",
+                map.filename,
+                map.orientation
+            );
+        }
+
+        let mut grid = CollisionGrid {
+            width: map.width,
+            height: map.height,
+            tilewidth: map.tilewidth as f32,
+            tileheight: map.tileheight as f32,
+            solid: vec![false; (map.width * map.height).max(0) as usize],
+        };
+
+        for layer in &map.layers {
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    let gid = match layer.get_tile(x, y) {
+                        Some(0) | None => continue,
+                        Some(gid) => gid,
+                    };
+                    let solid = match map.tileset_for_gid(gid) {
+                        Some(tileset) => {
+                            let property = match tileset.tile_property(gid, "solid") {
+                                Some(value) => value.as_bool().unwrap_or(false),
+                                None => false,
+                            };
+                            property || !tileset.collision(gid).is_empty()
+                        }
+                        None => false,
+                    };
+                    if solid {
+                        grid.set_solid(x, y, true);
+                    }
+                }
+            }
+        }
+
+        grid
+    }
+
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return false;
+        }
+        self.solid[(y * self.width + x) as usize]
+    }
+
+    /// Does nothing if the tile is outside the grid
+    pub fn set_solid(&mut self, x: i32, y: i32, solid: bool) -> () {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return;
+        }
+        self.solid[(y * self.width + x) as usize] = solid;
+    }
+
+    /// The tile under a world position
+    pub fn tile_at(&self, wx: f32, wy: f32) -> (i32, i32) {
+        (
+            (wx / self.tilewidth).floor() as i32,
+            (-wy / self.tileheight).floor() as i32,
+        )
+    }
+
+    /// Moves a box by `delta` (world coordinates), stopping at solid tiles.
+    /// X is resolved first, then Y, so the box slides along walls and floors.
+    /// Moves one tile at a time, so fast boxes don't tunnel through thin walls.
+    pub fn sweep(&self, center: (f32, f32), half_extents: (f32, f32), delta: (f32, f32)) -> Sweep {
+        let (hw, hh) = half_extents;
+        // Tiled pixels, Y down
+        let (mut left, top) = (center.0 - hw, -center.1 - hh);
+        let (mut right, bottom) = (center.0 + hw, -center.1 + hh);
+        let mut contacts = Contacts::default();
+
+        let dx = self.sweep_axis(left, right, top, bottom, delta.0, true);
+        if dx != delta.0 {
+            if delta.0 > 0. {
+                contacts.right = true;
+            } else {
+                contacts.left = true;
+            }
+        }
+        left += dx;
+        right += dx;
+
+        let dy = self.sweep_axis(top, bottom, left, right, -delta.1, false);
+        if dy != -delta.1 {
+            if delta.1 > 0. {
+                contacts.top = true;
+            } else {
+                contacts.bottom = true;
+            }
+        }
+
+        Sweep {
+            delta: (dx, -dy),
+            contacts: contacts,
+        }
+    }
+
+    /// How far the box can move along one axis. `min`/`max` are the sides of the box on
+    /// the moving axis, `cross_min`/`cross_max` on the other one.
+    fn sweep_axis(&self, min: f32, max: f32, cross_min: f32, cross_max: f32, delta: f32, x_axis: bool) -> f32 {
+        let (size, cross_size) = if x_axis {
+            (self.tilewidth, self.tileheight)
+        } else {
+            (self.tileheight, self.tilewidth)
+        };
+        // The tiles the box overlaps on the other axis, touching doesn't count
+        let first = (cross_min / cross_size).floor() as i32;
+        let last = (cross_max / cross_size).ceil() as i32 - 1;
+        let blocked = |i: i32| {
+            (first..last + 1).any(|j| if x_axis { self.is_solid(i, j) } else { self.is_solid(j, i) })
+        };
+
+        if delta > 0. {
+            let start = (max / size).ceil() as i32;
+            let end = ((max + delta) / size).ceil() as i32;
+            for i in start..end {
+                if blocked(i) {
+                    return (i as f32 * size - max).max(0.);
+                }
+            }
+        } else if delta < 0. {
+            let start = (min / size).floor() as i32 - 1;
+            let end = ((min + delta) / size).floor() as i32;
+            let mut i = start;
+            while i >= end {
+                if blocked(i) {
+                    return ((i + 1) as f32 * size - min).min(0.);
+                }
+                i -= 1;
+            }
+        }
+        delta
+    }
+
+    /// Casts a ray from `origin` towards `direction` (world coordinates), and returns the
+    /// first solid tile it hits within `max_distance`. Walks the grid one tile at a time,
+    /// so the cost only depends on how far the ray goes.
+    pub fn raycast(&self, origin: (f32, f32), direction: (f32, f32), max_distance: f32) -> Option<RayHit> {
+        let length = (direction.0 * direction.0 + direction.1 * direction.1).sqrt();
+        if length == 0. || self.solid.is_empty() {
+            return None;
+        }
+        // Tiled pixels, Y down
+        let (dx, dy) = (direction.0 / length, -direction.1 / length);
+        let (ox, oy) = (origin.0, -origin.1);
+        let (tw, th) = (self.tilewidth, self.tileheight);
+
+        let (mut x, mut y) = ((ox / tw).floor() as i32, (oy / th).floor() as i32);
+        if self.is_solid(x, y) {
+            return Some(RayHit {
+                tile: (x, y),
+                point: origin,
+                normal: (0., 0.),
+                distance: 0.,
+            });
+        }
+
+        let step_x = if dx > 0. { 1 } else { -1 };
+        let step_y = if dy > 0. { 1 } else { -1 };
+        // Distance along the ray between two vertical / horizontal grid lines
+        let delta_x = if dx != 0. { (tw / dx).abs() } else { ::std::f32::INFINITY };
+        let delta_y = if dy != 0. { (th / dy).abs() } else { ::std::f32::INFINITY };
+        // Distance along the ray to the next vertical / horizontal grid line
+        let mut next_x = if dx > 0. {
+            ((x + 1) as f32 * tw - ox) / dx
+        } else if dx < 0. {
+            (x as f32 * tw - ox) / dx
+        } else {
+            ::std::f32::INFINITY
+        };
+        let mut next_y = if dy > 0. {
+            ((y + 1) as f32 * th - oy) / dy
+        } else if dy < 0. {
+            (y as f32 * th - oy) / dy
+        } else {
+            ::std::f32::INFINITY
+        };
+
+        loop {
+            let (distance, normal) = if next_x < next_y {
+                x += step_x;
+                let distance = next_x;
+                next_x += delta_x;
+                (distance, (-step_x as f32, 0.))
+            } else {
+                y += step_y;
+                let distance = next_y;
+                next_y += delta_y;
+                // Back to Y up
+                (distance, (0., step_y as f32))
+            };
+
+            if distance > max_distance {
+                return None;
+            }
+            // Once the ray has left the map and is moving away from it, nothing can be hit
+            if (x < 0 && step_x < 0) || (x >= self.width && step_x > 0) || (y < 0 && step_y < 0)
+                || (y >= self.height && step_y > 0)
+            {
+                return None;
+            }
+
+            if self.is_solid(x, y) {
+                return Some(RayHit {
+                    tile: (x, y),
+                    point: (ox + dx * distance, -(oy + dy * distance)),
+                    normal: normal,
+                    distance: distance,
+                });
+            }
+        }
+    }
+}
+
+impl Default for CollisionGrid {
+    fn default() -> CollisionGrid {
+        CollisionGrid::new()
+    }
+}
+
+/// Moves entities with a Velocity and a Collider, stopping them at solid tiles.
+/// The velocity is zeroed on the axis that hit something.
+pub struct CollisionSystem;
+
+impl<'a> System<'a> for CollisionSystem {
+    type SystemData = (
+        Fetch<'a, DeltaTime>,
+        Fetch<'a, CollisionGrid>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, Collider>,
+    );
+
+    fn run(&mut self, (delta, grid, mut positions, mut velocities, mut colliders): Self::SystemData) {
+        let dt = delta.0.as_secs() as f32 + delta.0.subsec_nanos() as f32 / 1_000_000_000.;
+
+        for (position, velocity, collider) in (&mut positions, &mut velocities, &mut colliders).join() {
+            let sweep = grid.sweep(
+                (position.x, position.y),
+                (collider.half_width, collider.half_height),
+                (velocity.x * dt, velocity.y * dt),
+            );
+            position.x += sweep.delta.0;
+            position.y += sweep.delta.1;
+
+            if sweep.contacts.left || sweep.contacts.right {
+                velocity.x = 0.;
+            }
+            if sweep.contacts.top || sweep.contacts.bottom {
+                velocity.y = 0.;
+            }
+            collider.contacts = sweep.contacts;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use resource::tilemap::Tilemap;
+    use super::CollisionGrid;
+
+    /// 16x16 tiles, `#` is solid. The first row is the top of the map.
+    fn grid(rows: &[&str]) -> CollisionGrid {
+        let mut grid = CollisionGrid {
+            width: rows[0].len() as i32,
+            height: rows.len() as i32,
+            tilewidth: 16.,
+            tileheight: 16.,
+            solid: Vec::new(),
+        };
+        for row in rows {
+            grid.solid.extend(row.chars().map(|c| c == '#'));
+        }
+        grid
+    }
+
+    #[test]
+    fn touching_a_tile_is_not_overlapping_it() {
+        let grid = grid(&["....", "..#.", "...."]);
+        let size = (8., 8.);
+
+        // Right side exactly on the left edge of the wall
+        let sweep = grid.sweep((24., -24.), size, (5., 0.));
+        assert_eq!(sweep.delta, (0., 0.));
+        assert!(sweep.contacts.right);
+        let sweep = grid.sweep((24., -24.), size, (-5., 0.));
+        assert_eq!(sweep.delta, (-5., 0.));
+        assert!(!sweep.contacts.left);
+
+        // Left side exactly on the right edge of the wall
+        let sweep = grid.sweep((56., -24.), size, (-5., 0.));
+        assert_eq!(sweep.delta, (0., 0.));
+        assert!(sweep.contacts.left);
+
+        // Bottom exactly on the top of the wall, so it passes over it
+        let sweep = grid.sweep((8., -8.), size, (40., 0.));
+        assert_eq!(sweep.delta, (40., 0.));
+        assert!(!sweep.contacts.right);
+    }
+
+    #[test]
+    fn negative_deltas_stop_at_walls() {
+        let grid = grid(&["#...", "#...", "#...", "####"]);
+
+        let sweep = grid.sweep((40., -24.), (8., 8.), (-100., -100.));
+        assert_eq!(sweep.delta, (-16., -16.));
+        assert!(sweep.contacts.left && sweep.contacts.bottom);
+        assert!(!sweep.contacts.right && !sweep.contacts.top);
+    }
+
+    #[test]
+    fn fast_boxes_dont_tunnel_through_thin_walls() {
+        let wall = grid(&["..#......................................."]);
+        let sweep = wall.sweep((8., -8.), (8., 8.), (1000., 0.));
+        assert_eq!(sweep.delta, (16., 0.));
+        assert!(sweep.contacts.right);
+
+        let floor = grid(&[".", ".", "#", ".", ".", ".", ".", ".", ".", "."]);
+        let sweep = floor.sweep((8., -8.), (8., 8.), (0., -1000.));
+        assert_eq!(sweep.delta, (0., -16.));
+        assert!(sweep.contacts.bottom);
+    }
+
+    #[test]
+    fn boxes_slide_along_walls_and_floors() {
+        let grid = grid(&["....#", "....#", "#####"]);
+
+        // Standing on the floor
+        let sweep = grid.sweep((8., -24.), (8., 8.), (20., -5.));
+        assert_eq!(sweep.delta, (20., 0.));
+        assert!(sweep.contacts.bottom && !sweep.contacts.right);
+
+        // Against the wall on the right, in the air
+        let sweep = grid.sweep((56., -8.), (8., 8.), (5., -10.));
+        assert_eq!(sweep.delta, (0., -10.));
+        assert!(sweep.contacts.right && !sweep.contacts.bottom);
+    }
+
+    #[test]
+    fn rays_from_outside_the_map_hit_it() {
+        let grid = grid(&["....", "..#.", "...."]);
+
+        let hit = grid.raycast((-40., -24.), (1., 0.), 100.).unwrap();
+        assert_eq!(hit.tile, (2, 1));
+        assert_eq!(hit.point, (32., -24.));
+        assert_eq!(hit.normal, (-1., 0.));
+        assert_eq!(hit.distance, 72.);
+
+        assert!(grid.raycast((-40., -24.), (1., 0.), 70.).is_none());
+        assert!(grid.raycast((-40., -24.), (-1., 0.), 1000.).is_none());
+        assert!(grid.raycast((-40., -24.), (0., 1.), 1000.).is_none());
+    }
+
+    #[test]
+    fn axis_parallel_rays_only_cross_one_kind_of_grid_line() {
+        let grid = grid(&["....", "....", "....", "####"]);
+
+        // Straight down, and with a negative zero that must not count as moving left
+        for &direction in &[(0., -1.), (-0., -3.)] {
+            let hit = grid.raycast((40., -8.), direction, 100.).unwrap();
+            assert_eq!(hit.tile, (2, 3));
+            assert_eq!(hit.point, (40., -48.));
+            assert_eq!(hit.normal, (0., 1.));
+            assert_eq!(hit.distance, 40.);
+        }
+
+        // Straight up leaves the map right away
+        assert!(grid.raycast((40., -8.), (0., 1.), 100.).is_none());
+        // Sideways never gets to the floor
+        assert!(grid.raycast((40., -8.), (1., 0.), 100.).is_none());
+        assert!(grid.raycast((40., -8.), (-1., 0.), 100.).is_none());
+    }
+
+    #[test]
+    fn rays_starting_in_a_solid_tile_hit_it_right_away() {
+        let grid = grid(&["#."]);
+
+        let hit = grid.raycast((4., -4.), (1., 1.), 100.).unwrap();
+        assert_eq!(hit.tile, (0, 0));
+        assert_eq!(hit.point, (4., -4.));
+        assert_eq!(hit.normal, (0., 0.));
+        assert_eq!(hit.distance, 0.);
+    }
+
+    #[test]
+    fn tiles_past_the_end_of_a_row_are_not_on_the_next_row() {
+        let mut map = Tilemap::parse(
+            "grid.json",
+            r#"{"version": 1, "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16,
+                "orientation": "orthogonal", "nextobjectid": 1,
+                "tilesets": [{"firstgid": 1, "name": "walls", "image": "walls.png",
+                              "imagewidth": 32, "imageheight": 16, "tilewidth": 16, "tileheight": 16,
+                              "tileproperties": {"1": {"solid": true}}}],
+                "layers": [{"name": "ground", "type": "tilelayer", "visible": true, "opacity": 1,
+                            "width": 2, "height": 2, "data": [1, 2, 2, 1]}]}"#,
+        ).unwrap();
+        // Embedded, so nothing is read
+        map.resolve_tilesets().unwrap();
+
+        let layer = &map.layers[0];
+        assert_eq!(layer.get_tile(1, 0), Some(2));
+        assert_eq!(layer.get_tile(2, 0), None);
+        assert_eq!(layer.get_tile(0, 2), None);
+        assert_eq!(layer.get_tile(-1, 1), None);
+
+        let grid = CollisionGrid::from_tilemap(&map);
+        assert!(grid.is_solid(1, 0) && grid.is_solid(0, 1));
+        assert!(!grid.is_solid(0, 0) && !grid.is_solid(1, 1));
+        assert!(!grid.is_solid(2, 0));
+    }
+}
@@ -2,6 +2,25 @@
 
 use std::time::Duration;
 
+/// Length of one simulation tick. Constant, since the game loop uses a fixed timestep.
 pub struct DeltaTime(pub Duration);
 
+/// Total simulated time, the sum of every DeltaTime so far.
+pub struct ElapsedTime(pub Duration);
+
+/// How far (0.0 - 1.0) the current frame is between the last simulation tick and the next one.
+/// Rendering systems can use this to interpolate between the previous and current state.
+pub struct Interpolation(pub f32);
+
+/// The new size of the window in pixels, if it was resized since the last tick.
+/// Cleared after every tick, so systems see each resize once.
+pub struct WindowResized(pub Option<(u32, u32)>);
+
+/// How often changed assets are checked for, if hot reloading is enabled.
+/// See `development.hot_reload` in the config.
+pub struct HotReload(pub Option<Duration>);
+
+pub mod camera;
+pub mod collision;
 pub mod sprite;
+pub mod tilemap;
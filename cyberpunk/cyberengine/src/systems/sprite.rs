@@ -1,17 +1,18 @@
 use std::mem;
-use std::collections::HashMap;
 
 use gfx;
 use specs::{Component, System, ReadStorage, WriteStorage, Entities, VecStorage, Join, RunNow,
-            ParJoin, Fetch, LazyUpdate, Entity};
+            ParJoin, Fetch, FetchMut, LazyUpdate, Entity};
 use shred;
 use rayon::iter::ParallelIterator;
 
 use graphics;
-use graphics::texture;
+use graphics::batch::SpriteBatch;
 use resource;
+use resource::assets::{AssetServer, Handle};
+use ui::{Field, Inspect};
 
-#[derive(Debug)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
@@ -21,6 +22,13 @@ impl Component for Position {
     type Storage = VecStorage<Self>;
 }
 
+impl Inspect for Position {
+    fn fields(&mut self) -> Vec<(&'static str, Field)> {
+        vec![("x", Field::Float(&mut self.x)), ("y", Field::Float(&mut self.y))]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct SpriteSpawn {
     pub texture_identifier: resource::Identifier,
 }
@@ -29,74 +37,189 @@ impl Component for SpriteSpawn {
     type Storage = VecStorage<Self>;
 }
 
+impl Inspect for SpriteSpawn {
+    fn fields(&mut self) -> Vec<(&'static str, Field)> {
+        match self.texture_identifier {
+            resource::Identifier::Image(ref mut filename) => vec![("image", Field::Text(filename))],
+        }
+    }
+}
+
 pub struct Sprite {
     pub texture: graphics::texture::Texture,
+    /// Sprites on higher layers are drawn on top
+    pub layer: i32,
+    /// Multiplied with the texture
+    pub color: [f32; 4],
+    pub blend: graphics::BlendMode,
+    /// Where the texture came from, so the sprite can be saved in a scene
+    pub source: Option<resource::Identifier>,
+}
+
+impl Sprite {
+    pub fn new(texture: graphics::texture::Texture) -> Sprite {
+        Sprite {
+            texture: texture,
+            layer: 0,
+            color: [1., 1., 1., 1.],
+            blend: graphics::BlendMode::Alpha,
+            source: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: resource::Identifier) -> Sprite {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Sprite {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Sprite {
+        self.color = color;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: graphics::BlendMode) -> Sprite {
+        self.blend = blend;
+        self
+    }
 }
 
 impl Component for Sprite {
     type Storage = VecStorage<Self>;
 }
 
+impl Inspect for Sprite {
+    fn fields(&mut self) -> Vec<(&'static str, Field)> {
+        let (width, height) = self.texture.dimensions();
+        vec![
+            ("texture", Field::ReadOnly(format!("#{} ({}x{})", self.texture.id(), width, height))),
+            ("layer", Field::Int(&mut self.layer)),
+            ("color", Field::Color(&mut self.color)),
+            ("blend", Field::ReadOnly(format!("{:?}", self.blend))),
+            ("source", Field::ReadOnly(format!("{:?}", self.source))),
+        ]
+    }
+}
+
+/// Sprite whose texture is still loading, see SpriteLoader. The Sprite gets the
+/// rest of the fields once the texture is ready.
+pub struct PendingSprite {
+    pub texture: Handle<graphics::texture::Texture>,
+    pub source: resource::Identifier,
+    pub layer: i32,
+    pub color: [f32; 4],
+    pub blend: graphics::BlendMode,
+}
+
+impl PendingSprite {
+    pub fn new(texture: Handle<graphics::texture::Texture>, source: resource::Identifier) -> PendingSprite {
+        PendingSprite {
+            texture: texture,
+            source: source,
+            layer: 0,
+            color: [1., 1., 1., 1.],
+            blend: graphics::BlendMode::Alpha,
+        }
+    }
+}
+
+impl Component for PendingSprite {
+    type Storage = VecStorage<Self>;
+}
+
+/// Turns SpriteSpawns into Sprites. The textures are loaded in the background by the
+/// AssetServer, the entities get a PendingSprite until their texture is ready.
+/// Images that fail to load are replaced with the missing texture, so one typo
+/// doesn't take the whole game down.
+///
+/// Runs with the rendering systems, after the state Manager has uploaded this frame's
+/// textures, so sprites show up on the frame their texture gets to the GPU.
 pub struct SpriteLoader {
-    texture_cache: HashMap<String, graphics::texture::Texture>,
     pub renderer: Option<graphics::Renderer>,
 }
 
 impl SpriteLoader {
     pub fn new() -> SpriteLoader {
-        SpriteLoader {
-            renderer: None,
-            texture_cache: HashMap::new(),
-        }
+        SpriteLoader { renderer: None }
     }
 }
 
 impl<'a> System<'a> for SpriteLoader {
-    type SystemData = (WriteStorage<'a, SpriteSpawn>, WriteStorage<'a, Sprite>, Entities<'a>);
+    type SystemData = (
+        FetchMut<'a, AssetServer>,
+        WriteStorage<'a, SpriteSpawn>,
+        WriteStorage<'a, PendingSprite>,
+        WriteStorage<'a, Sprite>,
+        Entities<'a>,
+    );
 
-    fn run(&mut self, (mut spawns, mut sprites, entities): Self::SystemData) {
-        let mut to_remove: Vec<Entity> = Vec::new();
-        match self.renderer {
-            None => panic!("No renderer"),
-            Some(ref mut renderer) => {
-                for (entity, spawn) in (&*entities, &spawns).join() {
-                    use resource::Identifier;
-
-                    let texture = match spawn.texture_identifier {
-                        Identifier::Image(ref filename) => {
-                            if self.texture_cache.contains_key(filename) {
-                                self.texture_cache.get(filename).unwrap().clone()
-                            } else {
-                                println!("Loading {:?}!", filename);
-                                let texture = texture::Builder::new()
-                                    .from_file(filename.to_owned())
-                                    .build(&mut renderer.factory);
-                                self.texture_cache.insert(
-                                    filename.to_owned(),
-                                    texture.clone(),
-                                );
-                                texture
-                            }
-                        }
-                    };
-
-                    sprites.insert(entity, Sprite { texture: texture });
-                    to_remove.push(entity);
+    fn run(&mut self, (mut assets, mut spawns, mut pending, mut sprites, entities): Self::SystemData) {
+        // Hot reloaded textures
+        let reloaded = assets.take_reloaded();
+        if !reloaded.is_empty() {
+            for sprite in (&mut sprites).join() {
+                for &(old, ref texture) in &reloaded {
+                    if sprite.texture.id() == old {
+                        sprite.texture = texture.clone();
+                    }
                 }
             }
         }
 
-        for e in &to_remove {
+        let mut spawned: Vec<Entity> = Vec::new();
+        for (entity, spawn) in (&*entities, &spawns).join() {
+            use resource::Identifier;
+
+            let texture = match spawn.texture_identifier {
+                Identifier::Image(ref filename) => assets.load_texture(filename),
+            };
+            pending.insert(entity, PendingSprite::new(texture, spawn.texture_identifier.clone()));
+            spawned.push(entity);
+        }
+        for e in &spawned {
             spawns.remove(*e);
         }
 
+        let mut ready: Vec<Entity> = Vec::new();
+        for (entity, waiting) in (&*entities, &pending).join() {
+            if let Some(texture) = assets.texture(waiting.texture) {
+                let sprite = Sprite::new(texture)
+                    .with_layer(waiting.layer)
+                    .with_color(waiting.color)
+                    .with_blend(waiting.blend)
+                    .with_source(waiting.source.clone());
+                sprites.insert(entity, sprite);
+                ready.push(entity);
+            }
+        }
+        for e in &ready {
+            pending.remove(*e);
+        }
     }
 }
 
+/// How many sprites fit in one draw call
+const BATCH_SIZE: usize = 1024;
+
+/// Draws sprites, sorted by layer, and by texture inside a layer so they batch nicely.
 pub struct SpriteRenderer {
+    batch: Option<SpriteBatch>,
     pub renderer: Option<graphics::Renderer>,
 }
 
+impl SpriteRenderer {
+    pub fn new() -> SpriteRenderer {
+        SpriteRenderer {
+            batch: None,
+            renderer: None,
+        }
+    }
+}
+
 impl<'a> System<'a> for SpriteRenderer {
     type SystemData = (ReadStorage<'a, Position>, ReadStorage<'a, Sprite>);
 
@@ -104,9 +227,24 @@ impl<'a> System<'a> for SpriteRenderer {
         match self.renderer {
             None => panic!("No renderer"),
             Some(ref mut renderer) => {
-                for (position, sprite) in (&position, &sprite).join() {
-                    renderer.draw_texture(&sprite.texture, (position.x, position.y));
+                if self.batch.is_none() {
+                    self.batch = Some(SpriteBatch::new(&mut renderer.factory, BATCH_SIZE));
+                }
+                let batch = self.batch.as_mut().unwrap();
+
+                let mut sprites: Vec<(&Position, &Sprite)> = (&position, &sprite).join().collect();
+                sprites.sort_by_key(|&(_, sprite)| (sprite.layer, sprite.blend, sprite.texture.id()));
+
+                for (position, sprite) in sprites {
+                    batch.draw(
+                        renderer,
+                        &sprite.texture,
+                        (position.x, position.y),
+                        sprite.color,
+                        sprite.blend,
+                    );
                 }
+                batch.flush(renderer);
             }
         }
     }
@@ -0,0 +1,278 @@
+//! Rendering for Tiled maps, and turning map objects into entities
+
+use std::collections::HashMap;
+use std::mem;
+
+use serde_json::Value;
+use serde_json::map::Map;
+use shred;
+use specs::{Component, System, Entities, Entity, Fetch, FetchMut, LazyUpdate, VecStorage};
+
+use graphics;
+use resource::AssetRegistry;
+use resource::assets::{AssetServer, Handle};
+use resource::tilemap::{Grid, MapObject, ObjectShape, Tilemap};
+use resource::watch::FileWatcher;
+use systems::{ElapsedTime, HotReload};
+use systems::collision::CollisionGrid;
+use systems::sprite::Position;
+
+/// Everything Tiled knows about the object an entity was spawned from
+#[derive(Debug)]
+pub struct MapObjectInfo {
+    pub id: i32,
+    pub name: String,
+    pub objecttype: String,
+    pub shape: ObjectShape,
+    pub properties: Map<String, Value>,
+}
+
+impl Component for MapObjectInfo {
+    type Storage = VecStorage<Self>;
+}
+
+pub type SpawnHandler = Box<Fn(&MapObject, Entity, &LazyUpdate) + Send + Sync>;
+
+/// Turns Tiled objects into entities.
+///
+/// Every object gets a Position and a MapObjectInfo. Handlers registered for the object's
+/// `type` (the "Type" field in Tiled) can then add whatever other components they need,
+/// so level designers can place spawn points, triggers and such.
+///
+/// The world has to have Position and MapObjectInfo registered.
+pub struct ObjectSpawner {
+    handlers: HashMap<String, SpawnHandler>,
+    fallback: Option<SpawnHandler>,
+}
+
+impl ObjectSpawner {
+    pub fn new() -> ObjectSpawner {
+        ObjectSpawner {
+            handlers: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Calls `handler` for every object with the given type.
+    pub fn with_handler<F>(mut self, objecttype: &str, handler: F) -> ObjectSpawner
+    where
+        F: Fn(&MapObject, Entity, &LazyUpdate) + Send + Sync + 'static,
+    {
+        self.handlers.insert(objecttype.to_owned(), Box::new(handler));
+        self
+    }
+
+    /// Calls `handler` for objects whose type has no handler of its own.
+    pub fn with_fallback<F>(mut self, handler: F) -> ObjectSpawner
+    where
+        F: Fn(&MapObject, Entity, &LazyUpdate) + Send + Sync + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Spawns an entity for each object in the map. The components are added lazily,
+    /// so they show up after the next `World::maintain`.
+    pub fn spawn<'a>(&self, map: &Tilemap, entities: &Entities<'a>, lazy: &LazyUpdate) -> Vec<Entity> {
+        let grid = map.grid();
+        map.objects()
+            .into_iter()
+            .map(|object| {
+                let entity = entities.create();
+                let (x, y) = object_center(object, &grid);
+                lazy.insert(entity, Position { x: x, y: y });
+                lazy.insert(
+                    entity,
+                    MapObjectInfo {
+                        id: object.id,
+                        name: object.name.clone(),
+                        objecttype: object.objecttype.clone(),
+                        shape: object.shape.clone(),
+                        properties: object.properties.clone(),
+                    },
+                );
+
+                match self.handlers.get(&object.objecttype) {
+                    Some(handler) => handler(object, entity, lazy),
+                    None => match self.fallback {
+                        Some(ref handler) => handler(object, entity, lazy),
+                        None => {}
+                    },
+                }
+                entity
+            })
+            .collect()
+    }
+}
+
+/// Center of the object in world coordinates (Y up), points and polygons use their origin.
+fn object_center(object: &MapObject, grid: &Grid) -> (f32, f32) {
+    let (x, y) = match object.shape {
+        ObjectShape::Rectangle { width, height } |
+        ObjectShape::Ellipse { width, height } |
+        ObjectShape::Text { width, height, .. } => (object.x + width / 2., object.y + height / 2.),
+        ObjectShape::Tile { width, height, .. } => (object.x + width / 2., object.y - height / 2.),
+        ObjectShape::Point | ObjectShape::Polygon(_) | ObjectShape::Polyline(_) => (object.x, object.y),
+    };
+    grid.object_to_world(x as f32, y as f32)
+}
+
+/// Draws a Tilemap, layer by layer. The map is loaded in the background by the
+/// AssetServer, starting on the first render, and so are the tileset images.
+/// The map is drawn once they are all on the GPU. Objects in the map
+/// are spawned as entities when the map is loaded, and the CollisionGrid
+/// resource is replaced with the one of the map.
+///
+/// With hot reloading, the map is loaded again when it or its tilesets change,
+/// the AssetServer takes care of the images. The old map is drawn until the new one is loaded.
+/// Objects are only spawned the first time, reloading would duplicate them.
+pub struct TilemapRenderer {
+    filename: String,
+    failed: bool,
+    loading: Option<Handle<Tilemap>>,
+    /// The files changed while the map was loading, so it has to be loaded again
+    stale: bool,
+    spawner: ObjectSpawner,
+    watcher: Option<FileWatcher>,
+    pub map: Option<Tilemap>,
+    pub renderer: Option<graphics::Renderer>,
+}
+
+impl TilemapRenderer {
+    pub fn new(filename: &str) -> TilemapRenderer {
+        TilemapRenderer {
+            filename: filename.to_owned(),
+            failed: false,
+            loading: None,
+            stale: false,
+            spawner: ObjectSpawner::new(),
+            watcher: None,
+            map: None,
+            renderer: None,
+        }
+    }
+
+    pub fn with_object_spawner(mut self, spawner: ObjectSpawner) -> TilemapRenderer {
+        self.spawner = spawner;
+        self
+    }
+}
+
+impl<'a> System<'a> for TilemapRenderer {
+    type SystemData = (
+        Entities<'a>,
+        Fetch<'a, LazyUpdate>,
+        Fetch<'a, ElapsedTime>,
+        Fetch<'a, HotReload>,
+        FetchMut<'a, AssetServer>,
+    );
+
+    fn run(&mut self, (entities, lazy, elapsed, hot_reload, mut assets): Self::SystemData) {
+        match self.renderer {
+            None => panic!("No renderer"),
+            Some(ref mut renderer) => {
+                if self.watcher.is_none() {
+                    if let Some(interval) = hot_reload.0 {
+                        let mut watcher = FileWatcher::new(interval);
+                        // The map itself, even if it failed to load, so fixing it gets picked up
+                        watch(&mut watcher, assets.registry(), &self.filename);
+                        if let Some(ref map) = self.map {
+                            for file in map.files() {
+                                watch(&mut watcher, assets.registry(), &file);
+                            }
+                        }
+                        self.watcher = Some(watcher);
+                    }
+                }
+
+                let changed = match self.watcher {
+                    Some(ref mut watcher) => !watcher.poll().is_empty(),
+                    None => false,
+                };
+                if changed {
+                    println!("Reloading {}", self.filename);
+                }
+
+                if changed && self.loading.is_some() {
+                    self.stale = true;
+                } else if (self.map.is_none() && !self.failed && self.loading.is_none()) || changed {
+                    self.loading = Some(assets.load::<Tilemap>(&self.filename));
+                }
+
+                let loaded = match self.loading {
+                    Some(handle) => assets.take(handle),
+                    None => None,
+                };
+                if let Some(loaded) = loaded {
+                    self.loading = None;
+                    let loaded = loaded.and_then(|mut map| {
+                        map.request_textures(&mut assets)?;
+                        Ok(map)
+                    });
+                    match loaded {
+                        Ok(map) => {
+                            if self.map.is_none() {
+                                self.spawner.spawn(&map, &entities, &lazy);
+                            }
+                            let grid = CollisionGrid::from_tilemap(&map);
+                            lazy.execute(move |world| world.add_resource(grid));
+                            if let Some(ref mut watcher) = self.watcher {
+                                for file in map.files() {
+                                    watch(watcher, assets.registry(), &file);
+                                }
+                            }
+                            self.failed = false;
+                            self.map = Some(map);
+                        }
+                        Err(e) => {
+                            // Don't spam the log every frame
+                            println!("Failed to load tilemap: {}", e);
+                            self.failed = true;
+                        }
+                    }
+                    if self.stale {
+                        self.stale = false;
+                        self.loading = Some(assets.load::<Tilemap>(&self.filename));
+                    }
+                }
+
+                match self.map {
+                    Some(ref mut map) => if map.update_textures(&assets) {
+                        map.draw(elapsed.0, renderer)
+                    },
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+/// Watches the file an asset path comes from, if it comes from a directory
+fn watch(watcher: &mut FileWatcher, registry: &AssetRegistry, path: &str) -> () {
+    if let Some(file) = registry.file_path(path) {
+        watcher.watch(file);
+    }
+}
+
+impl graphics::RenderingSystem for TilemapRenderer {
+    fn render_world<'s, 'r>(
+        &'s mut self,
+        res: &'r mut shred::Resources,
+        renderer: graphics::Renderer,
+    ) -> graphics::Renderer {
+        use specs::RunNow;
+
+        {
+            self.renderer = Some(renderer);
+            self.run_now(res);
+        }
+        let renderer = mem::replace(&mut self.renderer, None);
+
+        match renderer {
+            Some(renderer) => renderer,
+            None => {
+                panic!("No renderer after render??");
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Entity inspector
+//!
+//! Lists the entities of a World, and shows the components that have been
+//! registered with the Inspector. Components opt in by implementing Inspect,
+//! which hands out their fields for the inspector to draw and edit.
+//!
+//! The components also need to be registered in the World, the Inspector doesn't do that.
+
+use imgui::{ImGuiSetCond_FirstUseEver, ImString, Ui};
+use specs::{Component, Entity, Join, World};
+
+/// How long strings can get when edited in the inspector
+const TEXT_CAPACITY: usize = 256;
+
+/// A field of a component, as the inspector sees it
+pub enum Field<'a> {
+    Float(&'a mut f32),
+    Int(&'a mut i32),
+    Bool(&'a mut bool),
+    /// RGBA
+    Color(&'a mut [f32; 4]),
+    Text(&'a mut String),
+    /// Shown, but can't be edited
+    ReadOnly(String),
+}
+
+/// Components that can be shown in the inspector
+pub trait Inspect {
+    /// Name and value of every field that should show up in the inspector
+    fn fields(&mut self) -> Vec<(&'static str, Field)>;
+}
+
+/// Type erased component, so different components fit in the same Vec
+trait InspectComponent {
+    fn name(&self) -> &'static str;
+    fn has(&self, world: &World, entity: Entity) -> bool;
+    /// Whether `add` can do anything
+    fn has_default(&self) -> bool;
+    /// Adds a default component to the entity
+    fn add(&self, world: &mut World, entity: Entity) -> ();
+    fn remove(&self, world: &mut World, entity: Entity) -> ();
+    fn draw(&self, ui: &Ui, world: &mut World, entity: Entity) -> ();
+}
+
+struct Inspected<T> {
+    name: &'static str,
+    default: Option<fn() -> T>,
+}
+
+impl<T> InspectComponent for Inspected<T>
+where
+    T: Component + Inspect,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn has(&self, world: &World, entity: Entity) -> bool {
+        world.read::<T>().get(entity).is_some()
+    }
+
+    fn has_default(&self) -> bool {
+        self.default.is_some()
+    }
+
+    fn add(&self, world: &mut World, entity: Entity) -> () {
+        match self.default {
+            Some(default) => {
+                world.write::<T>().insert(entity, default());
+            }
+            None => {}
+        }
+    }
+
+    fn remove(&self, world: &mut World, entity: Entity) -> () {
+        world.write::<T>().remove(entity);
+    }
+
+    fn draw(&self, ui: &Ui, world: &mut World, entity: Entity) -> () {
+        let mut storage = world.write::<T>();
+        let component = match storage.get_mut(entity) {
+            Some(component) => component,
+            None => return,
+        };
+
+        for (name, field) in component.fields() {
+            // imgui uses the label as the id, the part after ## isn't shown
+            let label = im_str!("{}##{}{}", name, self.name, entity.id());
+            match field {
+                Field::Float(value) => {
+                    ui.input_float(label, value).build();
+                }
+                Field::Int(value) => {
+                    ui.input_int(label, value).build();
+                }
+                Field::Bool(value) => {
+                    ui.checkbox(label, value);
+                }
+                Field::Color(value) => {
+                    ui.input_float4(label, value).build();
+                }
+                Field::Text(value) => {
+                    let mut buffer = ImString::with_capacity(TEXT_CAPACITY);
+                    buffer.push_str(value);
+                    if ui.input_text(label, &mut buffer).build() {
+                        *value = buffer.to_str().to_owned();
+                    }
+                }
+                Field::ReadOnly(value) => ui.text(im_str!("{}: {}", name, value)),
+            }
+        }
+    }
+}
+
+/// Debug window that lists the entities of a World.
+///
+/// Use it through `GameState::with_inspector`, or call `draw` from a UI callback.
+pub struct Inspector {
+    components: Vec<Box<InspectComponent>>,
+}
+
+impl Inspector {
+    pub fn new() -> Inspector {
+        Inspector {
+            components: Vec::new(),
+        }
+    }
+
+    /// Shows T in the inspector.
+    pub fn with<T>(mut self, name: &'static str) -> Inspector
+    where
+        T: Component + Inspect,
+    {
+        self.components.push(Box::new(Inspected::<T> {
+            name: name,
+            default: None,
+        }));
+        self
+    }
+
+    /// Shows T in the inspector, and lets you add it to entities.
+    pub fn with_default<T>(mut self, name: &'static str) -> Inspector
+    where
+        T: Component + Inspect + Default,
+    {
+        self.components.push(Box::new(Inspected::<T> {
+            name: name,
+            default: Some(T::default),
+        }));
+        self
+    }
+
+    /// Draws the inspector window. Created and deleted entities show up after
+    /// the next `world.maintain()`.
+    pub fn draw(&mut self, ui: &Ui, world: &mut World) -> () {
+        let entities: Vec<Entity> = (&*world.entities()).join().collect();
+        let components = &self.components;
+
+        ui.window(im_str!("Inspector"))
+            .size((350.0, 500.0), ImGuiSetCond_FirstUseEver)
+            .build(|| {
+                ui.text(im_str!("{} entities", entities.len()));
+                if ui.small_button(im_str!("New entity")) {
+                    world.create_entity().build();
+                }
+                ui.separator();
+
+                for &entity in &entities {
+                    let label = im_str!("Entity {} (gen {})", entity.id(), entity.gen().id());
+                    ui.tree_node(label).build(|| {
+                        draw_entity(ui, world, entity, components);
+                    });
+                }
+            });
+    }
+}
+
+fn draw_entity(ui: &Ui, world: &mut World, entity: Entity, components: &[Box<InspectComponent>]) -> () {
+    if ui.small_button(im_str!("Delete##{}", entity.id())) {
+        match world.entities().delete(entity) {
+            Ok(_) => {}
+            Err(e) => println!("Couldn't delete {:?}: {:?}", entity, e),
+        }
+        return;
+    }
+
+    for component in components {
+        if component.has(world, entity) {
+            ui.separator();
+            ui.text(im_str!("{}", component.name()));
+            ui.same_line(0.);
+            if ui.small_button(im_str!("Remove##{}{}", component.name(), entity.id())) {
+                component.remove(world, entity);
+                continue;
+            }
+            component.draw(ui, world, entity);
+        }
+    }
+
+    ui.separator();
+    for component in components {
+        if component.has_default() && !component.has(world, entity) {
+            if ui.small_button(im_str!("Add {}##{}", component.name(), entity.id())) {
+                component.add(world, entity);
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Dear ImGui debug overlay
+//!
+//! imgui-gfx-renderer wants an Rgba8 target and our screen is Srgba8, so the UI is
+//! rendered into a texture of its own, which is then drawn over the game.
+//!
+//! States can draw whatever they want with `GameState::with_ui`. Systems can't hold on
+//! to the Ui, so they push text into the DebugWindows resource instead.
+//! There is also an entity inspector, see `GameState::with_inspector`.
+use std::collections::BTreeMap;
+
+use gfx;
+use gfx_core;
+use glutin;
+use imgui::{ImGui, ImGuiSetCond_FirstUseEver, Ui};
+use imgui_gfx_renderer::{Renderer, Shaders};
+
+use error::{Error, Result};
+use graphics;
+use graphics::camera::Camera2D;
+
+pub mod inspector;
+
+pub use self::inspector::{Field, Inspect, Inspector};
+
+type UiTarget = gfx_core::handle::RenderTargetView<graphics::Resources, gfx::format::Rgba8>;
+
+/// Text that systems want to show, by window title. Cleared before every tick,
+/// so systems should push their lines every tick.
+#[derive(Debug, Default)]
+pub struct DebugWindows {
+    windows: BTreeMap<String, Vec<String>>,
+}
+
+impl DebugWindows {
+    pub fn new() -> DebugWindows {
+        DebugWindows::default()
+    }
+
+    /// Adds a line of text to a window, the window is created if needed
+    pub fn text(&mut self, window: &str, line: String) -> () {
+        self.windows
+            .entry(window.to_owned())
+            .or_insert_with(Vec::new)
+            .push(line);
+    }
+
+    pub fn clear(&mut self) -> () {
+        self.windows.clear();
+    }
+
+    /// Draws the windows
+    pub fn draw(&self, ui: &Ui) -> () {
+        for (title, lines) in &self.windows {
+            ui.window(im_str!("{}", title))
+                .size((300.0, 200.0), ImGuiSetCond_FirstUseEver)
+                .build(|| for line in lines {
+                    ui.text(im_str!("{}", line));
+                });
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+struct MouseState {
+    pos: (i32, i32),
+    pressed: (bool, bool, bool),
+    wheel: f32,
+}
+
+pub struct DebugUi {
+    imgui: ImGui,
+    renderer: Renderer<graphics::Resources>,
+    shaders: Shaders,
+    texture: graphics::texture::Texture,
+    target: UiTarget,
+    mouse_state: MouseState,
+    /// Toggled with F12
+    pub visible: bool,
+}
+
+impl DebugUi {
+    pub fn new(renderer: &mut graphics::Renderer) -> Result<DebugUi> {
+        let shaders = {
+            let version = renderer.device_info().shading_language;
+            if version.is_embedded {
+                if version.major >= 3 {
+                    Shaders::GlSlEs300
+                } else {
+                    Shaders::GlSlEs100
+                }
+            } else {
+                if version.major >= 4 {
+                    Shaders::GlSl400
+                } else if version.major >= 3 {
+                    Shaders::GlSl130
+                } else {
+                    Shaders::GlSl110
+                }
+            }
+        };
+
+        let mut imgui = ImGui::init();
+        configure_keys(&mut imgui);
+
+        let (width, height) = renderer.target_size();
+        let (texture, target) = create_target(&mut renderer.factory, width, height)?;
+        let ui_renderer = Renderer::init(&mut imgui, &mut renderer.factory, shaders, target.clone())
+            .map_err(|e| Error::Gpu(format!("Failed to initialize imgui renderer: {:?}", e)))?;
+
+        Ok(DebugUi {
+            imgui: imgui,
+            renderer: ui_renderer,
+            shaders: shaders,
+            texture: texture,
+            target: target,
+            mouse_state: MouseState::default(),
+            visible: cfg!(debug_assertions),
+        })
+    }
+
+    /// Recreates the UI texture to match the new window size.
+    pub fn resize(&mut self, renderer: &mut graphics::Renderer, width: u32, height: u32) -> () {
+        let (texture, target) = match create_target(&mut renderer.factory, width, height) {
+            Ok(created) => created,
+            Err(e) => {
+                println!("Failed to resize the UI: {}", e);
+                return;
+            }
+        };
+        match Renderer::init(&mut self.imgui, &mut renderer.factory, self.shaders, target.clone()) {
+            Ok(ui_renderer) => {
+                self.renderer = ui_renderer;
+                self.texture = texture;
+                self.target = target;
+            }
+            Err(e) => println!("Failed to resize the UI: {:?}", e),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &glutin::WindowEvent) -> () {
+        use glutin::WindowEvent::*;
+        use glutin::ElementState::Pressed;
+        use glutin::{MouseButton, MouseScrollDelta, TouchPhase};
+        use glutin::VirtualKeyCode as Key;
+
+        let imgui = &mut self.imgui;
+        let mouse_state = &mut self.mouse_state;
+
+        match *event {
+            KeyboardInput { input, .. } => {
+                let pressed = input.state == Pressed;
+                match input.virtual_keycode {
+                    Some(Key::F12) => if pressed {
+                        self.visible = !self.visible;
+                    },
+                    Some(Key::Tab) => imgui.set_key(0, pressed),
+                    Some(Key::Left) => imgui.set_key(1, pressed),
+                    Some(Key::Right) => imgui.set_key(2, pressed),
+                    Some(Key::Up) => imgui.set_key(3, pressed),
+                    Some(Key::Down) => imgui.set_key(4, pressed),
+                    Some(Key::PageUp) => imgui.set_key(5, pressed),
+                    Some(Key::PageDown) => imgui.set_key(6, pressed),
+                    Some(Key::Home) => imgui.set_key(7, pressed),
+                    Some(Key::End) => imgui.set_key(8, pressed),
+                    Some(Key::Delete) => imgui.set_key(9, pressed),
+                    Some(Key::Back) => imgui.set_key(10, pressed),
+                    Some(Key::Return) => imgui.set_key(11, pressed),
+                    Some(Key::Escape) => imgui.set_key(12, pressed),
+                    Some(Key::A) => imgui.set_key(13, pressed),
+                    Some(Key::C) => imgui.set_key(14, pressed),
+                    Some(Key::V) => imgui.set_key(15, pressed),
+                    Some(Key::X) => imgui.set_key(16, pressed),
+                    Some(Key::Y) => imgui.set_key(17, pressed),
+                    Some(Key::Z) => imgui.set_key(18, pressed),
+                    Some(Key::LControl) |
+                    Some(Key::RControl) => imgui.set_key_ctrl(pressed),
+                    Some(Key::LShift) |
+                    Some(Key::RShift) => imgui.set_key_shift(pressed),
+                    Some(Key::LAlt) | Some(Key::RAlt) => imgui.set_key_alt(pressed),
+                    Some(Key::LWin) | Some(Key::RWin) => imgui.set_key_super(pressed),
+                    _ => {}
+                }
+            }
+            MouseMoved { position: (x, y), .. } => mouse_state.pos = (x as i32, y as i32),
+            MouseInput { state, button, .. } => match button {
+                MouseButton::Left => mouse_state.pressed.0 = state == Pressed,
+                MouseButton::Right => mouse_state.pressed.1 = state == Pressed,
+                MouseButton::Middle => mouse_state.pressed.2 = state == Pressed,
+                _ => {}
+            },
+            MouseWheel {
+                delta: MouseScrollDelta::LineDelta(_, y),
+                phase: TouchPhase::Moved,
+                ..
+            } |
+            MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(_, y),
+                phase: TouchPhase::Moved,
+                ..
+            } => mouse_state.wheel = y,
+            ReceivedCharacter(c) => imgui.add_input_character(c),
+            _ => (),
+        }
+    }
+
+    /// Builds the UI with `build`, and draws it over whatever has been rendered so far.
+    /// `dt` is the real time since the last frame, in seconds.
+    pub fn render<F>(&mut self, renderer: &mut graphics::Renderer, window: &glutin::GlWindow, dt: f32, build: F) -> ()
+    where
+        F: FnOnce(&Ui),
+    {
+        if !self.visible {
+            return;
+        }
+        update_mouse(&mut self.imgui, &mut self.mouse_state);
+
+        let size_points = window.get_inner_size_points().unwrap();
+        let size_pixels = window.get_inner_size_pixels().unwrap();
+        let ui = self.imgui.frame(size_points, size_pixels, dt);
+
+        ui.window(im_str!("Debug"))
+            .size((250.0, 100.0), ImGuiSetCond_FirstUseEver)
+            .build(|| {
+                ui.text(im_str!("FPS: {:.0}", 1.0 / dt));
+                let mouse_pos = ui.imgui().mouse_pos();
+                ui.text(im_str!(
+                    "Mouse Position: ({:.1},{:.1})",
+                    mouse_pos.0,
+                    mouse_pos.1
+                ));
+                ui.text(im_str!("F12 hides this"));
+            });
+        build(&ui);
+
+        renderer.encoder.clear(&self.target, [0., 0., 0., 0.]);
+        if let Err(e) = self.renderer.render(ui, &mut renderer.factory, &mut renderer.encoder) {
+            println!("Failed to render the UI: {:?}", e);
+            return;
+        }
+
+        // The UI is in window pixels, no matter where the game camera is
+        let (width, height) = renderer.target_size();
+        renderer.set_camera(&Camera2D::new(width as f32, height as f32));
+        renderer.draw_texture(&self.texture, (0., 0.));
+    }
+}
+
+fn create_target(
+    factory: &mut graphics::Factory,
+    width: u32,
+    height: u32,
+) -> Result<(graphics::texture::Texture, UiTarget)> {
+    use gfx::traits::FactoryExt;
+
+    let (_, view, target) = factory
+        .create_render_target::<gfx::format::Rgba8>(width as u16, height as u16)
+        .map_err(|e| Error::Gpu(format!("Failed to create UI target: {:?}", e)))?;
+    let texture = graphics::texture::Builder::new()
+        .with_view(view)
+        .with_dimensions(width, height)
+        // Render targets are upside down
+        .with_flipped_y()
+        .build(factory)?;
+    Ok((texture, target))
+}
+
+// some imgui-rs helpers
+fn configure_keys(imgui: &mut ImGui) {
+    use imgui::ImGuiKey;
+
+    imgui.set_imgui_key(ImGuiKey::Tab, 0);
+    imgui.set_imgui_key(ImGuiKey::LeftArrow, 1);
+    imgui.set_imgui_key(ImGuiKey::RightArrow, 2);
+    imgui.set_imgui_key(ImGuiKey::UpArrow, 3);
+    imgui.set_imgui_key(ImGuiKey::DownArrow, 4);
+    imgui.set_imgui_key(ImGuiKey::PageUp, 5);
+    imgui.set_imgui_key(ImGuiKey::PageDown, 6);
+    imgui.set_imgui_key(ImGuiKey::Home, 7);
+    imgui.set_imgui_key(ImGuiKey::End, 8);
+    imgui.set_imgui_key(ImGuiKey::Delete, 9);
+    imgui.set_imgui_key(ImGuiKey::Backspace, 10);
+    imgui.set_imgui_key(ImGuiKey::Enter, 11);
+    imgui.set_imgui_key(ImGuiKey::Escape, 12);
+    imgui.set_imgui_key(ImGuiKey::A, 13);
+    imgui.set_imgui_key(ImGuiKey::C, 14);
+    imgui.set_imgui_key(ImGuiKey::V, 15);
+    imgui.set_imgui_key(ImGuiKey::X, 16);
+    imgui.set_imgui_key(ImGuiKey::Y, 17);
+    imgui.set_imgui_key(ImGuiKey::Z, 18);
+}
+
+fn update_mouse(imgui: &mut ImGui, mouse_state: &mut MouseState) {
+    let scale = imgui.display_framebuffer_scale();
+    imgui.set_mouse_pos(
+        mouse_state.pos.0 as f32 / scale.0,
+        mouse_state.pos.1 as f32 / scale.1,
+    );
+    imgui.set_mouse_down(
+        &[
+            mouse_state.pressed.0,
+            mouse_state.pressed.1,
+            mouse_state.pressed.2,
+            false,
+            false,
+        ],
+    );
+    imgui.set_mouse_wheel(mouse_state.wheel / scale.1);
+    mouse_state.wheel = 0.0;
+}
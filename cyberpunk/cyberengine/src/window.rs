@@ -1,17 +1,17 @@
 //! Window management.
 //! wrapper for winit and gfx context creation
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use gfx;
-use gfx_core;
-use imgui::{ImGui, Ui};
-use imgui_gfx_renderer::{Renderer, Shaders};
 use glutin as winit;
 use gfx_window_glutin;
 
 use graphics;
+use input;
+use input::gamepad::{self, GamepadBackend};
+use resource::assets::AssetServer;
 use screen;
+use state;
 
 /// Builds Windows
 pub struct Builder {
@@ -20,6 +20,12 @@ pub struct Builder {
     title: Option<String>,
     vsync: bool,
     multisampling: u16,
+    input_bindings: Option<input::Bindings>,
+    gamepad_backend: Option<Box<GamepadBackend>>,
+    hot_reload: Option<Duration>,
+    assets: Option<AssetServer>,
+    states: Vec<state::GameState>,
+    initial_state: Option<&'static str>,
 }
 
 impl Builder {
@@ -30,6 +36,12 @@ impl Builder {
             title: None,
             vsync: false,
             multisampling: 0,
+            input_bindings: None,
+            gamepad_backend: None,
+            hot_reload: None,
+            assets: None,
+            states: Vec::new(),
+            initial_state: None,
         }
     }
 
@@ -58,6 +70,42 @@ impl Builder {
         self
     }
 
+    pub fn with_input_bindings(mut self, bindings: input::Bindings) -> Builder {
+        self.input_bindings = Some(bindings);
+        self
+    }
+
+    /// Where gamepad events come from. Defaults to gilrs (if enabled), use
+    /// `gamepad::ScriptedGamepads` to fake them.
+    pub fn with_gamepad_backend(mut self, backend: Box<GamepadBackend>) -> Builder {
+        self.gamepad_backend = Some(backend);
+        self
+    }
+
+    /// Reloads shaders and assets when they change, checking every `interval`.
+    /// For development, see `development.hot_reload` in the config.
+    pub fn with_hot_reload(mut self, interval: Duration) -> Builder {
+        self.hot_reload = Some(interval);
+        self
+    }
+
+    /// The AssetServer the states get shares of. Defaults to `AssetServer::new`.
+    pub fn with_assets(mut self, assets: AssetServer) -> Builder {
+        self.assets = Some(assets);
+        self
+    }
+
+    pub fn with_state(mut self, state: state::GameState) -> Builder {
+        self.states.push(state);
+        self
+    }
+
+    /// The state the game starts in. Defaults to the first one added.
+    pub fn with_initial_state(mut self, name: &'static str) -> Builder {
+        self.initial_state = Some(name);
+        self
+    }
+
     pub fn build(self) -> Window {
         let dimensions = match self.dimensions {
             Some(d) => d,
@@ -84,17 +132,36 @@ impl Builder {
             );
 
         let renderer = graphics::Renderer::new(factory, device, main_color, main_depth);
+        let assets = match self.assets {
+            Some(assets) => assets,
+            None => AssetServer::new(),
+        };
+        let mut statemanager = state::Manager::new(dimensions.0, dimensions.1, assets);
+        let initial_state = self.initial_state.or(self.states.first().map(|state| state.name()));
+        for state in self.states {
+            statemanager.add_state(state);
+        }
+        if let Some(name) = initial_state {
+            statemanager.push(name);
+        }
+
+        let mut screen = screen::Screen::new(renderer, statemanager);
+        if let Some(interval) = self.hot_reload {
+            println!("Hot reloading enabled");
+            screen.enable_hot_reload(interval);
+        }
 
         let window = Window {
-            screen: screen::Screen::new(renderer),
+            screen: screen,
             dimensions: dimensions,
             events_loop: events_loop,
             window_handle: window_handle,
-            //ui_texture: None,
-            //ui_color: None,
-            //ui_renderer: None,
-            //imgui: None,
-            //mouse_state: MouseState::default(),
+            input: input::Input::new(self.input_bindings.unwrap_or_default()),
+            gamepads: match self.gamepad_backend {
+                Some(backend) => backend,
+                None => gamepad::default_backend(),
+            },
+            last_frame: Instant::now(),
         };
 
         window
@@ -106,20 +173,21 @@ pub struct Window {
     dimensions: (u32, u32),
     events_loop: winit::EventsLoop,
     window_handle: winit::GlWindow,
-
-    // NOTE: UI stuff, will probably move this away later
-    /*
-    imgui: Option<ImGui>,
-    ui_texture: Option<graphics::texture::Texture>,
-    ui_color: Option<gfx_core::handle::RenderTargetView<graphics::Resources, gfx::format::Rgba8>>,
-    ui_renderer: Option<Renderer<graphics::Resources>>,
-    mouse_state: MouseState,
-    */
+    input: input::Input,
+    gamepads: Box<GamepadBackend>,
+    /// When the last frame was rendered, for the UI
+    last_frame: Instant,
 }
 
 impl Window {
-    pub fn render(mut self) -> Window {
-        self.screen = self.screen.render();
+    /// Renders the current frame. `alpha` is the interpolation between the last two ticks.
+    pub fn render(mut self, alpha: f32) -> Window {
+        let now = Instant::now();
+        let frame_time = now.duration_since(self.last_frame);
+        self.last_frame = now;
+        let dt = frame_time.as_secs() as f32 + frame_time.subsec_nanos() as f32 / 1_000_000_000.;
+
+        self.screen = self.screen.render(alpha, &self.window_handle, dt);
 
         use glutin::GlContext;
         self.window_handle.swap_buffers().unwrap();
@@ -127,216 +195,72 @@ impl Window {
         self
     }
 
+    /// Runs one tick. The input collected so far is handed to the game, and then
+    /// the just pressed/released state is cleared for the next tick.
     pub fn update(&mut self, delta: Duration) -> () {
-        self.screen.update(delta);
+        self.screen.update(delta, &self.input);
+        self.input.end_tick();
     }
 
-    pub fn poll_events(&mut self) -> bool {
-        use winit::WindowEvent::*;
-        use winit::ElementState::Pressed;
-        use winit::{Event, MouseButton, MouseScrollDelta, TouchPhase};
-
-        let mut running = true;
-        /*
-        let imgui = match self.imgui {
-            Some(ref mut imgui) => imgui,
-            None => panic!("ImGui not initialized??"),
-        };
-        let ref mut mouse_state = self.mouse_state;
-        */
-
-        self.events_loop.poll_events(|event| match event {
-            winit::Event::WindowEvent { event, .. } => {
-                match event {
-                    Closed => running = false,
-                    KeyboardInput { input, .. } => {
-                        /*
-                        use glutin::VirtualKeyCode as Key;
-
-                        let pressed = input.state == Pressed;
-                        match input.virtual_keycode {
-                            Some(Key::Tab) => imgui.set_key(0, pressed),
-                            Some(Key::Left) => imgui.set_key(1, pressed),
-                            Some(Key::Right) => imgui.set_key(2, pressed),
-                            Some(Key::Up) => imgui.set_key(3, pressed),
-                            Some(Key::Down) => imgui.set_key(4, pressed),
-                            Some(Key::PageUp) => imgui.set_key(5, pressed),
-                            Some(Key::PageDown) => imgui.set_key(6, pressed),
-                            Some(Key::Home) => imgui.set_key(7, pressed),
-                            Some(Key::End) => imgui.set_key(8, pressed),
-                            Some(Key::Delete) => imgui.set_key(9, pressed),
-                            Some(Key::Back) => imgui.set_key(10, pressed),
-                            Some(Key::Return) => imgui.set_key(11, pressed),
-                            Some(Key::Escape) => imgui.set_key(12, pressed),
-                            Some(Key::A) => imgui.set_key(13, pressed),
-                            Some(Key::C) => imgui.set_key(14, pressed),
-                            Some(Key::V) => imgui.set_key(15, pressed),
-                            Some(Key::X) => imgui.set_key(16, pressed),
-                            Some(Key::Y) => imgui.set_key(17, pressed),
-                            Some(Key::Z) => imgui.set_key(18, pressed),
-                            Some(Key::LControl) |
-                            Some(Key::RControl) => imgui.set_key_ctrl(pressed),
-                            Some(Key::LShift) |
-                            Some(Key::RShift) => imgui.set_key_shift(pressed),
-                            Some(Key::LAlt) | Some(Key::RAlt) => imgui.set_key_alt(pressed),
-                            Some(Key::LWin) | Some(Key::RWin) => imgui.set_key_super(pressed),
-                            _ => {}
-                        }
-                        */
-                    }
-                    MouseMoved { position: (x, y), .. } => {}, //mouse_state.pos = (x as i32, y as i32),
-                    MouseInput { state, button, .. } => {
-                        /*
-                        match button {
-                            MouseButton::Left => mouse_state.pressed.0 = state == Pressed,
-                            MouseButton::Right => mouse_state.pressed.1 = state == Pressed,
-                            MouseButton::Middle => mouse_state.pressed.2 = state == Pressed,
-                            _ => {}
-                        }
-                        */
-                    }
-                    MouseWheel {
-                        delta: MouseScrollDelta::LineDelta(_, y),
-                        phase: TouchPhase::Moved,
-                        ..
-                    } |
-                    MouseWheel {
-                        delta: MouseScrollDelta::PixelDelta(_, y),
-                        phase: TouchPhase::Moved,
-                        ..
-                    } => {}, //mouse_state.wheel = y,
-                    ReceivedCharacter(c) => {}, //imgui.add_input_character(c),
-                    _ => (),
-                    _ => (),
-                }
-            }
-            _ => (),
-        });
+    /// Resizes the gfx views to match the window, and lets the game know.
+    pub fn resize(&mut self, width: u32, height: u32) -> () {
+        if width == 0 || height == 0 {
+            // Minimized, nothing to render to anyway
+            return;
+        }
+        println!("Window resized to {}x{}", width, height);
 
-        running
+        use glutin::GlContext;
+        self.window_handle.resize(width, height);
+        self.dimensions = (width, height);
+        self.screen.resize(&self.window_handle, width, height);
     }
 
-    /*
-    pub fn setup_ui(&mut self) -> () {
-        use gfx_core::Factory;
-        let ref device = self.device;
-        let ref mut factory = self.factory;
-
-        let shaders = {
-            let version = device.get_info().shading_language;
-            if version.is_embedded {
-                if version.major >= 3 {
-                    Shaders::GlSlEs300
-                } else {
-                    Shaders::GlSlEs100
-                }
-            } else {
-                if version.major >= 4 {
-                    Shaders::GlSl400
-                } else if version.major >= 3 {
-                    Shaders::GlSl130
-                } else {
-                    Shaders::GlSl110
-                }
-            }
-        };
-
-        let (_, ui_view, ui_color) = factory
-            .create_render_target::<gfx::format::Rgba8>(
-                self.dimensions.0 as u16,
-                self.dimensions.1 as u16,
-            )
-            .unwrap();
-
-        self.ui_texture = Some(
-            graphics::texture::Builder::new()
-                .with_view(ui_view)
-                .with_dimensions(self.dimensions.0, self.dimensions.1)
-                .build(factory),
-        );
-
-        let mut imgui = ImGui::init();
-        let renderer =
-            Renderer::init::<graphics::Factory>(&mut imgui, factory, shaders, ui_color.clone())
-                .expect("Failed to initialize renderer");
-
-        configure_keys(&mut imgui);
-
-        self.imgui = Some(imgui);
-        self.ui_color = Some(ui_color);
-        self.ui_renderer = Some(renderer);
+    pub fn set_title(&mut self, title: &str) -> () {
+        self.window_handle.set_title(title);
     }
 
-    pub fn get_ui_frame(&mut self, dt: f32) -> Ui {
-        let ref mut window = self.glutin_window;
-        let imgui = match self.imgui {
-            Some(ref mut imgui) => imgui,
-            None => panic!("ImGui not initialized??"),
-        };
-        update_mouse(imgui, &mut self.mouse_state);
-
-        let size_points = window.get_inner_size_points().unwrap();
-        let size_pixels = window.get_inner_size_pixels().unwrap();
-
-        imgui.frame(size_points, size_pixels, dt)
+    /// Asks for a new size, the game hears about it when the window has been resized.
+    pub fn set_dimensions(&mut self, width: u32, height: u32) -> () {
+        if (width, height) != self.dimensions {
+            self.window_handle.set_inner_size(width, height);
+        }
     }
 
-    pub fn render_ui(&mut self) -> () {}
-    */
-}
+    pub fn poll_events(&mut self) -> bool {
+        use winit::WindowEvent::*;
 
+        let mut running = true;
+        let mut resized = None;
+        {
+            let input = &mut self.input;
+            let mut debug_ui = self.screen.debug_ui();
+
+            self.events_loop.poll_events(|event| match event {
+                winit::Event::WindowEvent { event, .. } => {
+                    input.handle_event(&event);
+                    if let Some(ref mut debug_ui) = debug_ui {
+                        debug_ui.handle_event(&event);
+                    }
+                    match event {
+                        Closed => running = false,
+                        // Only the last size matters
+                        Resized(width, height) => resized = Some((width, height)),
+                        _ => (),
+                    }
+                }
+                _ => (),
+            });
+        }
 
-// some imgui-rs helpers
-// TODO: move these out of here
-/*
-fn configure_keys(imgui: &mut ImGui) {
-    use imgui::ImGuiKey;
-
-    imgui.set_imgui_key(ImGuiKey::Tab, 0);
-    imgui.set_imgui_key(ImGuiKey::LeftArrow, 1);
-    imgui.set_imgui_key(ImGuiKey::RightArrow, 2);
-    imgui.set_imgui_key(ImGuiKey::UpArrow, 3);
-    imgui.set_imgui_key(ImGuiKey::DownArrow, 4);
-    imgui.set_imgui_key(ImGuiKey::PageUp, 5);
-    imgui.set_imgui_key(ImGuiKey::PageDown, 6);
-    imgui.set_imgui_key(ImGuiKey::Home, 7);
-    imgui.set_imgui_key(ImGuiKey::End, 8);
-    imgui.set_imgui_key(ImGuiKey::Delete, 9);
-    imgui.set_imgui_key(ImGuiKey::Backspace, 10);
-    imgui.set_imgui_key(ImGuiKey::Enter, 11);
-    imgui.set_imgui_key(ImGuiKey::Escape, 12);
-    imgui.set_imgui_key(ImGuiKey::A, 13);
-    imgui.set_imgui_key(ImGuiKey::C, 14);
-    imgui.set_imgui_key(ImGuiKey::V, 15);
-    imgui.set_imgui_key(ImGuiKey::X, 16);
-    imgui.set_imgui_key(ImGuiKey::Y, 17);
-    imgui.set_imgui_key(ImGuiKey::Z, 18);
-}
+        for event in self.gamepads.poll() {
+            self.input.handle_gamepad_event(event);
+        }
 
-fn update_mouse(imgui: &mut ImGui, mouse_state: &mut MouseState) {
-    let scale = imgui.display_framebuffer_scale();
-    imgui.set_mouse_pos(
-        mouse_state.pos.0 as f32 / scale.0,
-        mouse_state.pos.1 as f32 / scale.1,
-    );
-    imgui.set_mouse_down(
-        &[
-            mouse_state.pressed.0,
-            mouse_state.pressed.1,
-            mouse_state.pressed.2,
-            false,
-            false,
-        ],
-    );
-    imgui.set_mouse_wheel(mouse_state.wheel / scale.1);
-    mouse_state.wheel = 0.0;
-}
+        if let Some((width, height)) = resized {
+            self.resize(width, height);
+        }
 
-#[derive(Copy, Clone, PartialEq, Debug, Default)]
-struct MouseState {
-    pos: (i32, i32),
-    pressed: (bool, bool, bool),
-    wheel: f32,
+        running
+    }
 }
-
-*/
\ No newline at end of file
@@ -0,0 +1,120 @@
+//! The same map saved as .tmx and exported to JSON from Tiled has to load the same.
+//!
+//! tests/maps/level.tmx has its tile layers as CSV, base64+zlib and base64+gzip, and
+//! level.json has them as plain arrays. Both use the external tiles.tsx and an embedded
+//! tileset, and have an object layer with every kind of object.
+
+extern crate cyberengine;
+
+use cyberengine::resource::tilemap::{LayerData, Tilemap, Tileset};
+
+fn load(name: &str) -> Tilemap {
+    let path = format!("{}/tests/maps/{}", env!("CARGO_MANIFEST_DIR"), name);
+    match Tilemap::parse_file(&path) {
+        Ok(map) => map,
+        Err(e) => panic!("Can't load {}: {}", name, e),
+    }
+}
+
+fn assert_tilesets_eq(a: &Tileset, b: &Tileset) -> () {
+    assert_eq!(a.name, b.name);
+    assert_eq!(a.path, b.path);
+    assert_eq!(a.root, b.root);
+    assert_eq!(a.firstgid, b.firstgid);
+    assert_eq!(a.image, b.image);
+    assert_eq!(a.imagewidth, b.imagewidth);
+    assert_eq!(a.imageheight, b.imageheight);
+    assert_eq!(a.tilewidth, b.tilewidth);
+    assert_eq!(a.tileheight, b.tileheight);
+    assert_eq!(a.tilecount, b.tilecount);
+    assert_eq!(a.columns, b.columns);
+    assert_eq!(a.margin, b.margin);
+    assert_eq!(a.spacing, b.spacing);
+    assert_eq!(a.properties, b.properties);
+    assert_eq!(a.tileproperties, b.tileproperties);
+    assert_eq!(a.tiles, b.tiles);
+}
+
+#[test]
+fn tmx_and_json_export_are_equal() {
+    let tmx = load("level.tmx");
+    let json = load("level.json");
+
+    assert_eq!(tmx.version, json.version);
+    assert_eq!(tmx.width, json.width);
+    assert_eq!(tmx.height, json.height);
+    assert_eq!(tmx.tilewidth, json.tilewidth);
+    assert_eq!(tmx.tileheight, json.tileheight);
+    assert_eq!(tmx.orientation, json.orientation);
+    assert_eq!(tmx.properties, json.properties);
+
+    assert_eq!(tmx.layers.len(), 4);
+    assert_eq!(tmx.layers.len(), json.layers.len());
+    for (a, b) in tmx.layers.iter().zip(json.layers.iter()) {
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.layertype, b.layertype);
+        assert_eq!(a.width, b.width);
+        assert_eq!(a.height, b.height);
+        assert_eq!(a.visible, b.visible);
+        assert_eq!(a.opacity, b.opacity);
+        assert_eq!(a.properties, b.properties);
+        assert_eq!(a.data, b.data, "data of layer {}", a.name);
+    }
+
+    assert_eq!(tmx.tilesets.len(), 2);
+    assert_eq!(tmx.tilesets.len(), json.tilesets.len());
+    for (a, b) in tmx.tilesets.iter().zip(json.tilesets.iter()) {
+        assert_tilesets_eq(a, b);
+    }
+
+    assert_eq!(tmx.objects().len(), 6);
+    assert_eq!(tmx.objects(), json.objects());
+}
+
+#[test]
+fn encoded_layers_decode() {
+    let tmx = load("level.tmx");
+    let data: Vec<&Vec<u32>> = tmx.layers
+        .iter()
+        .filter_map(|layer| match layer.data {
+            LayerData::TileData(ref data) => Some(data),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(*data[0], vec![1, 2, 2, 1, 3, 4, 4, 3, 1, 1, 2, 2]);
+    // Flip flags survive the decoding
+    assert_eq!(data[1][5], 5 | 0x80000000);
+    assert_eq!(data[1][7], 9);
+    assert_eq!(data[2][8], 7 | 0x40000000);
+}
+
+#[test]
+fn external_tileset_is_resolved() {
+    let tmx = load("level.tmx");
+    let tiles = &tmx.tilesets[0];
+    assert_eq!(tiles.name, "tiles");
+    assert_eq!(tiles.firstgid, 1);
+    assert!(tiles.path.as_ref().unwrap().ends_with("tiles.tsx"));
+    assert_eq!(tiles.animation(4).map(|frames| frames.len()), Some(2));
+    assert_eq!(tiles.collision(5).len(), 1);
+}
+
+#[test]
+fn maps_without_tiles_are_rejected() {
+    let map = |width, height, layer_width| {
+        format!(
+            r#"{{"version": 1, "width": {}, "height": {}, "tilewidth": 16, "tileheight": 16,
+                "orientation": "orthogonal", "nextobjectid": 1, "tilesets": [],
+                "layers": [{{"name": "ground", "type": "tilelayer", "visible": true, "opacity": 1,
+                             "width": {}, "height": 1, "data": [0, 0]}}]}}"#,
+            width, height, layer_width
+        )
+    };
+
+    assert!(Tilemap::parse("ok.json", &map(2, 1, 2)).is_ok());
+    assert!(Tilemap::parse("narrow.json", &map(0, 1, 2)).is_err());
+    assert!(Tilemap::parse("flat.json", &map(2, 0, 2)).is_err());
+    // Would divide by zero when building the meshes
+    assert!(Tilemap::parse("layer.json", &map(2, 1, 0)).is_err());
+}
@@ -0,0 +1,538 @@
+//! # Cyberpak
+//! The packed asset archive format. One file to ship instead of a pile of loose assets.
+//!
+//! Layout, everything little endian:
+//!
+//! ```text
+//! header   magic "CPAK", version u16, flags u16, entry count u32,
+//!          index offset u64, index crc32 u32
+//! data     the entries, one after another
+//! index    per entry: path length u16, path (UTF-8, `/` separated),
+//!          offset u64, stored size u64, size u64, compression u8, crc32 u32
+//! ```
+//!
+//! The index is at the end so the packer can stream entries out without knowing
+//! them all up front. Checksums are of the uncompressed data, and are checked every
+//! time an entry is read.
+
+extern crate flate2;
+
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::{self, SeekFrom};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Crc;
+
+pub const MAGIC: &[u8; 4] = b"CPAK";
+/// Bump this when the layout changes. Older versions are still read if we can.
+pub const VERSION: u16 = 1;
+
+const HEADER_SIZE: u64 = 24;
+/// Of an index entry with an empty path
+const MIN_ENTRY_SIZE: u64 = 2 + 8 + 8 + 8 + 1 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Stored as is. Used for files that don't get any smaller, like PNGs.
+    None,
+    Deflate,
+}
+
+impl Compression {
+    fn from_u8(value: u8) -> io::Result<Compression> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            other => Err(invalid(format!("unknown compression {}", other))),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: String,
+    /// Where the data starts in the archive
+    pub offset: u64,
+    /// Size in the archive
+    pub stored_size: u64,
+    /// Size after decompressing
+    pub size: u64,
+    pub compression: Compression,
+    /// Of the uncompressed data
+    pub crc32: u32,
+}
+
+/// Reads an archive. Only the index is read up front.
+pub struct Archive<R> {
+    reader: R,
+    version: u16,
+    /// Where the data ends and the index starts
+    index_offset: u64,
+    entries: Vec<Entry>,
+    by_path: HashMap<String, usize>,
+}
+
+impl<R: Read + Seek> Archive<R> {
+    pub fn open(mut reader: R) -> io::Result<Archive<R>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a cyberpak archive".to_owned()));
+        }
+
+        let version = read_u16(&mut reader)?;
+        if version == 0 || version > VERSION {
+            return Err(invalid(format!(
+                "unsupported archive version {} (newest supported is {})",
+                version, VERSION
+            )));
+        }
+        let _flags = read_u16(&mut reader)?;
+        let count = read_u32(&mut reader)?;
+        let index_offset = read_u64(&mut reader)?;
+        let index_crc = read_u32(&mut reader)?;
+
+        // The header isn't checksummed, so nothing in it can be trusted before it's
+        // checked against the file
+        let len = reader.seek(SeekFrom::End(0))?;
+        if index_offset < HEADER_SIZE || index_offset > len {
+            return Err(invalid(format!(
+                "index offset {} is outside the archive ({} bytes)",
+                index_offset, len
+            )));
+        }
+        if u64::from(count) * MIN_ENTRY_SIZE > len - index_offset {
+            return Err(invalid(format!("index is too short for {} entries", count)));
+        }
+
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let mut index = Vec::new();
+        reader.read_to_end(&mut index)?;
+        if checksum(&index) != index_crc {
+            return Err(invalid("index checksum mismatch".to_owned()));
+        }
+
+        let mut cursor = io::Cursor::new(index);
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut by_path = HashMap::new();
+        for i in 0..count as usize {
+            let entry = read_entry(&mut cursor)?;
+            match entry.offset.checked_add(entry.stored_size) {
+                Some(end) if entry.offset >= HEADER_SIZE && end <= index_offset => {}
+                _ => return Err(invalid(format!("{} is outside the data", entry.path))),
+            }
+            if by_path.insert(entry.path.clone(), i).is_some() {
+                return Err(invalid(format!("{} is in the index twice", entry.path)));
+            }
+            entries.push(entry);
+        }
+
+        Ok(Archive {
+            reader,
+            version,
+            index_offset,
+            entries,
+            by_path,
+        })
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// In the order they were packed
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&Entry> {
+        self.by_path.get(path).map(|&i| &self.entries[i])
+    }
+
+    /// Reads and decompresses a file. Missing files are `io::ErrorKind::NotFound`,
+    /// and broken ones (checksum mismatch and such) `io::ErrorKind::InvalidData`.
+    pub fn read(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        let entry = match self.entry(path) {
+            Some(entry) => entry.clone(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in the archive", path),
+                ))
+            }
+        };
+        self.read_entry(&entry)
+    }
+
+    /// Entries that didn't come from `entries` are checked against the archive too
+    pub fn read_entry(&mut self, entry: &Entry) -> io::Result<Vec<u8>> {
+        match entry.offset.checked_add(entry.stored_size) {
+            Some(end) if end <= self.index_offset => {}
+            _ => return Err(invalid(format!("{} is outside the data", entry.path))),
+        }
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        let mut stored = vec![0u8; entry.stored_size as usize];
+        self.reader.read_exact(&mut stored)?;
+
+        let bytes = match entry.compression {
+            Compression::None => stored,
+            Compression::Deflate => {
+                // One byte more than expected is enough to tell the size is wrong
+                let mut bytes = Vec::new();
+                DeflateDecoder::new(&stored[..])
+                    .take(entry.size.saturating_add(1))
+                    .read_to_end(&mut bytes)?;
+                bytes
+            }
+        };
+
+        if bytes.len() as u64 != entry.size {
+            return Err(invalid(format!(
+                "{}: expected {} bytes, got {}",
+                entry.path,
+                entry.size,
+                bytes.len()
+            )));
+        }
+        if checksum(&bytes) != entry.crc32 {
+            return Err(invalid(format!("{}: checksum mismatch", entry.path)));
+        }
+        Ok(bytes)
+    }
+
+    /// Reads every entry, returning the paths that are broken and why
+    pub fn verify(&mut self) -> Vec<(String, io::Error)> {
+        let entries = self.entries.clone();
+        let mut broken = Vec::new();
+        for entry in entries {
+            if let Err(e) = self.read_entry(&entry) {
+                broken.push((entry.path, e));
+            }
+        }
+        broken
+    }
+}
+
+/// Writes an archive. Call `finish` when done, or the archive won't have an index.
+pub struct Writer<W> {
+    writer: W,
+    entries: Vec<Entry>,
+    offset: u64,
+}
+
+impl<W: Write + Seek> Writer<W> {
+    pub fn new(mut writer: W) -> io::Result<Writer<W>> {
+        // Filled in by finish
+        writer.write_all(&[0u8; HEADER_SIZE as usize])?;
+        Ok(Writer {
+            writer,
+            entries: Vec::new(),
+            offset: HEADER_SIZE,
+        })
+    }
+
+    /// Adds a file. With `Compression::Deflate` the data is stored uncompressed
+    /// anyway if compressing doesn't make it smaller.
+    pub fn add(&mut self, path: &str, bytes: &[u8], compression: Compression) -> io::Result<()> {
+        if path.len() > u16::MAX as usize {
+            return Err(invalid(format!("path too long: {}", path)));
+        }
+        if self.entries.iter().any(|entry| entry.path == path) {
+            return Err(invalid(format!("{} is already in the archive", path)));
+        }
+
+        let compressed = match compression {
+            Compression::None => None,
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(bytes)?;
+                let compressed = encoder.finish()?;
+                if compressed.len() < bytes.len() {
+                    Some(compressed)
+                } else {
+                    None
+                }
+            }
+        };
+
+        let (stored, compression) = match compressed {
+            Some(ref compressed) => (&compressed[..], Compression::Deflate),
+            None => (bytes, Compression::None),
+        };
+
+        self.writer.write_all(stored)?;
+        self.entries.push(Entry {
+            path: path.to_owned(),
+            offset: self.offset,
+            stored_size: stored.len() as u64,
+            size: bytes.len() as u64,
+            compression,
+            crc32: checksum(bytes),
+        });
+        self.offset += stored.len() as u64;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Writes the index and the header, and gives the writer back
+    pub fn finish(mut self) -> io::Result<W> {
+        let mut index = Vec::new();
+        for entry in &self.entries {
+            write_entry(&mut index, entry)?;
+        }
+        self.writer.write_all(&index)?;
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(MAGIC)?;
+        write_u16(&mut self.writer, VERSION)?;
+        write_u16(&mut self.writer, 0)?;
+        write_u32(&mut self.writer, self.entries.len() as u32)?;
+        write_u64(&mut self.writer, self.offset)?;
+        write_u32(&mut self.writer, checksum(&index))?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+pub fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_entry<R: Read>(r: &mut R) -> io::Result<Entry> {
+    let path_len = read_u16(r)?;
+    let mut path = vec![0u8; path_len as usize];
+    r.read_exact(&mut path)?;
+    let path = String::from_utf8(path).map_err(|_| invalid("path is not UTF-8".to_owned()))?;
+
+    Ok(Entry {
+        path,
+        offset: read_u64(r)?,
+        stored_size: read_u64(r)?,
+        size: read_u64(r)?,
+        compression: Compression::from_u8(read_u8(r)?)?,
+        crc32: read_u32(r)?,
+    })
+}
+
+fn write_entry<W: Write>(w: &mut W, entry: &Entry) -> io::Result<()> {
+    write_u16(w, entry.path.len() as u16)?;
+    w.write_all(entry.path.as_bytes())?;
+    write_u64(w, entry.offset)?;
+    write_u64(w, entry.stored_size)?;
+    write_u64(w, entry.size)?;
+    w.write_all(&[entry.compression.to_u8()])?;
+    write_u32(w, entry.crc32)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0] as u16 | (buf[1] as u16) << 8)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(buf.iter().rev().fold(0, |acc, &b| acc << 8 | b as u32))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(buf.iter().rev().fold(0, |acc, &b| acc << 8 | b as u64))
+}
+
+fn write_u16<W: Write>(w: &mut W, value: u16) -> io::Result<()> {
+    w.write_all(&[value as u8, (value >> 8) as u8])
+}
+
+fn write_u32<W: Write>(w: &mut W, value: u32) -> io::Result<()> {
+    let mut buf = [0u8; 4];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (value >> (i * 8)) as u8;
+    }
+    w.write_all(&buf)
+}
+
+fn write_u64<W: Write>(w: &mut W, value: u64) -> io::Result<()> {
+    let mut buf = [0u8; 8];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (value >> (i * 8)) as u8;
+    }
+    w.write_all(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(files: &[(&str, &[u8], Compression)]) -> Vec<u8> {
+        let mut writer = Writer::new(io::Cursor::new(Vec::new())).unwrap();
+        for &(path, bytes, compression) in files {
+            writer.add(path, bytes, compression).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn open(bytes: Vec<u8>) -> io::Result<Archive<io::Cursor<Vec<u8>>>> {
+        Archive::open(io::Cursor::new(bytes))
+    }
+
+    fn index_offset(bytes: &[u8]) -> usize {
+        read_u64(&mut &bytes[12..20]).unwrap() as usize
+    }
+
+    /// Replaces the index with `entries`, with a valid checksum
+    fn with_index(mut bytes: Vec<u8>, entries: &[Entry]) -> Vec<u8> {
+        let offset = index_offset(&bytes);
+        bytes.truncate(offset);
+        let mut index = Vec::new();
+        for entry in entries {
+            write_entry(&mut index, entry).unwrap();
+        }
+        bytes.extend_from_slice(&index);
+
+        let mut header = Vec::new();
+        write_u32(&mut header, entries.len() as u32).unwrap();
+        write_u64(&mut header, offset as u64).unwrap();
+        write_u32(&mut header, checksum(&index)).unwrap();
+        bytes[8..24].copy_from_slice(&header);
+        bytes
+    }
+
+    fn assert_invalid<T>(result: io::Result<T>) {
+        match result {
+            Ok(_) => panic!("expected InvalidData, got Ok"),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{}", e),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = b"cyberpunk ".repeat(100);
+        let image = [0u8, 255, 3, 7, 128];
+        let bytes = pack(&[
+            ("maps/offices.json", &text, Compression::Deflate),
+            ("test.jpg", &image, Compression::None),
+            // Doesn't get smaller, so it's stored as is
+            ("noise.bin", &image, Compression::Deflate),
+        ]);
+
+        let mut archive = open(bytes).unwrap();
+        assert_eq!(archive.version(), VERSION);
+        assert_eq!(archive.entries().len(), 3);
+        assert_eq!(archive.entry("maps/offices.json").unwrap().compression, Compression::Deflate);
+        assert!(archive.entry("maps/offices.json").unwrap().stored_size < text.len() as u64);
+        assert_eq!(archive.entry("test.jpg").unwrap().compression, Compression::None);
+        assert_eq!(archive.entry("noise.bin").unwrap().compression, Compression::None);
+
+        assert_eq!(archive.read("maps/offices.json").unwrap(), text);
+        assert_eq!(archive.read("test.jpg").unwrap(), image);
+        assert_eq!(archive.read("noise.bin").unwrap(), image);
+        assert!(archive.verify().is_empty());
+        assert_eq!(archive.read("missing.png").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn empty_archive() {
+        let archive = open(pack(&[])).unwrap();
+        assert!(archive.entries().is_empty());
+    }
+
+    #[test]
+    fn flipped_data_byte() {
+        let mut bytes = pack(&[("test.jpg", b"not really a jpeg", Compression::None)]);
+        bytes[HEADER_SIZE as usize + 3] ^= 0xff;
+
+        let mut archive = open(bytes).unwrap();
+        let error = archive.read("test.jpg").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("checksum mismatch"), "{}", error);
+        assert_eq!(archive.verify().len(), 1);
+    }
+
+    #[test]
+    fn flipped_index_byte() {
+        let mut bytes = pack(&[("test.jpg", b"not really a jpeg", Compression::None)]);
+        let offset = index_offset(&bytes);
+        bytes[offset + 4] ^= 0xff;
+        assert_invalid(open(bytes));
+    }
+
+    #[test]
+    fn unsupported_versions() {
+        for &version in &[0, VERSION + 1] {
+            let mut bytes = pack(&[("test.jpg", b"jpeg", Compression::None)]);
+            bytes[4] = version as u8;
+            bytes[5] = (version >> 8) as u8;
+            assert_invalid(open(bytes));
+        }
+    }
+
+    #[test]
+    fn duplicate_paths() {
+        let mut writer = Writer::new(io::Cursor::new(Vec::new())).unwrap();
+        writer.add("test.jpg", b"jpeg", Compression::None).unwrap();
+        assert_invalid(writer.add("test.jpg", b"jpeg", Compression::None));
+
+        let bytes = pack(&[("test.jpg", b"jpeg", Compression::None)]);
+        let entry = open(bytes.clone()).unwrap().entries()[0].clone();
+        assert_invalid(open(with_index(bytes, &[entry.clone(), entry])));
+    }
+
+    #[test]
+    fn header_is_checked_against_the_file() {
+        let bytes = pack(&[("test.jpg", b"jpeg", Compression::None)]);
+
+        // Way more entries than the index has room for
+        let mut huge_count = bytes.clone();
+        huge_count[8..12].copy_from_slice(&[0xff; 4]);
+        assert_invalid(open(huge_count));
+
+        let mut bad_offset = bytes.clone();
+        bad_offset[12..20].copy_from_slice(&[0xff; 8]);
+        assert_invalid(open(bad_offset));
+
+        let mut truncated = bytes.clone();
+        truncated.truncate(HEADER_SIZE as usize);
+        assert_invalid(open(truncated));
+    }
+
+    #[test]
+    fn entries_are_checked_against_the_file() {
+        let bytes = pack(&[("test.jpg", b"jpeg", Compression::None)]);
+        let entry = open(bytes.clone()).unwrap().entries()[0].clone();
+
+        let mut huge = entry.clone();
+        huge.stored_size = u64::MAX - 1;
+        assert_invalid(open(with_index(bytes.clone(), &[huge.clone()])));
+
+        let mut archive = open(bytes).unwrap();
+        assert_invalid(archive.read_entry(&huge));
+    }
+}
@@ -0,0 +1,180 @@
+//! Packs asset directories into archives, and looks inside them.
+//!
+//! ```text
+//! cyberpak pack <dir> <archive> [--store]
+//! cyberpak list <archive>
+//! cyberpak verify <archive>
+//! cyberpak extract <archive> <dir> [paths...]
+//! ```
+
+extern crate cyberpak;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use cyberpak::{Archive, Compression, Writer};
+
+const USAGE: &str = "Usage:
+    cyberpak pack <dir> <archive> [--store]    pack every file in <dir>, --store skips compression
+    cyberpak list <archive>                    list the files in <archive>
+    cyberpak verify <archive>                  check every file against its checksum
+    cyberpak extract <archive> <dir> [paths]   extract everything, or just [paths], into <dir>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("pack") if args.len() >= 3 => {
+            let store = args[3..].iter().any(|arg| arg == "--store");
+            pack(Path::new(&args[1]), Path::new(&args[2]), store)
+        }
+        Some("list") if args.len() == 2 => list(Path::new(&args[1])),
+        Some("verify") if args.len() == 2 => verify(Path::new(&args[1])),
+        Some("extract") if args.len() >= 3 => extract(Path::new(&args[1]), Path::new(&args[2]), &args[3..]),
+        _ => {
+            println!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        println!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn open(path: &Path) -> io::Result<Archive<BufReader<File>>> {
+    Archive::open(BufReader::new(File::open(path)?))
+}
+
+fn pack(dir: &Path, archive: &Path, store: bool) -> io::Result<()> {
+    let compression = if store {
+        Compression::None
+    } else {
+        Compression::Deflate
+    };
+
+    let mut files = Vec::new();
+    files_in(dir, &mut files)?;
+    files.sort();
+
+    let mut writer = Writer::new(BufWriter::new(File::create(archive)?))?;
+    let mut total = 0;
+    for file in &files {
+        let path = asset_path(dir, file);
+        let mut bytes = Vec::new();
+        File::open(file)?.read_to_end(&mut bytes)?;
+        writer.add(&path, &bytes, compression)?;
+        total += bytes.len();
+    }
+
+    let packed = writer.entries().iter().map(|e| e.stored_size).sum::<u64>();
+    writer.finish()?;
+    println!(
+        "Packed {} files, {} bytes into {} bytes",
+        files.len(),
+        total,
+        packed
+    );
+    Ok(())
+}
+
+fn list(archive: &Path) -> io::Result<()> {
+    let archive = open(archive)?;
+    println!("Version {}, {} files", archive.version(), archive.entries().len());
+    for entry in archive.entries() {
+        let compression = match entry.compression {
+            Compression::None => "stored",
+            Compression::Deflate => "deflate",
+        };
+        println!(
+            "{:>10} {:>10} {:<8} {:08x} {}",
+            entry.size, entry.stored_size, compression, entry.crc32, entry.path
+        );
+    }
+    Ok(())
+}
+
+fn verify(archive: &Path) -> io::Result<()> {
+    let mut archive = open(archive)?;
+    let broken = archive.verify();
+    for (path, error) in &broken {
+        println!("{}: {}", path, error);
+    }
+    if broken.is_empty() {
+        println!("All {} files are fine", archive.entries().len());
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} broken files", broken.len()),
+        ))
+    }
+}
+
+fn extract(archive: &Path, dir: &Path, paths: &[String]) -> io::Result<()> {
+    let mut archive = open(archive)?;
+    let entries: Vec<_> = if paths.is_empty() {
+        archive.entries().to_vec()
+    } else {
+        let mut entries = Vec::new();
+        for path in paths {
+            match archive.entry(path) {
+                Some(entry) => entries.push(entry.clone()),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not in the archive", path),
+                    ))
+                }
+            }
+        }
+        entries
+    };
+
+    for entry in &entries {
+        // Don't let a nasty archive write outside of dir
+        if entry.path.split('/').any(|part| part == ".." || part.is_empty()) {
+            println!("Skipping {}", entry.path);
+            continue;
+        }
+
+        let bytes = archive.read_entry(entry)?;
+        let mut target = dir.to_owned();
+        for part in entry.path.split('/') {
+            target.push(part);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        File::create(&target)?.write_all(&bytes)?;
+        println!("{}", entry.path);
+    }
+    Ok(())
+}
+
+fn files_in(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files_in(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Path inside the archive, relative to the packed directory and `/` separated
+fn asset_path(dir: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(dir).unwrap_or(file);
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    parts.join("/")
+}
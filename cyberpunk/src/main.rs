@@ -6,9 +6,44 @@ extern crate cyberengine;
 #[macro_use]
 extern crate imgui;
 
+use cyberengine::DispatcherBuilder;
 use cyberengine::game::Game;
+use cyberengine::resource::Identifier;
+use cyberengine::resource::assets::AssetServer;
+use cyberengine::state::GameState;
+use cyberengine::systems::sprite::{PendingSprite, Position, Sprite, SpriteLoader, SpriteRenderer, SpriteSpawn};
+
+/// Nothing but a picture for now
+fn title_state() -> GameState {
+    GameState::new(
+        "title",
+        |world| {
+            world.register::<Position>();
+            world.register::<Sprite>();
+            world.register::<SpriteSpawn>();
+            world.register::<PendingSprite>();
+            DispatcherBuilder::new().build()
+        },
+        vec![Box::new(SpriteLoader::new()), Box::new(SpriteRenderer::new())],
+    )
+    .with_preload(|world, _| {
+        world.write_resource::<AssetServer>().load_texture("test.jpg");
+    })
+    .with_enter(|world| {
+        world
+            .create_entity()
+            .with(Position { x: 0., y: 0. })
+            .with(SpriteSpawn {
+                texture_identifier: Identifier::Image("test.jpg".to_owned()),
+            })
+            .build();
+    })
+}
 
 fn main() -> () {
-    let mut game = Game::new();
-    game.play();
+    Game::builder()
+        .with_state(title_state())
+        .initial_state("title")
+        .build()
+        .play();
 }